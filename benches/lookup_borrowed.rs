@@ -0,0 +1,76 @@
+//! allocations of lookup and lookup_borrowed on a plain dict,
+//! run with cargo bench --bench lookup_borrowed
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::fs;
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+use stardict::{no_cache, DictWriter, StarDict, WordDefinitionSegment};
+
+const WORDS: usize = 20000;
+
+/// system allocator counting every allocation
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+	unsafe fn alloc(&self, layout: Layout) -> *mut u8
+	{
+		ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+		System.alloc(layout)
+	}
+
+	unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout)
+	{
+		System.dealloc(ptr, layout)
+	}
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+fn measure(name: &str, f: impl FnOnce())
+{
+	let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+	let start = Instant::now();
+	f();
+	let elapsed = start.elapsed();
+	let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+	println!("{:<16}{:>10} allocations, {:>6.2} per word, {:?}", name, allocations,
+		allocations as f64 / WORDS as f64, elapsed);
+}
+
+fn main()
+{
+	let dir = std::env::temp_dir().join(format!("stardict-bench-{}", std::process::id()));
+	fs::create_dir_all(&dir).unwrap();
+	let ifo = dir.join("bench.ifo");
+	let mut writer = DictWriter::new("bench");
+	for i in 0..WORDS {
+		let segments = ["m", "g", "h"].iter()
+			.map(|types| WordDefinitionSegment {
+				types: types.to_string(),
+				text: format!("definition {} of word {} in a plain dict", types, i),
+				data: None,
+			})
+			.collect();
+		writer.add(format!("word{}", i), segments);
+	}
+	writer.write(&ifo).unwrap();
+	let words: Vec<String> = (0..WORDS).map(|i| format!("word{}", i)).collect();
+	let dict = no_cache(&ifo).unwrap();
+
+	measure("lookup", || for word in &words {
+		let definitions = dict.lookup(word).unwrap().unwrap();
+		black_box(definitions[0].segments.iter().map(|segment| segment.text.len()).sum::<usize>());
+	});
+	measure("lookup_borrowed", || for word in &words {
+		dict.lookup_borrowed(word, |_, segments| {
+			black_box(segments.iter().map(|segment| segment.text.len()).sum::<usize>());
+		}).unwrap();
+	});
+	drop(dict);
+	fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,223 @@
+use std::future::Future;
+use std::num::NonZeroUsize;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use crate::error::Result;
+use crate::{StarDict, WordDefinition};
+
+/// most blocking threads shared by every AsyncStarDict
+const MAX_BLOCKING_THREADS: usize = 8;
+
+/// lookup in a bounded pool of blocking threads, async callers not blocked by
+/// file or cache io, works with any async runtime
+pub struct AsyncStarDict<T> {
+	dict: Arc<T>,
+}
+
+impl<T> AsyncStarDict<T>
+	where T: StarDict + Send + Sync + 'static
+{
+	#[inline]
+	pub fn new(dict: T) -> Self
+	{
+		AsyncStarDict { dict: Arc::new(dict) }
+	}
+
+	/// open the dict in a blocking thread, e.g. with_sqlite reading idx
+	/// and building cache
+	pub async fn open<F>(open: F) -> Result<Self>
+		where F: FnOnce() -> Result<T> + Send + 'static
+	{
+		let dict = spawn_blocking(open).await?;
+		Ok(Self::new(dict))
+	}
+
+	#[inline]
+	pub fn dict(&self) -> &T
+	{
+		&self.dict
+	}
+
+	pub async fn lookup(&self, word: &str) -> Result<Option<Vec<WordDefinition>>>
+	{
+		let dict = self.dict.clone();
+		let word = word.to_owned();
+		spawn_blocking(move || dict.lookup(&word)).await
+	}
+
+	pub async fn lookup_many(&self, words: &[&str]) -> Result<Vec<(String, Option<Vec<WordDefinition>>)>>
+	{
+		let dict = self.dict.clone();
+		let words: Vec<String> = words.iter().map(|word| word.to_string()).collect();
+		spawn_blocking(move || {
+			let words: Vec<&str> = words.iter().map(String::as_str).collect();
+			dict.lookup_many(&words)
+		}).await
+	}
+}
+
+struct Blocking<R> {
+	result: Option<thread::Result<R>>,
+	waker: Option<Waker>,
+}
+
+/// resolved with the result of f running in a pool thread
+struct BlockingFuture<R> {
+	state: Arc<Mutex<Blocking<R>>>,
+}
+
+impl<R> Future for BlockingFuture<R> {
+	type Output = R;
+
+	fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<R>
+	{
+		let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+		match state.result.take() {
+			Some(Ok(result)) => Poll::Ready(result),
+			// panic in f raised in the caller
+			Some(Err(payload)) => panic::resume_unwind(payload),
+			None => {
+				state.waker = Some(cx.waker().clone());
+				Poll::Pending
+			}
+		}
+	}
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// threads as many as cpus up to MAX_BLOCKING_THREADS, started on first use,
+/// jobs over them queued
+fn pool() -> &'static Sender<Job>
+{
+	static POOL: OnceLock<Sender<Job>> = OnceLock::new();
+	POOL.get_or_init(|| {
+		let (sender, receiver) = mpsc::channel::<Job>();
+		let receiver = Arc::new(Mutex::new(receiver));
+		for _ in 0..pool_size() {
+			let receiver = receiver.clone();
+			thread::spawn(move || worker(&receiver));
+		}
+		sender
+	})
+}
+
+#[inline]
+fn pool_size() -> usize
+{
+	thread::available_parallelism().map_or(1, NonZeroUsize::get).min(MAX_BLOCKING_THREADS)
+}
+
+fn worker(receiver: &Mutex<Receiver<Job>>)
+{
+	loop {
+		let job = receiver.lock().unwrap_or_else(PoisonError::into_inner).recv();
+		match job {
+			Ok(job) => job(),
+			Err(_) => break,
+		}
+	}
+}
+
+fn spawn_blocking<F, R>(f: F) -> BlockingFuture<R>
+	where F: FnOnce() -> R + Send + 'static, R: Send + 'static
+{
+	let state = Arc::new(Mutex::new(Blocking { result: None, waker: None }));
+	let thread_state = state.clone();
+	// the pool never dropped, always receiving
+	let _ = pool().send(Box::new(move || {
+		let result = panic::catch_unwind(AssertUnwindSafe(f));
+		let mut state = thread_state.lock().unwrap_or_else(PoisonError::into_inner);
+		state.result = Some(result);
+		if let Some(waker) = state.waker.take() {
+			waker.wake();
+		}
+	}));
+	BlockingFuture { state }
+}
+
+#[cfg(test)]
+mod tests {
+	use std::future::Future;
+	use std::pin::pin;
+	use std::sync::atomic::{AtomicUsize, Ordering};
+	use std::sync::Arc;
+	use std::task::{Context, Poll, Wake};
+	use std::thread::{self, Thread};
+	use std::time::Duration;
+	use crate::{AsyncStarDict, no_cache};
+	use super::{pool_size, spawn_blocking};
+	use crate::error::Error;
+	use crate::tests::{create_dict, SAMPLE_WORDS};
+
+	struct ThreadWaker(Thread);
+
+	impl Wake for ThreadWaker {
+		fn wake(self: Arc<Self>)
+		{
+			self.0.unpark();
+		}
+	}
+
+	fn block_on<F: Future>(future: F) -> F::Output
+	{
+		let waker = Arc::new(ThreadWaker(thread::current())).into();
+		let mut cx = Context::from_waker(&waker);
+		let mut future = pin!(future);
+		loop {
+			match future.as_mut().poll(&mut cx) {
+				Poll::Ready(output) => return output,
+				Poll::Pending => thread::park(),
+			}
+		}
+	}
+
+	#[test]
+	fn lookup() {
+		let ifo = create_dict("async_lookup", &SAMPLE_WORDS, &[]);
+		let dict = block_on(AsyncStarDict::open(move || no_cache(ifo))).unwrap();
+		let definitions = block_on(dict.lookup("apple")).unwrap().unwrap();
+		assert_eq!(definitions[0].word, "Apple");
+		assert!(block_on(dict.lookup("cherry")).unwrap().is_none());
+
+		let results = block_on(dict.lookup_many(&["banana", "cherry"])).unwrap();
+		assert_eq!(results.len(), 2);
+		assert_eq!(results[0].0, "banana");
+		assert!(results[0].1.is_some());
+		assert!(results[1].1.is_none());
+
+		let result = block_on(AsyncStarDict::open(|| no_cache("not_exists.ifo")));
+		assert!(matches!(result, Err(Error::InvalidDictPath)));
+	}
+
+	#[test]
+	fn bounded_pool() {
+		let running = Arc::new(AtomicUsize::new(0));
+		let most = Arc::new(AtomicUsize::new(0));
+		let jobs: Vec<_> = (0..pool_size() * 3).map(|i| {
+			let running = running.clone();
+			let most = most.clone();
+			spawn_blocking(move || {
+				let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+				most.fetch_max(now, Ordering::SeqCst);
+				thread::sleep(Duration::from_millis(20));
+				running.fetch_sub(1, Ordering::SeqCst);
+				i
+			})
+		}).collect();
+		for (i, job) in jobs.into_iter().enumerate() {
+			assert_eq!(block_on(job), i);
+		}
+		assert!(most.load(Ordering::SeqCst) <= pool_size());
+	}
+
+	#[test]
+	#[should_panic(expected = "lookup failed")]
+	fn panic_raised() {
+		block_on(spawn_blocking(|| panic!("lookup failed")));
+	}
+}
@@ -0,0 +1,305 @@
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::{Mutex, PoisonError};
+use crate::collation::Collation;
+use crate::error::Result;
+use crate::ifo::Ifo;
+use crate::resource::ResourceDb;
+use crate::{MatchedDefinition, StarDict, WordDefinition};
+
+/// results of recent lookups by normalized word, not found ones included,
+/// evict the least recently used one when full
+struct LookupCache {
+	capacity: usize,
+	results: HashMap<String, Option<Vec<WordDefinition>>>,
+	// least recently used first
+	order: VecDeque<String>,
+}
+
+impl LookupCache {
+	fn new(capacity: usize) -> Self
+	{
+		LookupCache {
+			capacity: capacity.max(1),
+			results: HashMap::new(),
+			order: VecDeque::new(),
+		}
+	}
+
+	fn get(&mut self, key: &str) -> Option<Option<Vec<WordDefinition>>>
+	{
+		let result = self.results.get(key)?.clone();
+		if let Some(pos) = self.order.iter().position(|k| k == key) {
+			if let Some(key) = self.order.remove(pos) {
+				self.order.push_back(key);
+			}
+		}
+		Some(result)
+	}
+
+	fn insert(&mut self, key: String, result: Option<Vec<WordDefinition>>)
+	{
+		while self.results.len() >= self.capacity {
+			if let Some(lru) = self.order.pop_front() {
+				self.results.remove(&lru);
+			} else {
+				break;
+			}
+		}
+		self.order.push_back(key.clone());
+		self.results.insert(key, result);
+	}
+}
+
+/// lookup results of the wrapped dict kept for repeated words,
+/// e.g. while scanning text, other methods forwarded without cache,
+/// failed lookups not kept
+pub struct CachedLookup<D: StarDict> {
+	dict: D,
+	collation: Collation,
+	cache: Mutex<LookupCache>,
+}
+
+impl<D: StarDict> CachedLookup<D> {
+	/// keep at most capacity results, at least one
+	#[inline]
+	pub fn new(dict: D, capacity: usize) -> Self
+	{
+		CachedLookup { dict, collation: Collation::default(), cache: Mutex::new(LookupCache::new(capacity)) }
+	}
+
+	/// the collation the dict opened with, words normalized the same
+	/// share one result
+	#[inline]
+	pub fn collation(mut self, collation: Collation) -> Self
+	{
+		self.collation = collation;
+		self
+	}
+
+	#[inline]
+	pub fn dict(&self) -> &D
+	{
+		&self.dict
+	}
+
+	#[inline]
+	pub fn into_inner(self) -> D
+	{
+		self.dict
+	}
+
+	/// drop all kept results, e.g. after the dict changed
+	pub fn clear(&self)
+	{
+		let mut cache = self.cache.lock().unwrap_or_else(PoisonError::into_inner);
+		cache.results.clear();
+		cache.order.clear();
+	}
+}
+
+impl<D: StarDict> StarDict for CachedLookup<D> {
+	#[inline]
+	fn path(&self) -> &PathBuf {
+		self.dict.path()
+	}
+
+	#[inline]
+	fn ifo(&self) -> &Ifo {
+		self.dict.ifo()
+	}
+
+	#[inline]
+	fn dict_source_filename(&self) -> Option<&str> {
+		self.dict.dict_source_filename()
+	}
+
+	#[inline]
+	fn dict_source_comment(&self) -> Option<&str> {
+		self.dict.dict_source_comment()
+	}
+
+	#[inline]
+	fn dict_size(&self) -> Result<u64> {
+		self.dict.dict_size()
+	}
+
+	#[inline]
+	fn cache_size(&self) -> Result<u64> {
+		self.dict.cache_size()
+	}
+
+	fn lookup(&self, word: &str) -> Result<Option<Vec<WordDefinition>>> {
+		let key = self.collation.normalize(word);
+		if let Some(result) = self.cache.lock().unwrap_or_else(PoisonError::into_inner).get(&key) {
+			return Ok(result);
+		}
+		// not locked while looking up, the same word may be looked up twice
+		let result = self.dict.lookup(word)?;
+		self.cache.lock().unwrap_or_else(PoisonError::into_inner).insert(key, result.clone());
+		Ok(result)
+	}
+
+	#[inline]
+	fn lookup_detailed(&self, word: &str) -> Result<Option<Vec<MatchedDefinition>>> {
+		self.dict.lookup_detailed(word)
+	}
+
+	#[inline]
+	fn lookup_limited(&self, word: &str, max: usize) -> Result<Option<Vec<WordDefinition>>> {
+		self.dict.lookup_limited(word, max)
+	}
+
+	#[inline]
+	fn contains(&self, word: &str) -> Result<bool> {
+		self.dict.contains(word)
+	}
+
+	#[inline]
+	fn lookup_exact(&self, word: &str) -> Result<Option<Vec<WordDefinition>>> {
+		self.dict.lookup_exact(word)
+	}
+
+	#[inline]
+	fn search_prefix(&self, prefix: &str, limit: usize) -> Result<Vec<String>> {
+		self.dict.search_prefix(prefix, limit)
+	}
+
+	#[inline]
+	fn words(&self) -> Result<Vec<String>> {
+		self.dict.words()
+	}
+
+	#[inline]
+	fn neighbors(&self, word: &str, before: usize, after: usize) -> Result<Vec<String>> {
+		self.dict.neighbors(word, before, after)
+	}
+
+	#[inline]
+	fn suggest(&self, word: &str, max_distance: usize, limit: usize) -> Result<Vec<String>> {
+		self.dict.suggest(word, max_distance, limit)
+	}
+
+	#[inline]
+	fn search_glob(&self, pattern: &str, limit: usize) -> Result<Vec<String>> {
+		self.dict.search_glob(pattern, limit)
+	}
+
+	#[inline]
+	fn synonyms_of(&self, word: &str) -> Result<Vec<String>> {
+		self.dict.synonyms_of(word)
+	}
+
+	#[inline]
+	fn resource_db(&self) -> Result<Option<&ResourceDb>> {
+		self.dict.resource_db()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::cell::Cell;
+	use std::path::PathBuf;
+	use crate::error::Result;
+	use crate::tests::{create_dict, SAMPLE_WORDS};
+	use crate::{no_cache, CachedLookup, Ifo, MatchedDefinition, StarDict, StarDictStd, WordDefinition};
+
+	/// count lookups reaching the dict
+	struct Counting {
+		dict: StarDictStd,
+		lookups: Cell<usize>,
+	}
+
+	impl StarDict for Counting {
+		fn path(&self) -> &PathBuf {
+			self.dict.path()
+		}
+
+		fn ifo(&self) -> &Ifo {
+			self.dict.ifo()
+		}
+
+		fn dict_size(&self) -> Result<u64> {
+			self.dict.dict_size()
+		}
+
+		fn lookup(&self, word: &str) -> Result<Option<Vec<WordDefinition>>> {
+			self.lookups.set(self.lookups.get() + 1);
+			self.dict.lookup(word)
+		}
+
+		fn lookup_detailed(&self, word: &str) -> Result<Option<Vec<MatchedDefinition>>> {
+			self.dict.lookup_detailed(word)
+		}
+
+		fn lookup_limited(&self, word: &str, max: usize) -> Result<Option<Vec<WordDefinition>>> {
+			self.dict.lookup_limited(word, max)
+		}
+
+		fn contains(&self, word: &str) -> Result<bool> {
+			self.dict.contains(word)
+		}
+
+		fn lookup_exact(&self, word: &str) -> Result<Option<Vec<WordDefinition>>> {
+			self.dict.lookup_exact(word)
+		}
+
+		fn search_prefix(&self, prefix: &str, limit: usize) -> Result<Vec<String>> {
+			self.dict.search_prefix(prefix, limit)
+		}
+
+		fn words(&self) -> Result<Vec<String>> {
+			self.dict.words()
+		}
+
+		fn neighbors(&self, word: &str, before: usize, after: usize) -> Result<Vec<String>> {
+			self.dict.neighbors(word, before, after)
+		}
+
+		fn suggest(&self, word: &str, max_distance: usize, limit: usize) -> Result<Vec<String>> {
+			self.dict.suggest(word, max_distance, limit)
+		}
+
+		fn search_glob(&self, pattern: &str, limit: usize) -> Result<Vec<String>> {
+			self.dict.search_glob(pattern, limit)
+		}
+
+		fn synonyms_of(&self, word: &str) -> Result<Vec<String>> {
+			self.dict.synonyms_of(word)
+		}
+	}
+
+	#[test]
+	fn cached_lookup() {
+		let ifo = create_dict("cached_lookup", &SAMPLE_WORDS, &[]);
+		let dict = Counting { dict: no_cache(&ifo).unwrap(), lookups: Cell::new(0) };
+		let cached = CachedLookup::new(dict, 2);
+		let apple = cached.lookup("apple").unwrap();
+		assert!(apple.is_some());
+		assert_eq!(cached.dict().lookups.get(), 1);
+		// same normalized word
+		assert_eq!(cached.lookup("APPLE").unwrap(), apple);
+		assert_eq!(cached.dict().lookups.get(), 1);
+		// not found kept as well
+		assert!(cached.lookup("cherry").unwrap().is_none());
+		assert!(cached.lookup("cherry").unwrap().is_none());
+		assert_eq!(cached.dict().lookups.get(), 2);
+
+		// apple used more recently than cherry, cherry evicted
+		cached.lookup("apple").unwrap();
+		cached.lookup("band").unwrap();
+		assert_eq!(cached.dict().lookups.get(), 3);
+		cached.lookup("apple").unwrap();
+		assert_eq!(cached.dict().lookups.get(), 3);
+		cached.lookup("cherry").unwrap();
+		assert_eq!(cached.dict().lookups.get(), 4);
+
+		let results = cached.lookup_many(&["apple", "cherry"]).unwrap();
+		assert_eq!(results[0].1, apple);
+		assert_eq!(cached.dict().lookups.get(), 4);
+		cached.clear();
+		cached.lookup("apple").unwrap();
+		assert_eq!(cached.dict().lookups.get(), 5);
+		assert_eq!(cached.into_inner().lookups.get(), 5);
+	}
+}
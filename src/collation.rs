@@ -0,0 +1,161 @@
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+
+/// how headwords and queries are normalized into lookup keys
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum Collation {
+	/// str::to_lowercase, the behavior of caches built by earlier versions
+	#[default]
+	Lowercase,
+	/// only ASCII letters are lowercased, other characters kept as is
+	AsciiLower,
+	/// lowercase with full case folding, e.g. "ß" to "ss", "ς" to "σ"
+	UnicodeCaseFold,
+	/// language specific lowercase, e.g. "tr" and "az" for dotted and dotless i
+	Locale(String),
+	/// the normalizer for both queries and the keys of caches,
+	/// e.g. unicode normalization or diacritic folding
+	Custom(Normalizer),
+}
+
+/// normalize function of Collation::Custom, caches built with another
+/// name rebuilt, so change the name when the function changes
+#[derive(Clone)]
+pub struct Normalizer {
+	name: String,
+	f: Arc<dyn Fn(&str) -> String + Send + Sync>,
+}
+
+impl Normalizer {
+	#[inline]
+	pub fn new(name: impl Into<String>, f: impl Fn(&str) -> String + Send + Sync + 'static) -> Self
+	{
+		Normalizer { name: name.into(), f: Arc::new(f) }
+	}
+
+	#[inline]
+	pub fn name(&self) -> &str
+	{
+		&self.name
+	}
+}
+
+impl Debug for Normalizer {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+	{
+		f.debug_tuple("Normalizer").field(&self.name).finish()
+	}
+}
+
+/// same by name, functions not comparable
+impl PartialEq for Normalizer {
+	#[inline]
+	fn eq(&self, other: &Self) -> bool
+	{
+		self.name == other.name
+	}
+}
+
+impl Eq for Normalizer {}
+
+impl Collation {
+	pub fn normalize(&self, word: &str) -> String
+	{
+		match self {
+			Collation::Lowercase => word.to_lowercase(),
+			Collation::AsciiLower => word.to_ascii_lowercase(),
+			Collation::UnicodeCaseFold => {
+				let mut folded = String::with_capacity(word.len());
+				for ch in word.chars() {
+					match ch {
+						'ß' | 'ẞ' => folded.push_str("ss"),
+						'ς' => folded.push('σ'),
+						'ſ' => folded.push('s'),
+						_ => folded.extend(ch.to_lowercase()),
+					}
+				}
+				folded
+			}
+			Collation::Locale(locale) => {
+				let language = locale.split(['-', '_']).next().unwrap_or("");
+				if language.eq_ignore_ascii_case("tr") || language.eq_ignore_ascii_case("az") {
+					let mut lowercase = String::with_capacity(word.len());
+					for ch in word.chars() {
+						match ch {
+							'I' => lowercase.push('ı'),
+							'İ' => lowercase.push('i'),
+							_ => lowercase.extend(ch.to_lowercase()),
+						}
+					}
+					lowercase
+				} else {
+					word.to_lowercase()
+				}
+			}
+			Collation::Custom(normalizer) => (normalizer.f)(word),
+		}
+	}
+
+	/// identify the collation a cache was built with
+	#[cfg(any(feature = "sled", feature = "sqlite"))]
+	pub(crate) fn id(&self) -> String
+	{
+		match self {
+			Collation::Lowercase => "lowercase".to_owned(),
+			Collation::AsciiLower => "ascii".to_owned(),
+			Collation::UnicodeCaseFold => "casefold".to_owned(),
+			Collation::Locale(locale) => format!("locale:{}", locale.to_lowercase()),
+			Collation::Custom(normalizer) => format!("custom:{}", normalizer.name),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{no_cache, no_cache_with_collation, Collation, Normalizer, StarDict};
+	use crate::tests::{create_dict, strip_accents, ACCENT_WORDS, COLLATION_WORDS};
+
+	#[test]
+	fn normalize() {
+		assert_eq!(Collation::Lowercase.normalize("Straße İ"), "straße i̇");
+		assert_eq!(Collation::AsciiLower.normalize("ÄBC"), "Äbc");
+		assert_eq!(Collation::UnicodeCaseFold.normalize("STRASSE Straße"), "strasse strasse");
+		assert_eq!(Collation::Locale("tr".to_owned()).normalize("DİYARBAKIR"), "diyarbakır");
+		assert_eq!(Collation::Locale("en_US".to_owned()).normalize("DIY"), "diy");
+	}
+
+	#[test]
+	fn custom() {
+		let upper = Collation::Custom(Normalizer::new("upper", str::to_uppercase));
+		assert_eq!(upper.normalize("Apple"), "APPLE");
+		#[cfg(any(feature = "sled", feature = "sqlite"))]
+		assert_eq!(upper.id(), "custom:upper");
+		assert_eq!(upper, Collation::Custom(Normalizer::new("upper", |word: &str| word.to_owned())));
+		assert_ne!(upper, Collation::Custom(Normalizer::new("lower", str::to_lowercase)));
+	}
+
+	#[test]
+	fn lookup() {
+		let ifo = create_dict("collation", &COLLATION_WORDS, &[]);
+		let dict = no_cache(&ifo).unwrap();
+		assert!(dict.lookup("äpfel").unwrap().is_some());
+		assert!(dict.lookup("diyarbakır").unwrap().is_none());
+
+		let dict = no_cache_with_collation(&ifo, Collation::Locale("tr".to_owned())).unwrap();
+		assert!(dict.lookup("diyarbakır").unwrap().is_some());
+
+		let dict = no_cache_with_collation(&ifo, Collation::AsciiLower).unwrap();
+		assert!(dict.lookup("äpfel").unwrap().is_none());
+		assert!(dict.lookup("Äpfel").unwrap().is_some());
+	}
+
+	#[test]
+	fn lookup_custom() {
+		let ifo = create_dict("custom_collation", &ACCENT_WORDS, &[]);
+		assert!(no_cache(&ifo).unwrap().lookup("cafe").unwrap().is_none());
+		let dict = no_cache_with_collation(&ifo, strip_accents()).unwrap();
+		assert_eq!(dict.lookup("CAFE").unwrap().unwrap()[0].word, "Café");
+		assert!(dict.lookup("Facade").unwrap().is_some());
+		assert_eq!(dict.search_prefix("fac", 0).unwrap(), ["façade"]);
+	}
+}
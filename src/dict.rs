@@ -1,94 +1,628 @@
-use std::fs::{File, OpenOptions};
 use std::io::{BufReader, Read, Seek, SeekFrom};
 use crate::error::{Error, Result};
 
+use std::collections::BTreeSet;
 use std::path::PathBuf;
-use crate::{buf_to_string, WordDefinition, WordDefinitionSegment};
+use std::sync::{Mutex, PoisonError};
+use crate::{WordDefinition, WordDefinitionSegment, WordDefinitionSegmentRef};
 use crate::dictzip::DictZip;
+use crate::encoding::{Encoding, LossyPolicy};
 use crate::idx::IdxEntry;
 use crate::ifo::Ifo;
+use crate::opener::OpenOptions;
+#[cfg(all(feature = "mmap", unix))]
+use crate::mmap::Mmap;
+use crate::shard::Shards;
 
 enum DictInner {
-	Plain(BufReader<File>, usize),
-	DictZip(DictZip),
+	Plain(BufReader<Shards>, usize),
+	#[cfg(all(feature = "mmap", unix))]
+	Mapped(Mmap),
+	DictZip(Box<DictZip<Shards>>),
 }
 
+impl DictInner {
+	/// mapped only when not split in shards, read with seek if failed mapping
+	#[cfg(all(feature = "mmap", unix))]
+	fn plain(shards: Shards) -> Result<DictInner> {
+		if let Some(mmap) = shards.single().and_then(|file| Mmap::new(file).ok()) {
+			return Ok(DictInner::Mapped(mmap));
+		}
+		Self::seek(shards)
+	}
+
+	#[cfg(not(all(feature = "mmap", unix)))]
+	#[inline]
+	fn plain(shards: Shards) -> Result<DictInner> {
+		Self::seek(shards)
+	}
+
+	fn seek(shards: Shards) -> Result<DictInner> {
+		let file_size = shards.len() as usize;
+		Ok(DictInner::Plain(BufReader::new(shards), file_size))
+	}
+}
+
+/// reader and dictzip chunk cache behind a mutex, so lookup with shared reference
 pub struct Dict {
-	inner: DictInner,
+	inner: Mutex<DictInner>,
+	// fail on type chars in data with sametypesequence
+	strict: bool,
+	// blocks parsed leniently so far
+	warnings: Mutex<BTreeSet<String>>,
+	encoding: Encoding,
+	lossy: LossyPolicy,
+	// from the dictzip header
+	filename: Option<String>,
+	comment: Option<String>,
 }
 
-impl<'a> Dict {
+impl Dict {
+	/// numbered shards of the path read as one dict, a split .dz is
+	/// the byte pieces of one dictzip file
+	#[inline]
 	pub fn new(path: PathBuf, bz: bool) -> Result<Dict> {
-		let file = OpenOptions::new()
-			.read(true)
-			.open(path)
-			.map_err(|e| Error::FailedOpenFile("dict", e))?;
-		let inner = if bz {
+		Self::open(path, bz, &OpenOptions::default())
+	}
+
+	/// with the charset, lossy policy, strictness and chunk cache capacity of the options,
+	/// dictzip decompressed and checked with the gzip trailer first with verify_dict
+	pub fn open(path: PathBuf, bz: bool, options: &OpenOptions) -> Result<Dict> {
+		let file = Shards::open(&path, "dict")?;
+		let (inner, filename, comment) = if bz {
 			let reader = BufReader::new(file);
-			let dictzip = DictZip::new(reader)?;
-			DictInner::DictZip(dictzip)
+			let dictzip = if options.verify_dict {
+				DictZip::new_verified(reader, options.chunk_cache)?
+			} else {
+				DictZip::with_cache_capacity(reader, options.chunk_cache)?
+			};
+			let filename = dictzip.filename().map(str::to_owned);
+			let comment = dictzip.comment().map(str::to_owned);
+			(DictInner::DictZip(Box::new(dictzip)), filename, comment)
 		} else {
-			let file_size = file.metadata()?.len() as usize;
-			let reader = BufReader::new(file);
-			DictInner::Plain(reader, file_size)
+			(DictInner::plain(file)?, None, None)
 		};
-		Ok(Dict { inner })
+		Ok(Self::with_inner(inner, filename, comment)
+			.strict(options.strict)
+			.encoding(options.encoding)
+			.lossy(options.lossy))
+	}
+
+	#[inline]
+	fn with_inner(inner: DictInner, filename: Option<String>, comment: Option<String>) -> Dict {
+		Dict {
+			inner: Mutex::new(inner),
+			strict: false,
+			warnings: Mutex::new(BTreeSet::new()),
+			encoding: Encoding::Utf8,
+			lossy: LossyPolicy::default(),
+			filename,
+			comment,
+		}
+	}
+
+	/// chunk length and count of dictzip, None for plain dict
+	pub fn compression_info(&self) -> Option<(usize, usize)> {
+		let inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+		if let DictInner::DictZip(dz) = &*inner {
+			Some((dz.chunk_length(), dz.chunk_count()))
+		} else {
+			None
+		}
+	}
+
+	/// bytes of the plain dict, or dictzip uncompressed
+	pub fn data_size(&self) -> usize {
+		let inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+		match &*inner {
+			DictInner::Plain(_, file_size) => *file_size,
+			#[cfg(all(feature = "mmap", unix))]
+			DictInner::Mapped(mmap) => mmap.len(),
+			DictInner::DictZip(dz) => dz.data_size(),
+		}
+	}
+
+	/// original file name in the dictzip header, None for plain dict
+	#[inline]
+	pub fn filename(&self) -> Option<&str> {
+		self.filename.as_deref()
+	}
+
+	/// comment in the dictzip header, None for plain dict
+	#[inline]
+	pub fn comment(&self) -> Option<&str> {
+		self.comment.as_deref()
+	}
+
+	/// with strict, type chars found in data while sametypesequence set
+	/// is an error, otherwise warned and parsed with the type chars
+	#[inline]
+	pub fn strict(mut self, strict: bool) -> Dict {
+		self.strict = strict;
+		self
+	}
+
+	/// charset of text segments
+	#[inline]
+	pub fn encoding(mut self, encoding: Encoding) -> Dict {
+		self.encoding = encoding;
+		self
 	}
 
-	pub fn get_definition(&mut self, idx: &IdxEntry, ifo: &Ifo) -> Result<Option<WordDefinition>> {
-		let mut segments = vec![];
+	/// invalid utf-8 in text segments, with LossyPolicy::Error
+	/// the definition is InvalidDictContent
+	#[inline]
+	pub fn lossy(mut self, lossy: LossyPolicy) -> Dict {
+		self.lossy = lossy;
+		self
+	}
+
+	/// blocks read so far parsed leniently, e.g. type chars in data
+	/// with sametypesequence, sorted
+	pub fn warnings(&self) -> Vec<String> {
+		self.warnings.lock().unwrap_or_else(PoisonError::into_inner).iter().cloned().collect()
+	}
+
+	/// whole decompressed dict data, memory used as large as the
+	/// uncompressed dict, e.g. hundreds of MB for big dictionaries
+	pub fn read_all(&self) -> Result<Vec<u8>> {
+		let mut inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+		match &mut *inner {
+			DictInner::Plain(reader, file_size) => {
+				reader.seek(SeekFrom::Start(0))?;
+				let mut buf = Vec::with_capacity(*file_size);
+				reader.read_to_end(&mut buf)?;
+				Ok(buf)
+			}
+			#[cfg(all(feature = "mmap", unix))]
+			DictInner::Mapped(mmap) => Ok(mmap.to_vec()),
+			DictInner::DictZip(dz) => dz.read_all(),
+		}
+	}
+
+	/// a definition for every block with its original headword,
+	/// entries merged by normalized key are kept apart
+	pub fn get_definitions(&self, idx: &IdxEntry, ifo: &Ifo) -> Result<Vec<WordDefinition>> {
+		// reader seeks before every read, still usable after a panic in other thread
+		let mut inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+		let mut definitions = vec![];
+		for block in &idx.blocks {
+			// empty block has no segment, even with sametypesequence
+			if block.size == 0 {
+				continue;
+			}
+			let word = idx.block_word(block);
+			let segments = read_block(&mut inner, block.offset, block.size, word,
+				|data| self.parse_entry_owned(data, word, ifo))?;
+			if !segments.is_empty() {
+				definitions.push(WordDefinition { word: word.to_owned(), segments });
+			}
+		}
+		Ok(definitions)
+	}
+
+	/// f called with the headword and segments borrowed from the data
+	/// for every block, empty ones skipped, same as get_definitions without copy,
+	/// dict locked while f runs
+	pub fn visit_definitions<F>(&self, idx: &IdxEntry, ifo: &Ifo, mut f: F) -> Result<()>
+		where F: FnMut(&str, &[WordDefinitionSegmentRef])
+	{
+		let mut inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
 		for block in &idx.blocks {
-			let offset = block.offset;
-			let size = block.size;
-			let result = match &mut self.inner {
-				DictInner::Plain(reader, file_size) =>
-					if offset + size <= *file_size {
-						reader.seek(SeekFrom::Start(offset as u64))?;
-						let mut buf = vec![0; size];
-						reader.read_exact(&mut buf)?;
-						parse_data(&buf, &ifo.sametypesequence)
-					} else {
-						None
-					}
-				DictInner::DictZip(dz) => {
-					let (buf, offset) = dz.get_segment_data(offset, size)
-						.ok_or_else(|| Error::InvalidDict)?;
-					let data = &buf[offset..offset + size];
-					parse_data(data, &ifo.sametypesequence)
+			if block.size == 0 {
+				continue;
+			}
+			let word = idx.block_word(block);
+			read_block(&mut inner, block.offset, block.size, word, |data| {
+				let segments = self.parse_entry(data, word, ifo)?;
+				if !segments.is_empty() {
+					f(word, &segments);
 				}
-			};
+				Ok(())
+			})?;
+		}
+		Ok(())
+	}
+
+	/// definition of a raw offset and size from the idx, word left empty,
+	/// None for empty block, block out of the dict is InvalidDictBlock with empty word
+	pub fn get_at(&self, offset: usize, size: usize, ifo: &Ifo) -> Result<Option<WordDefinition>> {
+		let segments = self.get_segments("", offset, size, ifo)?;
+		Ok(segments.map(|segments| WordDefinition { word: String::new(), segments }))
+	}
 
-			if let Some((types, text)) = result {
-				segments.push(WordDefinitionSegment {
-					types,
-					text,
-				});
+	/// segments of a block, None for empty block, word of the block for errors
+	pub fn get_segments(&self, word: &str, offset: usize, size: usize, ifo: &Ifo)
+		-> Result<Option<Vec<WordDefinitionSegment>>> {
+		if size == 0 {
+			return Ok(None);
+		}
+		let mut inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+		let segments = read_block(&mut inner, offset, size, word,
+			|data| self.parse_entry_owned(data, word, ifo))?;
+		Ok(if segments.is_empty() { None } else { Some(segments) })
+	}
+
+	/// raw bytes of a block, name of the entry for errors
+	pub fn read(&self, offset: usize, size: usize, name: &str) -> Result<Vec<u8>> {
+		let mut inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+		read_block(&mut inner, offset, size, name, |data| Ok(data.to_vec()))
+	}
+}
+
+/// call f with data of the block, without copy when mapped or in dictzip chunk cache
+fn read_block<T, F>(inner: &mut DictInner, offset: usize, size: usize, name: &str, f: F) -> Result<T>
+	where F: FnOnce(&[u8]) -> Result<T>
+{
+	// truncated dict, corrupted idx, or chunk failed to inflate
+	let invalid = || Error::InvalidDictBlock { word: name.to_owned(), offset };
+	match inner {
+		DictInner::Plain(reader, file_size) =>
+			if offset.checked_add(size).is_some_and(|end| end <= *file_size) {
+				reader.seek(SeekFrom::Start(offset as u64)).map_err(|_| invalid())?;
+				let mut buf = vec![0; size];
+				reader.read_exact(&mut buf).map_err(|_| invalid())?;
+				f(&buf)
+			} else {
+				Err(invalid())
 			}
+		#[cfg(all(feature = "mmap", unix))]
+		DictInner::Mapped(mmap) => {
+			let data = offset.checked_add(size)
+				.and_then(|end| mmap.get(offset..end))
+				.ok_or_else(invalid)?;
+			f(data)
 		}
+		DictInner::DictZip(_) if size == 0 => f(&[]),
+		DictInner::DictZip(dz) => {
+			let (buf, start) = dz.get_segment_data(offset, size).ok_or_else(invalid)?;
+			let data = buf.get(start..start + size).ok_or_else(invalid)?;
+			f(data)
+		}
+	}
+}
 
-		let definitions = if segments.len() == 0 {
-			None
+impl Dict {
+	fn parse_entry<'a>(&self, data: &'a [u8], word: &str, ifo: &Ifo) -> Result<Vec<WordDefinitionSegmentRef<'a>>> {
+		let sametypesequence = if ifo.sametypesequence.is_empty()
+			|| !has_type_chars(data, &ifo.sametypesequence) {
+			ifo.sametypesequence.as_str()
+		} else if self.strict {
+			return Err(Error::InvalidDictContent(word.to_owned()));
 		} else {
-			Some(WordDefinition {
-				word: idx.word.clone(),
-				segments,
-			})
+			self.warnings.lock().unwrap_or_else(PoisonError::into_inner)
+				.insert(format!("type chars found in data of {} with sametypesequence", word));
+			""
 		};
-		Ok(definitions)
+		parse_segments(data, sametypesequence, self.encoding, self.lossy)
+			.ok_or_else(|| Error::InvalidDictContent(word.to_owned()))
 	}
+
+	#[inline]
+	fn parse_entry_owned(&self, data: &[u8], word: &str, ifo: &Ifo) -> Result<Vec<WordDefinitionSegment>> {
+		Ok(self.parse_entry(data, word, ifo)?.into_iter().map(WordDefinitionSegmentRef::into_owned).collect())
+	}
+}
+
+/// data of malformed dictionaries stored with every type char
+/// of sametypesequence, the last lowercase field null-terminated as well
+fn has_type_chars(data: &[u8], sametypesequence: &str) -> bool {
+	let mut data = data;
+	for types in sametypesequence.chars() {
+		if !types.is_ascii() || data.first() != Some(&(types as u8)) {
+			return false;
+		}
+		data = &data[1..];
+		if types.is_ascii_lowercase() && !data.contains(&0) {
+			return false;
+		}
+		data = if let Some((_, remain)) = split_field(data, types, false) {
+			remain
+		} else {
+			return false;
+		};
+	}
+	data.is_empty()
+}
+
+/// split data block into segments
+///
+/// with sametypesequence, the type chars are not stored in the block,
+/// lowercase fields are null-terminated, uppercase fields are prefixed
+/// with 4 bytes big-endian size, but the final field runs to the end.
+/// without sametypesequence, every field starts with its type char.
+/// None if a text segment is invalid with LossyPolicy::Error
+fn parse_segments<'a>(data: &'a [u8], sametypesequence: &str, encoding: Encoding, lossy: LossyPolicy)
+	-> Option<Vec<WordDefinitionSegmentRef<'a>>> {
+	let mut segments = vec![];
+	let mut data = data;
+	if sametypesequence.is_empty() {
+		while data.len() > 1 {
+			let types = data[0] as char;
+			let (field, remain) = if let Some(split) = split_field(&data[1..], types, false) {
+				split
+			} else {
+				break;
+			};
+			segments.push(WordDefinitionSegmentRef::decode(types, field, encoding, lossy)?);
+			data = remain;
+		}
+	} else {
+		let count = sametypesequence.chars().count();
+		for (i, types) in sametypesequence.chars().enumerate() {
+			let (field, remain) = if let Some(split) = split_field(data, types, i == count - 1) {
+				split
+			} else {
+				break;
+			};
+			segments.push(WordDefinitionSegmentRef::decode(types, field, encoding, lossy)?);
+			data = remain;
+		}
+	}
+	Some(segments)
 }
 
-pub fn parse_data(data: &[u8], types: &str) -> Option<(String, String)> {
-	let (types, text) = if types.len() == 0 {
-		if data.len() < 2 {
+/// return the field and remain data
+fn split_field(data: &[u8], types: char, last: bool) -> Option<(&[u8], &[u8])> {
+	if types.is_ascii_uppercase() {
+		if last {
+			return Some((data, &[]));
+		}
+		if data.len() < 4 {
+			return None;
+		}
+		let size = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+		let data = &data[4..];
+		if size > data.len() {
 			return None;
 		}
-		let mut types = String::new();
-		types.push(data[0] as char);
-		let text = buf_to_string(&data[1..]);
-		(types, text)
+		Some(data.split_at(size))
+	} else if let Some(end) = data.iter().position(|&b| b == 0) {
+		Some((&data[..end], &data[end + 1..]))
 	} else {
-		(types.to_owned(), buf_to_string(&data[..]))
-	};
-	Some((types, text))
+		Some((data, &[]))
+	}
+}
+
+/// owned segments of parse_segments
+#[cfg(test)]
+fn parse_data(data: &[u8], sametypesequence: &str, encoding: Encoding, lossy: LossyPolicy)
+	-> Option<Vec<WordDefinitionSegment>> {
+	let segments = parse_segments(data, sametypesequence, encoding, lossy)?;
+	Some(segments.into_iter().map(WordDefinitionSegmentRef::into_owned).collect())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+	use crate::dict::{has_type_chars, parse_data, Dict};
+	use crate::error::Error;
+	use crate::ifo::Ifo;
+	use crate::{no_cache, Encoding, LossyPolicy, StarDict, WordDefinitionSegment};
+	use crate::tests::{create_dict, create_dict_with, SAMPLE_WORDS};
+
+	fn segments(list: &[(&str, &str)]) -> Vec<WordDefinitionSegment> {
+		list.iter()
+			.map(|(t, s)| WordDefinitionSegment::new(t.to_string(), s.as_bytes()))
+			.collect()
+	}
+
+	#[test]
+	fn parse_single_type() {
+		assert_eq!(parse_data(b"a round fruit", "m", Encoding::Utf8, LossyPolicy::Strip).unwrap(), segments(&[("m", "a round fruit")]));
+	}
+
+	#[test]
+	fn parse_multiple_types() {
+		assert_eq!(parse_data(b"<k>apple</k>\0<b>apple</b>", "xh", Encoding::Utf8, LossyPolicy::Strip).unwrap(),
+			segments(&[("x", "<k>apple</k>"), ("h", "<b>apple</b>")]));
+		// missing fields are empty
+		assert_eq!(parse_data(b"only", "xh", Encoding::Utf8, LossyPolicy::Strip).unwrap(), segments(&[("x", "only"), ("h", "")]));
+	}
+
+	#[test]
+	fn parse_mixed_types() {
+		let mut data = vec![];
+		data.extend_from_slice(&3u32.to_be_bytes());
+		data.extend_from_slice(b"wav");
+		data.extend_from_slice(b"fruit\0");
+		data.extend_from_slice(b"text");
+		let parsed = parse_data(&data, "Wmt", Encoding::Utf8, LossyPolicy::Strip).unwrap();
+		assert_eq!(parsed, segments(&[("W", "wav"), ("m", "fruit"), ("t", "text")]));
+		assert_eq!(parsed[0].text, "");
+		assert_eq!(parsed[0].data.as_deref(), Some(&b"wav"[..]));
+		assert_eq!(parsed[1].data, None);
+
+		// size out of the block
+		let mut data = vec![];
+		data.extend_from_slice(&30u32.to_be_bytes());
+		data.extend_from_slice(b"wav");
+		assert!(parse_data(&data, "Wm", Encoding::Utf8, LossyPolicy::Strip).unwrap().is_empty());
+	}
+
+	#[test]
+	fn parse_final_field() {
+		// size prefixed audio, then text to the end
+		let mut data = vec![];
+		data.extend_from_slice(&4u32.to_be_bytes());
+		data.extend_from_slice(b"w\0av");
+		data.extend_from_slice(b"<b>fruit</b>");
+		let parsed = parse_data(&data, "Wg", Encoding::Utf8, LossyPolicy::Strip).unwrap();
+		assert_eq!(parsed.len(), 2);
+		assert_eq!(parsed[0].data.as_deref(), Some(&b"w\0av"[..]));
+		assert_eq!(parsed[1].text, "<b>fruit</b>");
+
+		// final audio not size prefixed, null bytes and all to the end
+		let mut data = vec![];
+		data.extend_from_slice(b"<b>fruit</b>\0");
+		data.extend_from_slice(&4u32.to_be_bytes());
+		data.extend_from_slice(b"w\0av");
+		let parsed = parse_data(&data, "gW", Encoding::Utf8, LossyPolicy::Strip).unwrap();
+		assert_eq!(parsed.len(), 2);
+		assert_eq!(parsed[0].text, "<b>fruit</b>");
+		assert_eq!(parsed[1].data.as_deref(), Some(&[0, 0, 0, 4, b'w', 0, b'a', b'v'][..]));
+		assert!(parsed[1].text.is_empty());
+	}
+
+	#[test]
+	fn parse_without_sametypesequence() {
+		let mut data = vec![];
+		data.extend_from_slice(b"mfruit\0");
+		data.push(b'P');
+		data.extend_from_slice(&3u32.to_be_bytes());
+		data.extend_from_slice(b"png");
+		data.extend_from_slice(b"gtext\0");
+		assert_eq!(parse_data(&data, "", Encoding::Utf8, LossyPolicy::Strip).unwrap(),
+			segments(&[("m", "fruit"), ("P", "png"), ("g", "text")]));
+		assert!(parse_data(b"m", "", Encoding::Utf8, LossyPolicy::Strip).unwrap().is_empty());
+	}
+
+	#[test]
+	fn parse_lossy() {
+		let mut data = vec![];
+		data.extend_from_slice(&4u32.to_be_bytes());
+		data.extend_from_slice(b"\xff\xfe\0\x01");
+		data.extend_from_slice(b"caf\xe9");
+		let parsed = parse_data(&data, "Wm", Encoding::Utf8, LossyPolicy::Strip).unwrap();
+		assert_eq!(parsed[1].text, "caf");
+		let parsed = parse_data(&data, "Wm", Encoding::Utf8, LossyPolicy::Keep).unwrap();
+		assert_eq!(parsed[1].text, "caf\u{fffd}");
+		assert_eq!(parse_data(&data, "Wm", Encoding::Utf8, LossyPolicy::Error), None);
+		// binary data never decoded
+		let parsed = parse_data(b"\xff\xfe", "W", Encoding::Utf8, LossyPolicy::Error).unwrap();
+		assert_eq!(parsed[0].data.as_deref(), Some(&b"\xff\xfe"[..]));
+	}
+
+	#[test]
+	fn type_chars() {
+		assert!(has_type_chars(b"mfruit\0", "m"));
+		assert!(has_type_chars(b"ttext\0mfruit\0", "tm"));
+		let mut data = vec![b'W'];
+		data.extend_from_slice(&3u32.to_be_bytes());
+		data.extend_from_slice(b"wavmfruit\0");
+		assert!(has_type_chars(&data, "Wm"));
+
+		// well formed data
+		assert!(!has_type_chars(b"mango", "m"));
+		assert!(!has_type_chars(b"text\0fruit", "tm"));
+		assert!(!has_type_chars(b"ttext\0mfruit", "tm"));
+		assert!(!has_type_chars(b"mfruit\0more", "m"));
+	}
+
+	#[test]
+	fn get_at() {
+		let ifo_path = create_dict("dict_get_at", &SAMPLE_WORDS, &[]);
+		let ifo = Ifo::new(ifo_path.clone()).unwrap();
+		let dict = Dict::new(ifo_path.with_extension("dict"), false).unwrap();
+		// sorted as in the idx, application after Apple
+		let offset = SAMPLE_WORDS[0].1.len();
+		let size = SAMPLE_WORDS[1].1.len();
+		let definition = dict.get_at(offset, size, &ifo).unwrap().unwrap();
+		assert_eq!(definition.segments, segments(&[("m", "a formal request")]));
+		assert!(definition.word.is_empty());
+		assert!(dict.get_at(offset, 0, &ifo).unwrap().is_none());
+		let len = dict.read_all().unwrap().len();
+		assert!(matches!(dict.get_at(len - 2, 3, &ifo), Err(Error::InvalidDictBlock { word, offset })
+			if word.is_empty() && offset == len - 2));
+		assert!(dict.get_at(usize::MAX, 1, &ifo).is_err());
+	}
+
+	#[test]
+	#[cfg(all(feature = "mmap", unix))]
+	fn mmap() {
+		use crate::dict::DictInner;
+		use crate::idx::{IdxEntry, IdxEntryBlock};
+		use crate::shard::Shards;
+
+		let ifo_path = create_dict("dict_mmap", &SAMPLE_WORDS, &[]);
+		let path = ifo_path.with_extension("dict");
+		let mmap = Dict::new(path.clone(), false).unwrap();
+		assert!(matches!(*mmap.inner.lock().unwrap(), DictInner::Mapped(_)));
+		let seek = Dict::with_inner(DictInner::seek(Shards::open(&path, "dict").unwrap()).unwrap(), None, None);
+		assert_eq!(mmap.read_all().unwrap(), seek.read_all().unwrap());
+
+		let ifo = Ifo::new(ifo_path).unwrap();
+		let len = mmap.read_all().unwrap().len();
+		for (offset, size) in [(0, 5), (3, 4), (0, len), (len, 0)] {
+			let entry = IdxEntry { word: "word".to_owned(), blocks: vec![IdxEntryBlock { offset, size, variant: None }] };
+			assert_eq!(mmap.get_definitions(&entry, &ifo).unwrap(), seek.get_definitions(&entry, &ifo).unwrap());
+		}
+		let entry = IdxEntry { word: "word".to_owned(), blocks: vec![IdxEntryBlock { offset: len - 2, size: 3, variant: None }] };
+		assert!(matches!(mmap.get_definitions(&entry, &ifo), Err(Error::InvalidDictBlock { word, .. }) if word == "word"));
+		assert!(matches!(seek.get_definitions(&entry, &ifo), Err(Error::InvalidDictBlock { word, .. }) if word == "word"));
+	}
+
+	#[test]
+	fn compression_info() {
+		let ifo = create_dict("compression_info", &SAMPLE_WORDS, &[]);
+		assert_eq!(no_cache(&ifo).unwrap().compression_info(), None);
+		let dict_path = ifo.with_extension("dict");
+		let data = fs::read(&dict_path).unwrap();
+		let mut dz = vec![];
+		crate::DictZipWriter::new(8).write(&data, &mut dz).unwrap();
+		fs::remove_file(&dict_path).unwrap();
+		fs::write(ifo.with_extension("dict.dz"), &dz).unwrap();
+		assert_eq!(no_cache(&ifo).unwrap().compression_info(), Some((8, data.len().div_ceil(8))));
+	}
+
+	#[test]
+	fn truncated_dict() {
+		let ifo = create_dict("truncated_dict", &SAMPLE_WORDS, &[]);
+		let dict_path = ifo.with_extension("dict");
+		let data = fs::read(&dict_path).unwrap();
+		fs::write(&dict_path, &data[..data.len() - 4]).unwrap();
+		let dict = no_cache(&ifo).unwrap();
+		assert!(dict.lookup("apple").unwrap().is_some());
+		// the last entry
+		let offset = data.len() - "a group of musicians".len();
+		assert!(matches!(dict.lookup("band"), Err(Error::InvalidDictBlock { word, offset: o })
+			if word == "Band" && o == offset));
+
+		// truncated dictzip
+		let mut dz = vec![];
+		crate::DictZipWriter::new(8).write(&data, &mut dz).unwrap();
+		fs::remove_file(&dict_path).unwrap();
+		fs::write(ifo.with_extension("dict.dz"), &dz[..dz.len() - 12]).unwrap();
+		let dict = no_cache(&ifo).unwrap();
+		assert!(dict.lookup("apple").unwrap().is_some());
+		assert!(matches!(dict.lookup("band"), Err(Error::InvalidDictBlock { word, offset: o })
+			if word == "Band" && o == offset));
+
+		// corrupted chunk in the middle
+		let mut corrupted = dz.clone();
+		let chunk = corrupted.len() - 40;
+		corrupted[chunk..chunk + 8].fill(0xff);
+		fs::write(ifo.with_extension("dict.dz"), &corrupted).unwrap();
+		let dict = no_cache(&ifo).unwrap();
+		let mut failed = 0;
+		for (word, _) in SAMPLE_WORDS {
+			if let Err(error) = dict.lookup(word) {
+				assert!(matches!(&error, Error::InvalidDictBlock { word: w, .. } if w == word));
+				assert!(error.to_string().starts_with(&format!("Invalid dict block of {} at offset ", word)));
+				failed += 1;
+			}
+		}
+		assert!(failed > 0);
+	}
+
+	#[test]
+	fn type_chars_with_sametypesequence() {
+		use crate::no_cache_strict;
+		let words: [(&str, &[u8]); 2] = [("apple", b"ttext\0ma round fruit\0"), ("banana", b"bt\0mfruit")];
+		let ifo = create_dict_with("type_chars", "tm", &words, &[]);
+		let text = |types: &str, text: &str| WordDefinitionSegment::new(types.to_owned(), text.as_bytes());
+
+		// lenient, parsed with the type chars
+		let dict = no_cache(&ifo).unwrap();
+		let definitions = dict.lookup("apple").unwrap().unwrap();
+		assert_eq!(definitions[0].segments, vec![text("t", "text"), text("m", "a round fruit")]);
+		let definitions = dict.lookup("banana").unwrap().unwrap();
+		assert_eq!(definitions[0].segments, vec![text("t", "bt"), text("m", "mfruit")]);
+		assert_eq!(dict.idx_warnings(), ["type chars found in data of apple with sametypesequence"]);
+		assert_eq!(crate::with_memory(&ifo).unwrap().idx_warnings(), dict.idx_warnings());
+
+		let dict = no_cache_strict(&ifo).unwrap();
+		assert!(matches!(dict.lookup("apple"), Err(Error::InvalidDictContent(word)) if word == "apple"));
+		assert!(dict.lookup("banana").unwrap().is_some());
+	}
 }
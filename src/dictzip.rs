@@ -1,8 +1,11 @@
-use std::collections::HashMap;
-use std::fs::File;
-use inflate::inflate_bytes;
-use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
-use byteorder::{LE, ReadBytesExt};
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File};
+use std::path::Path;
+use flate2::{Compress, Compression, Crc, Decompress, FlushCompress, FlushDecompress, Status};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use byteorder::{LE, ReadBytesExt, WriteBytesExt};
+use crate::buf_to_string;
 use crate::error::{Error, Result};
 
 struct DictZipHeader {
@@ -27,28 +30,88 @@ const HEADER_FLAG_COMMENT: u8 = 0b00010000;
 const GZIP_ID: u16 = 0x8B1F;
 const COMPRESSION_METHOD_DEFLATE: u8 = 0x08;
 const RA_ID: u16 = 0x4152;
+pub const DEFAULT_CACHE_CAPACITY: usize = 64;
+pub const DEFAULT_CHUNK_LENGTH: usize = 58315;
+const OS_UNIX: u8 = 3;
 
-pub struct DictZip {
-	#[allow(unused)]
-	reader: BufReader<File>,
+/// decompressed chunks, evict the least recently used one when full
+struct ChunkCache {
+	capacity: usize,
+	chunks: HashMap<usize, Vec<u8>>,
+	// least recently used first
+	order: VecDeque<usize>,
+}
+
+impl ChunkCache {
+	fn new(capacity: usize) -> Self
+	{
+		ChunkCache {
+			capacity: capacity.max(1),
+			chunks: HashMap::new(),
+			order: VecDeque::new(),
+		}
+	}
+
+	fn contains(&mut self, chunk_index: usize) -> bool
+	{
+		if !self.chunks.contains_key(&chunk_index) {
+			return false;
+		}
+		if let Some(pos) = self.order.iter().position(|&i| i == chunk_index) {
+			self.order.remove(pos);
+		}
+		self.order.push_back(chunk_index);
+		true
+	}
+
+	fn get(&self, chunk_index: usize) -> Option<&Vec<u8>>
+	{
+		self.chunks.get(&chunk_index)
+	}
+
+	fn insert(&mut self, chunk_index: usize, chunk: Vec<u8>)
+	{
+		while self.chunks.len() >= self.capacity {
+			if let Some(lru) = self.order.pop_front() {
+				self.chunks.remove(&lru);
+			} else {
+				break;
+			}
+		}
+		self.chunks.insert(chunk_index, chunk);
+		self.order.push_back(chunk_index);
+	}
+}
+
+pub struct DictZip<R = File> {
+	reader: BufReader<R>,
 
 	#[allow(unused)]
 	header: DictZipHeader,
 	chunk_length: usize,
 	chunks: Vec<u16>,
-	data_offset: u64,
-	cache: HashMap<usize, Vec<u8>>,
+	// file offset of every chunk
+	chunk_offsets: Vec<u64>,
+	// file offset of the crc32 and isize trailer
+	trailer_offset: u64,
+	// uncompressed length, last chunk shorter than chunk length
+	data_size: usize,
+	cache: ChunkCache,
 
-	#[allow(unused)]
 	filename: Option<String>,
-	#[allow(unused)]
 	comment: Option<String>,
 	#[allow(unused)]
 	crc: Option<u16>,
 }
 
-impl DictZip {
-	pub fn new(mut reader: BufReader<File>) -> Result<DictZip> {
+impl<R: Read + Seek> DictZip<R> {
+	#[inline]
+	pub fn new(reader: BufReader<R>) -> Result<DictZip<R>> {
+		Self::with_cache_capacity(reader, DEFAULT_CACHE_CAPACITY)
+	}
+
+	/// keep at most capacity decompressed chunks in memory
+	pub fn with_cache_capacity(mut reader: BufReader<R>, capacity: usize) -> Result<DictZip<R>> {
 		let header = read_header(&mut reader).map_err(|_| Error::InvalidDict)?;
 		if header.id != GZIP_ID {
 			return Err(Error::FailedParseDictHeader("header id"));
@@ -62,6 +125,9 @@ impl DictZip {
 
 		let (chunk_length, chunks) = read_chunks(&mut reader).map_err(|_| Error::InvalidDict)?
 			.ok_or(Error::FailedParseDictHeader("header with no extra ra field"))?;
+		if chunk_length == 0 {
+			return Err(Error::FailedParseDictHeader("zero chunk length"));
+		}
 
 		let filename = if header.flags & HEADER_FLAG_NAME == 0 {
 			None
@@ -79,13 +145,26 @@ impl DictZip {
 			Some(reader.read_u16::<LE>()?)
 		};
 		let data_offset = reader.stream_position()?;
-		let cache = HashMap::new();
+		let mut chunk_offsets = Vec::with_capacity(chunks.len());
+		let mut offset = data_offset;
+		for chunk in &chunks {
+			chunk_offsets.push(offset);
+			offset += *chunk as u64;
+		}
+		let trailer_offset = offset;
+		let full_size = chunk_length * chunks.len();
+		let data_size = reader.seek(SeekFrom::Start(trailer_offset + 4)).ok()
+			.and_then(|_| reader.read_u32::<LE>().ok())
+			.map_or(full_size, |isize| data_size(full_size, chunk_length, isize));
+		let cache = ChunkCache::new(capacity);
 		let dict = DictZip {
 			reader,
 			header,
 			chunk_length,
 			chunks,
-			data_offset,
+			chunk_offsets,
+			trailer_offset,
+			data_size,
 			cache,
 			filename,
 			comment,
@@ -94,46 +173,336 @@ impl DictZip {
 		Ok(dict)
 	}
 
+	/// original file name in the gzip header
+	#[inline]
+	pub fn filename(&self) -> Option<&str> {
+		self.filename.as_deref()
+	}
+
+	/// comment in the gzip header
+	#[inline]
+	pub fn comment(&self) -> Option<&str> {
+		self.comment.as_deref()
+	}
+
+	/// uncompressed size of every chunk
+	#[inline]
+	pub fn chunk_length(&self) -> usize {
+		self.chunk_length
+	}
+
+	#[inline]
+	pub fn chunk_count(&self) -> usize {
+		self.chunks.len()
+	}
+
+	/// uncompressed length by the isize of the gzip trailer,
+	/// every chunk counted as full if the trailer is missing
+	#[inline]
+	pub fn data_size(&self) -> usize {
+		self.data_size
+	}
+
+	/// same as with_cache_capacity, but decompress all chunks and check
+	/// the crc32 and isize trailer of the gzip stream
+	pub fn new_verified(reader: BufReader<R>, capacity: usize) -> Result<DictZip<R>> {
+		let mut dict = Self::with_cache_capacity(reader, capacity)?;
+		dict.verify()?;
+		Ok(dict)
+	}
+
+	fn verify(&mut self) -> Result<()> {
+		let mut crc = Crc::new();
+		for i in 0..self.chunks.len() {
+			let chunk = self.inflate_chunk(i).ok_or(Error::InvalidDict)?;
+			crc.update(&chunk);
+		}
+		self.reader.seek(SeekFrom::Start(self.trailer_offset))?;
+		let expected_crc = self.reader.read_u32::<LE>().map_err(|_| Error::InvalidDict)?;
+		let expected_size = self.reader.read_u32::<LE>().map_err(|_| Error::InvalidDict)?;
+		if crc.sum() != expected_crc || crc.amount() != expected_size {
+			return Err(Error::InvalidDict);
+		}
+		Ok(())
+	}
+
+	/// all chunks decompressed and concatenated, bypass the chunk cache
+	pub fn read_all(&mut self) -> Result<Vec<u8>> {
+		let mut buf = Vec::with_capacity(self.chunks.len() * self.chunk_length);
+		for i in 0..self.chunks.len() {
+			let chunk = self.inflate_chunk(i).ok_or(Error::InvalidDict)?;
+			buf.extend_from_slice(&chunk);
+		}
+		Ok(buf)
+	}
+
 	/// return combined chunks contains from offset to offset + size
-	/// and the offset for the segment in this data
+	/// and the offset for the segment in this data,
+	/// None for empty or out of range segment, also past the data
+	/// of a last chunk shorter than chunk length
 	pub fn get_segment_data(&mut self, offset: usize, size: usize) -> Option<(Vec<u8>, usize)>
 	{
+		if size == 0 {
+			return None;
+		}
 		let chunk_count = self.chunks.len();
 		let first_chunk = offset / self.chunk_length;
-		if first_chunk > chunk_count {
+		if first_chunk >= chunk_count {
 			return None;
 		}
-		let last_chunk = (offset + size - 1) / self.chunk_length;
+		let end = offset.checked_add(size)?;
+		let last_chunk = (end - 1) / self.chunk_length;
 		if last_chunk >= chunk_count {
 			return None;
 		}
 
-		let mut buf = vec![];
+		// appended into one buffer, chunks copied once
+		let mut buf = Vec::with_capacity(self.chunk_length * (last_chunk - first_chunk + 1));
 		let chunk_offset = offset - first_chunk * self.chunk_length;
 		for i in first_chunk..=last_chunk {
 			let chunk = self.read_chunk(i)?;
-			buf = [buf.as_slice(), chunk.as_slice()].concat();
+			buf.extend_from_slice(chunk);
+		}
+		if buf.len() < chunk_offset + size {
+			return None;
 		}
 		Some((buf, chunk_offset))
 	}
 
+	/// return text from offset to offset + size
+	#[allow(unused)]
+	pub fn get_text(&mut self, offset: usize, size: usize) -> Option<Cow<'_, str>>
+	{
+		let (buf, offset) = self.get_segment_data(offset, size)?;
+		// the last chunk may be shorter than chunk length
+		let text = buf.get(offset..offset + size)?;
+		Some(Cow::Owned(buf_to_string(text)))
+	}
+
 	fn read_chunk(&mut self, chunk_index: usize) -> Option<&Vec<u8>> {
-		if !self.cache.contains_key(&chunk_index) {
-			let mut offset = self.data_offset;
-			for i in 0..chunk_index {
-				offset += *self.chunks.get(i)? as u64;
+		if !self.cache.contains(chunk_index) {
+			let text_buf = self.inflate_chunk(chunk_index)?;
+			self.cache.insert(chunk_index, text_buf);
+		}
+
+		self.cache.get(chunk_index)
+	}
+
+	fn inflate_chunk(&mut self, chunk_index: usize) -> Option<Vec<u8>> {
+		let offset = *self.chunk_offsets.get(chunk_index)?;
+		self.reader.seek(SeekFrom::Start(offset)).ok()?;
+		let length = *self.chunks.get(chunk_index)? as usize;
+		let mut buf = vec![0; length];
+		self.reader.read_exact(&mut buf).ok()?;
+
+		inflate_raw(&buf, self.chunk_length)
+	}
+}
+
+/// isize is the length modulo 2^32, with the last chunk
+/// of 1 to chunk length bytes the length is within one chunk of full
+fn data_size(full_size: usize, chunk_length: usize, isize: u32) -> usize
+{
+	let shortage = (full_size as u32).wrapping_sub(isize) as usize;
+	if shortage < chunk_length {
+		full_size - shortage
+	} else {
+		full_size
+	}
+}
+
+/// inflate a raw deflate chunk, chunks other than the last
+/// end with a full flush instead of the final block
+fn inflate_raw(chunk: &[u8], capacity: usize) -> Option<Vec<u8>>
+{
+	let mut decompress = Decompress::new(false);
+	let mut buf = Vec::with_capacity(capacity);
+	loop {
+		if buf.len() == buf.capacity() {
+			buf.reserve(1024);
+		}
+		let consumed = decompress.total_in() as usize;
+		let status = decompress.decompress_vec(&chunk[consumed..], &mut buf, FlushDecompress::Sync)
+			.ok()?;
+		let consumed = decompress.total_in() as usize;
+		if status == Status::StreamEnd || consumed == chunk.len() && buf.len() < buf.capacity() {
+			return Some(buf);
+		}
+		if status == Status::BufError && buf.len() < buf.capacity() {
+			// no progress with room left, truncated chunk
+			return None;
+		}
+	}
+}
+
+/// decompress the dictzip into a plain dict, so opened without inflating,
+/// output removed if its length not the isize of the gzip trailer
+pub fn decompress_dict(dz_path: impl AsRef<Path>, out_path: impl AsRef<Path>) -> Result<()>
+{
+	let file = File::open(dz_path).map_err(|e| Error::FailedOpenFile("dict", e))?;
+	let mut dz = DictZip::new(BufReader::new(file))?;
+	let out_path = out_path.as_ref();
+	let mut out = File::create(out_path).map_err(|e| Error::FailedWriteFile("dict", e))?;
+	let mut length = 0usize;
+	let written = (0..dz.chunks.len()).try_for_each(|i| {
+		let chunk = dz.inflate_chunk(i).ok_or(Error::InvalidDict)?;
+		length += chunk.len();
+		out.write_all(&chunk).map_err(|e| Error::FailedWriteFile("dict", e))
+	}).and_then(|_| {
+		dz.reader.seek(SeekFrom::Start(dz.trailer_offset + 4))?;
+		let expected = dz.reader.read_u32::<LE>().map_err(|_| Error::InvalidDict)?;
+		// isize is the length modulo 2^32
+		if length as u32 != expected {
+			return Err(Error::InvalidDict);
+		}
+		out.flush().map_err(|e| Error::FailedWriteFile("dict", e))
+	});
+	if written.is_err() {
+		drop(out);
+		let _ = fs::remove_file(out_path);
+	}
+	written
+}
+
+/// compress dict data into dictzip format, every chunk can be inflated alone
+pub struct DictZipWriter {
+	chunk_length: usize,
+	filename: Option<String>,
+	comment: Option<String>,
+}
+
+impl Default for DictZipWriter {
+	#[inline]
+	fn default() -> Self
+	{
+		Self::new(DEFAULT_CHUNK_LENGTH)
+	}
+}
+
+impl DictZipWriter {
+	#[inline]
+	pub fn new(chunk_length: usize) -> Self
+	{
+		DictZipWriter { chunk_length, filename: None, comment: None }
+	}
+
+	/// original file name stored in the gzip header
+	#[inline]
+	pub fn filename(mut self, filename: impl Into<String>) -> Self
+	{
+		self.filename = Some(filename.into());
+		self
+	}
+
+	/// comment stored in the gzip header
+	#[inline]
+	pub fn comment(mut self, comment: impl Into<String>) -> Self
+	{
+		self.comment = Some(comment.into());
+		self
+	}
+
+	pub fn write(&self, data: &[u8], writer: &mut impl Write) -> Result<()>
+	{
+		let chunk_count = data.len().div_ceil(self.chunk_length.max(1));
+		let ra_size = 6 + chunk_count * 2;
+		// extra field length should fit in u16 with the subfield header
+		if self.chunk_length == 0 || self.chunk_length > u16::MAX as usize
+			|| ra_size + 4 > u16::MAX as usize {
+			return Err(invalid_input("too many chunks or invalid chunk length"));
+		}
+		if [&self.filename, &self.comment].iter().any(|str| str.as_ref().is_some_and(|str| str.contains('\0'))) {
+			return Err(invalid_input("null in filename or comment"));
+		}
+
+		let mut compress = Compress::new(Compression::best(), false);
+		let mut chunks = Vec::with_capacity(chunk_count);
+		let mut iter = data.chunks(self.chunk_length).peekable();
+		while let Some(chunk) = iter.next() {
+			let flush = if iter.peek().is_some() {
+				FlushCompress::Full
+			} else {
+				FlushCompress::Finish
+			};
+			let compressed = deflate_chunk(&mut compress, chunk, flush)?;
+			if compressed.len() > u16::MAX as usize {
+				return Err(invalid_input("compressed chunk too large"));
 			}
-			self.reader.seek(SeekFrom::Start(offset)).ok()?;
-			let length = *self.chunks.get(chunk_index)? as usize;
-			let mut buf = vec![0; length];
-			self.reader.read_exact(&mut buf).ok()?;
+			chunks.push(compressed);
+		}
+		let mut crc = Crc::new();
+		crc.update(data);
 
-			let text_buf = inflate_bytes(&buf).ok()?;
-			self.cache.insert(chunk_index, text_buf);
+		write_dictzip(writer, self.chunk_length, &chunks, crc.sum(), data.len() as u32,
+			self.filename.as_deref(), self.comment.as_deref())
+			.map_err(|e| Error::FailedWriteFile("dict", e))
+	}
+}
+
+fn deflate_chunk(compress: &mut Compress, chunk: &[u8], flush: FlushCompress) -> Result<Vec<u8>>
+{
+	let start = compress.total_in();
+	let mut buf = Vec::with_capacity(chunk.len() + 64);
+	loop {
+		if buf.len() == buf.capacity() {
+			buf.reserve(1024);
+		}
+		let consumed = (compress.total_in() - start) as usize;
+		let status = compress.compress_vec(&chunk[consumed..], &mut buf, flush)
+			.map_err(|e| invalid_input(&e.to_string()))?;
+		let consumed = (compress.total_in() - start) as usize;
+		let done = match flush {
+			FlushCompress::Finish => status == Status::StreamEnd,
+			_ => consumed == chunk.len() && buf.len() < buf.capacity(),
+		};
+		if done {
+			return Ok(buf);
 		}
+	}
+}
 
-		self.cache.get(&chunk_index)
+fn write_dictzip(writer: &mut impl Write, chunk_length: usize, chunks: &[Vec<u8>],
+	crc: u32, size: u32, filename: Option<&str>, comment: Option<&str>) -> std::io::Result<()>
+{
+	let ra_size = 6 + chunks.len() * 2;
+	let mut flags = HEADER_FLAG_EXTRA;
+	if filename.is_some() {
+		flags |= HEADER_FLAG_NAME;
+	}
+	if comment.is_some() {
+		flags |= HEADER_FLAG_COMMENT;
+	}
+	writer.write_u16::<LE>(GZIP_ID)?;
+	writer.write_u8(COMPRESSION_METHOD_DEFLATE)?;
+	writer.write_u8(flags)?;
+	writer.write_u32::<LE>(0)?;
+	writer.write_u8(0)?;
+	writer.write_u8(OS_UNIX)?;
+	writer.write_u16::<LE>(ra_size as u16 + 4)?;
+	writer.write_u16::<LE>(RA_ID)?;
+	writer.write_u16::<LE>(ra_size as u16)?;
+	writer.write_u16::<LE>(1)?;
+	writer.write_u16::<LE>(chunk_length as u16)?;
+	writer.write_u16::<LE>(chunks.len() as u16)?;
+	for chunk in chunks {
+		writer.write_u16::<LE>(chunk.len() as u16)?;
+	}
+	for str in [filename, comment].into_iter().flatten() {
+		writer.write_all(str.as_bytes())?;
+		writer.write_u8(0)?;
 	}
+	for chunk in chunks {
+		writer.write_all(chunk)?;
+	}
+	writer.write_u32::<LE>(crc)?;
+	writer.write_u32::<LE>(size)?;
+	writer.flush()
+}
+
+#[inline]
+fn invalid_input(msg: &str) -> Error
+{
+	Error::FailedWriteFile("dict", std::io::Error::new(std::io::ErrorKind::InvalidInput, msg))
 }
 
 #[inline]
@@ -209,4 +578,351 @@ fn read_header(reader: &mut impl BufRead) -> Result<DictZipHeader> {
 		os,
 	};
 	Ok(header)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+	use std::fs;
+	use std::fs::{File, OpenOptions};
+	use std::io::{BufReader, Read};
+	use std::path::PathBuf;
+	use flate2::read::GzDecoder;
+	use crate::dictzip::{DEFAULT_CACHE_CAPACITY, DictZip, DictZipWriter, decompress_dict, inflate_raw};
+	use crate::error::Error;
+
+	pub(crate) fn write_dictzip(path: &PathBuf, data: &[u8], chunk_length: usize)
+	{
+		let mut file = File::create(path).unwrap();
+		DictZipWriter::new(chunk_length).write(data, &mut file).unwrap();
+	}
+
+	pub(crate) fn sample_data(len: usize) -> Vec<u8>
+	{
+		(0..len).map(|i| b'a' + (i * 7 % 26) as u8).collect()
+	}
+
+	pub(crate) fn open(path: &PathBuf) -> DictZip
+	{
+		let file = OpenOptions::new().read(true).open(path).unwrap();
+		DictZip::new(BufReader::new(file)).unwrap()
+	}
+
+	pub(crate) fn temp_file(name: &str) -> PathBuf
+	{
+		std::env::temp_dir().join(format!("stardict-{}-{}", std::process::id(), name))
+	}
+
+	#[test]
+	fn segment_data() {
+		let data = sample_data(10000);
+		let path = temp_file("segment_data.dict.dz");
+		write_dictzip(&path, &data, 1000);
+		let mut dz = open(&path);
+
+		let (buf, offset) = dz.get_segment_data(1500, 2000).unwrap();
+		assert_eq!(offset, 500);
+		assert_eq!(&buf[offset..offset + 2000], &data[1500..3500]);
+
+		let text = dz.get_text(9990, 10).unwrap();
+		assert_eq!(text.as_bytes(), &data[9990..]);
+		assert!(dz.get_segment_data(9990, 11).is_none());
+	}
+
+	#[test]
+	fn segment_many_chunks() {
+		let data = sample_data(200_000);
+		let path = temp_file("segment_many_chunks.dict.dz");
+		write_dictzip(&path, &data, 64);
+		let mut dz = open(&path);
+
+		// into the first byte of the last chunk
+		let (buf, offset) = dz.get_segment_data(10, 64 * 1000 - 9).unwrap();
+		assert_eq!(buf.len(), 64 * 1001);
+		assert_eq!(&buf[offset..offset + 64 * 1000 - 9], &data[10..64 * 1000 + 1]);
+		// whole data, more chunks than the cache capacity
+		let (buf, offset) = dz.get_segment_data(0, data.len()).unwrap();
+		assert_eq!(offset, 0);
+		assert_eq!(buf, data);
+		assert_eq!(dz.get_text(100_001, 99_999).unwrap().as_bytes(), &data[100_001..]);
+	}
+
+	#[test]
+	fn segment_bounds() {
+		let data = sample_data(10500);
+		let path = temp_file("segment_bounds.dict.dz");
+		write_dictzip(&path, &data, 1000);
+		let mut dz = open(&path);
+
+		// zero size
+		assert!(dz.get_segment_data(100, 0).is_none());
+		assert!(dz.get_text(0, 0).is_none());
+		// offset past end
+		assert!(dz.get_segment_data(11000, 10).is_none());
+		assert!(dz.get_text(100_000, 1).is_none());
+		// in the last chunk, but past the data
+		assert!(dz.get_text(10400, 200).is_none());
+		assert_eq!(dz.get_text(10400, 100).unwrap().as_bytes(), &data[10400..]);
+		// overflow
+		assert!(dz.get_segment_data(usize::MAX, 2).is_none());
+		assert!(dz.get_segment_data(10, usize::MAX).is_none());
+		assert!(dz.get_text(5, usize::MAX - 2).is_none());
+	}
+
+	#[test]
+	fn segment_last_byte() {
+		// last chunk full
+		let data = sample_data(10000);
+		let path = temp_file("segment_last_byte.dict.dz");
+		write_dictzip(&path, &data, 1000);
+		let mut dz = open(&path);
+		assert_eq!(dz.get_text(9000, 1000).unwrap().as_bytes(), &data[9000..]);
+		assert!(dz.get_segment_data(9000, 1001).is_none());
+		// larger than a chunk, to the last byte
+		let (buf, offset) = dz.get_segment_data(500, 9500).unwrap();
+		assert_eq!(&buf[offset..], &data[500..]);
+		assert!(dz.get_segment_data(500, 9501).is_none());
+		assert!(dz.get_segment_data(10000, 1).is_none());
+
+		// last chunk shorter than chunk length
+		let data = sample_data(10500);
+		write_dictzip(&path, &data, 1000);
+		let mut dz = open(&path);
+		let (buf, offset) = dz.get_segment_data(8800, 1700).unwrap();
+		assert_eq!(&buf[offset..offset + 1700], &data[8800..]);
+		assert!(dz.get_segment_data(8800, 1701).is_none());
+		assert!(dz.get_segment_data(10499, 2).is_none());
+	}
+
+	#[test]
+	fn decompress() {
+		let data = sample_data(10500);
+		let path = temp_file("decompress.dict.dz");
+		write_dictzip(&path, &data, 1000);
+		let out = temp_file("decompress.dict");
+		decompress_dict(&path, &out).unwrap();
+		assert_eq!(fs::read(&out).unwrap(), data);
+
+		// isize of another length
+		let mut dz = fs::read(&path).unwrap();
+		let len = dz.len();
+		dz[len - 4..].copy_from_slice(&10501u32.to_le_bytes());
+		fs::write(&path, dz).unwrap();
+		assert!(matches!(decompress_dict(&path, &out), Err(Error::InvalidDict)));
+		assert!(!out.exists());
+	}
+
+	#[test]
+	fn inflate_chunk() {
+		// chunk ends with a full flush, as written by dictzip
+		let chunk = [
+			0x2a, 0x2e, 0x49, 0x2c, 0x4a, 0xc9, 0x4c, 0x2e, 0x51, 0x00, 0x11, 0x55, 0x99, 0x05, 0x0a, 0xc9,
+			0x19, 0xa5, 0x79, 0xd9, 0x3a, 0x0a, 0xc5, 0x58, 0xc5, 0x01, 0x00, 0x00, 0x00, 0xff, 0xff,
+		];
+		let expected = b"stardict dictzip chunk, stardict dictzip chunk";
+		assert_eq!(inflate_raw(&chunk, 1).unwrap(), expected);
+		assert_eq!(inflate_raw(&chunk, 1000).unwrap(), expected);
+
+		// final chunk with the last block
+		let data = sample_data(5000);
+		let path = temp_file("inflate_chunk.dict.dz");
+		write_dictzip(&path, &data, 3000);
+		let mut dz = open(&path);
+		assert_eq!(dz.inflate_chunk(0).unwrap(), &data[..3000]);
+		assert_eq!(dz.inflate_chunk(1).unwrap(), &data[3000..]);
+		assert!(dz.inflate_chunk(2).is_none());
+		assert!(inflate_raw(&[0xff, 0xff, 0xff], 100).is_none());
+	}
+
+	#[test]
+	fn read_all() {
+		let data = sample_data(10500);
+		let path = temp_file("read_all.dict.dz");
+		write_dictzip(&path, &data, 1000);
+		let mut dz = open(&path);
+		let chunks_len: usize = (0..dz.chunks.len())
+			.map(|i| dz.inflate_chunk(i).unwrap().len())
+			.sum();
+		let all = dz.read_all().unwrap();
+		assert_eq!(all.len(), chunks_len);
+		assert_eq!(all, data);
+		assert!(dz.cache.chunks.is_empty());
+	}
+
+	#[test]
+	fn header_strings() {
+		let data = sample_data(3000);
+		let path = temp_file("header_strings.dict.dz");
+		let mut file = File::create(&path).unwrap();
+		DictZipWriter::new(1000)
+			.filename("sample.dict")
+			.comment("built by test")
+			.write(&data, &mut file)
+			.unwrap();
+		drop(file);
+		let mut dz = open(&path);
+		assert_eq!(dz.filename(), Some("sample.dict"));
+		assert_eq!(dz.comment(), Some("built by test"));
+		assert_eq!(dz.chunk_length(), 1000);
+		assert_eq!(dz.chunk_count(), 3);
+		assert_eq!(dz.read_all().unwrap(), data);
+		let mut decoded = vec![];
+		GzDecoder::new(File::open(&path).unwrap()).read_to_end(&mut decoded).unwrap();
+		assert_eq!(decoded, data);
+
+		write_dictzip(&path, &data, 1000);
+		let dz = open(&path);
+		assert_eq!(dz.filename(), None);
+		assert_eq!(dz.comment(), None);
+
+		assert!(DictZipWriter::new(1000).filename("a\0b").write(&data, &mut vec![]).is_err());
+	}
+
+	#[test]
+	fn data_size() {
+		for size in [0, 1, 999, 1000, 10_001] {
+			let data = sample_data(size);
+			let path = temp_file(&format!("data_size_{}.dict.dz", size));
+			write_dictzip(&path, &data, 1000);
+			assert_eq!(open(&path).data_size(), data.len());
+		}
+
+		// without the trailer every chunk counted as full
+		let path = temp_file("data_size_10001.dict.dz");
+		let bytes = fs::read(&path).unwrap();
+		fs::write(&path, &bytes[..bytes.len() - 8]).unwrap();
+		assert_eq!(open(&path).data_size(), 11_000);
+
+		// isize wrapped past 4 GiB
+		let full_size = 5 << 30;
+		assert_eq!(super::data_size(full_size, 60_000, (full_size - 100) as u32), full_size - 100);
+		assert_eq!(super::data_size(full_size, 60_000, 7), full_size);
+	}
+
+	#[test]
+	fn zero_chunk_length() {
+		let path = temp_file("zero_chunk_length.dict.dz");
+		write_dictzip(&path, &sample_data(3000), 1000);
+		let mut bytes = fs::read(&path).unwrap();
+		// chunk length of the ra field, right after the gzip header, extra length,
+		// ra id, ra length and version
+		assert_eq!(&bytes[12..14], b"RA");
+		assert_eq!(u16::from_le_bytes([bytes[18], bytes[19]]), 1000);
+		bytes[18..20].fill(0);
+		fs::write(&path, bytes).unwrap();
+		let file = File::open(&path).unwrap();
+		assert!(matches!(DictZip::new(BufReader::new(file)),
+			Err(Error::FailedParseDictHeader("zero chunk length"))));
+	}
+
+	#[test]
+	fn cache_capacity() {
+		let data = sample_data(100_000);
+		let path = temp_file("cache_capacity.dict.dz");
+		write_dictzip(&path, &data, 500);
+		assert_eq!(open(&path).cache.capacity, DEFAULT_CACHE_CAPACITY);
+
+		let file = OpenOptions::new().read(true).open(&path).unwrap();
+		let mut dz = DictZip::with_cache_capacity(BufReader::new(file), 8).unwrap();
+		for i in 0..500 {
+			let offset = i * 7919 % 99_000;
+			let (buf, start) = dz.get_segment_data(offset, 1000).unwrap();
+			assert_eq!(&buf[start..start + 1000], &data[offset..offset + 1000]);
+			assert!(dz.cache.chunks.len() <= 8);
+		}
+
+		// recently used chunk survives eviction
+		dz.get_segment_data(0, 10).unwrap();
+		for i in 1..20 {
+			dz.get_segment_data(i * 1000, 10).unwrap();
+			dz.get_segment_data(0, 10).unwrap();
+		}
+		assert!(dz.cache.get(0).is_some());
+	}
+
+	#[test]
+	fn chunk_offsets() {
+		let data = sample_data(200_000);
+		let path = temp_file("chunk_offsets.dict.dz");
+		write_dictzip(&path, &data, 100);
+		let mut dz = open(&path);
+		assert_eq!(dz.chunks.len(), 2000);
+
+		let last = dz.chunks.len() - 1;
+		let mut offset = dz.chunk_offsets[0];
+		for i in 0..last {
+			offset += dz.chunks[i] as u64;
+		}
+		assert_eq!(dz.chunk_offsets[last], offset);
+		let (buf, start) = dz.get_segment_data(199_900, 100).unwrap();
+		assert_eq!(start, 0);
+		assert_eq!(&buf[..], &data[199_900..]);
+	}
+
+	#[test]
+	fn verify() {
+		let data = sample_data(10000);
+		let path = temp_file("verify.dict.dz");
+		write_dictzip(&path, &data, 1000);
+		let file = OpenOptions::new().read(true).open(&path).unwrap();
+		assert!(DictZip::new_verified(BufReader::new(file), DEFAULT_CACHE_CAPACITY).is_ok());
+
+		let bytes = fs::read(&path).unwrap();
+		let len = bytes.len();
+		// corrupted crc32
+		let mut corrupted = bytes.clone();
+		corrupted[len - 8] ^= 0xff;
+		let corrupted_path = temp_file("verify_crc.dict.dz");
+		fs::write(&corrupted_path, &corrupted).unwrap();
+		let file = OpenOptions::new().read(true).open(&corrupted_path).unwrap();
+		assert!(matches!(DictZip::new_verified(BufReader::new(file), DEFAULT_CACHE_CAPACITY), Err(Error::InvalidDict)));
+
+		// corrupted isize
+		let mut corrupted = bytes.clone();
+		corrupted[len - 1] ^= 0xff;
+		fs::write(&corrupted_path, &corrupted).unwrap();
+		let file = OpenOptions::new().read(true).open(&corrupted_path).unwrap();
+		assert!(matches!(DictZip::new_verified(BufReader::new(file), DEFAULT_CACHE_CAPACITY), Err(Error::InvalidDict)));
+
+		// truncated
+		fs::write(&corrupted_path, &bytes[..len - 20]).unwrap();
+		let file = OpenOptions::new().read(true).open(&corrupted_path).unwrap();
+		assert!(matches!(DictZip::new_verified(BufReader::new(file), DEFAULT_CACHE_CAPACITY), Err(Error::InvalidDict)));
+		// not verified open still works
+		let file = OpenOptions::new().read(true).open(&corrupted_path).unwrap();
+		assert!(DictZip::new(BufReader::new(file)).is_ok());
+	}
+
+	#[test]
+	fn writer() {
+		// pseudo random data
+		let mut seed = 12345u32;
+		let data: Vec<u8> = (0..300_000).map(|_| {
+			seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+			(seed >> 16) as u8 % 16
+		}).collect();
+		let path = temp_file("writer.dict.dz");
+		let mut file = File::create(&path).unwrap();
+		DictZipWriter::default().write(&data, &mut file).unwrap();
+		drop(file);
+
+		let mut dz = open(&path);
+		assert_eq!(dz.chunk_length, 58315);
+		assert_eq!(dz.chunks.len(), 6);
+		for i in 0..dz.chunks.len() {
+			let offset = i * dz.chunk_length;
+			let size = dz.chunk_length.min(data.len() - offset);
+			let (buf, start) = dz.get_segment_data(offset, size).unwrap();
+			assert_eq!(&buf[start..start + size], &data[offset..offset + size]);
+		}
+		let file = OpenOptions::new().read(true).open(&path).unwrap();
+		assert!(DictZip::new_verified(BufReader::new(file), DEFAULT_CACHE_CAPACITY).is_ok());
+
+		// still a valid gzip file
+		let mut decoded = vec![];
+		GzDecoder::new(File::open(&path).unwrap()).read_to_end(&mut decoded).unwrap();
+		assert_eq!(decoded, data);
+
+		assert!(DictZipWriter::new(0).write(&data, &mut vec![]).is_err());
+		assert!(DictZipWriter::new(1).write(&data, &mut vec![]).is_err());
+	}
+}
@@ -0,0 +1,208 @@
+use std::borrow::Cow;
+use crate::buf_to_string;
+
+const UTF8_BOM: &[u8] = b"\xef\xbb\xbf";
+
+/// charset of headwords and text segments, for legacy dictionaries predate utf-8
+#[derive(Clone, Copy, Debug, Default)]
+pub enum Encoding {
+	/// invalid sequences dropped, leading BOM skipped
+	#[default]
+	Utf8,
+	/// ISO-8859-1, every byte a char
+	Latin1,
+	/// decoder of the caller for any other charset, e.g. GBK or Big5 from a charset crate,
+	/// this crate has no charset tables and decodes only utf-8 and latin1 itself
+	Custom(fn(&[u8]) -> String),
+}
+
+/// invalid utf-8 in text segments of definitions
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LossyPolicy {
+	/// dropped, as well as U+FFFD in the text
+	#[default]
+	Strip,
+	/// replaced with U+FFFD
+	Keep,
+	/// InvalidDictContent of the word returned
+	Error,
+}
+
+impl Encoding {
+	/// by charset name, case insensitive, utf-8 and latin1 only,
+	/// Encoding::Custom for others
+	pub fn from_name(name: &str) -> Option<Encoding>
+	{
+		match name.to_ascii_lowercase().as_str() {
+			"utf-8" | "utf8" => Some(Encoding::Utf8),
+			"latin1" | "latin-1" | "iso-8859-1" | "iso8859-1" => Some(Encoding::Latin1),
+			_ => None,
+		}
+	}
+
+	/// name kept by caches to rebuild them for another encoding,
+	/// None for Custom decoders can not be told apart
+	#[cfg(any(feature = "sled", feature = "sqlite"))]
+	pub(crate) fn id(&self) -> Option<&'static str>
+	{
+		match self {
+			Encoding::Utf8 => Some("utf-8"),
+			Encoding::Latin1 => Some("latin1"),
+			Encoding::Custom(_) => None,
+		}
+	}
+
+	pub fn decode(&self, buf: &[u8]) -> String
+	{
+		match self {
+			Encoding::Utf8 => buf_to_string(skip_bom(buf)),
+			Encoding::Latin1 => buf.iter().map(|&b| b as char).collect(),
+			Encoding::Custom(decode) => decode(buf),
+		}
+	}
+
+	/// None for invalid utf-8 with LossyPolicy::Error,
+	/// policy not used by other encodings
+	pub(crate) fn decode_with(&self, buf: &[u8], lossy: LossyPolicy) -> Option<String>
+	{
+		match (self, lossy) {
+			(Encoding::Utf8, LossyPolicy::Keep) => Some(String::from_utf8_lossy(skip_bom(buf)).into_owned()),
+			(Encoding::Utf8, LossyPolicy::Error) => std::str::from_utf8(skip_bom(buf)).ok().map(str::to_owned),
+			_ => Some(self.decode(buf)),
+		}
+	}
+
+	/// same as decode_with, borrowed when buf is valid utf-8 kept as is by the policy
+	pub(crate) fn decode_cow<'a>(&self, buf: &'a [u8], lossy: LossyPolicy) -> Option<Cow<'a, str>>
+	{
+		match (self, std::str::from_utf8(skip_bom(buf))) {
+			// U+FFFD in the text dropped with Strip
+			(Encoding::Utf8, Ok(text)) if lossy != LossyPolicy::Strip || !text.contains('\u{fffd}') =>
+				Some(Cow::Borrowed(text)),
+			_ => self.decode_with(buf, lossy).map(Cow::Owned),
+		}
+	}
+}
+
+#[inline]
+fn skip_bom(buf: &[u8]) -> &[u8]
+{
+	buf.strip_prefix(UTF8_BOM).unwrap_or(buf)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::borrow::Cow;
+	use std::fs;
+	use crate::error::Error;
+	use crate::{no_cache, Encoding, LossyPolicy, StarDict};
+	use crate::tests::{create_dict, create_dict_with};
+
+	#[test]
+	fn decode() {
+		assert_eq!(Encoding::Utf8.decode("café".as_bytes()), "café");
+		assert_eq!(Encoding::Utf8.decode(b"caf\xe9"), "caf");
+		assert_eq!(Encoding::Latin1.decode(b"caf\xe9"), "café");
+		assert_eq!(Encoding::Custom(|buf| format!("{}", buf.len())).decode(b"abc"), "3");
+	}
+
+	#[test]
+	fn bom() {
+		let buf = "\u{feff}café".as_bytes();
+		assert_eq!(Encoding::Utf8.decode(buf), "café");
+		for lossy in [LossyPolicy::Strip, LossyPolicy::Keep, LossyPolicy::Error] {
+			assert_eq!(Encoding::Utf8.decode_with(buf, lossy).unwrap(), "café");
+			assert!(matches!(Encoding::Utf8.decode_cow(buf, lossy), Some(Cow::Borrowed("café"))));
+		}
+		// only leading
+		assert_eq!(Encoding::Utf8.decode("a\u{feff}".as_bytes()), "a\u{feff}");
+		assert_eq!(Encoding::Latin1.decode(b"\xef\xbb\xbfa").chars().count(), 4);
+	}
+
+	#[test]
+	fn from_name() {
+		assert!(matches!(Encoding::from_name("UTF-8"), Some(Encoding::Utf8)));
+		assert!(matches!(Encoding::from_name("ISO-8859-1"), Some(Encoding::Latin1)));
+		assert!(Encoding::from_name("gbk").is_none());
+	}
+
+	#[test]
+	fn lossy_policy() {
+		let buf = b"caf\xe9 \xef\xbf\xbd ok";
+		assert_eq!(Encoding::Utf8.decode_with(buf, LossyPolicy::Strip).unwrap(), "caf  ok");
+		assert_eq!(Encoding::Utf8.decode_with(buf, LossyPolicy::Keep).unwrap(), "caf\u{fffd} \u{fffd} ok");
+		assert_eq!(Encoding::Utf8.decode_with(buf, LossyPolicy::Error), None);
+		assert_eq!(Encoding::Utf8.decode_with("café".as_bytes(), LossyPolicy::Error).unwrap(), "café");
+		assert_eq!(Encoding::Latin1.decode_with(buf, LossyPolicy::Error).unwrap().chars().count(), buf.len());
+	}
+
+	#[test]
+	fn decode_cow() {
+		assert!(matches!(Encoding::Utf8.decode_cow("café".as_bytes(), LossyPolicy::Strip), Some(Cow::Borrowed("café"))));
+		assert!(matches!(Encoding::Utf8.decode_cow("\u{fffd}".as_bytes(), LossyPolicy::Keep), Some(Cow::Borrowed(_))));
+		let buf = b"caf\xe9 \xef\xbf\xbd ok";
+		for lossy in [LossyPolicy::Strip, LossyPolicy::Keep, LossyPolicy::Error] {
+			assert_eq!(Encoding::Utf8.decode_cow(buf, lossy).map(Cow::into_owned), Encoding::Utf8.decode_with(buf, lossy));
+		}
+		assert!(matches!(Encoding::Latin1.decode_cow(b"abc", LossyPolicy::Strip), Some(Cow::Owned(_))));
+	}
+
+	#[test]
+	fn custom_decoder() {
+		use crate::{Encoding, no_cache_with_encoding};
+		// GBK of the chars used below, as a charset crate would decode them,
+		// this crate has no GBK tables
+		fn two_bytes(buf: &[u8]) -> String {
+			buf.chunks(2).map(|pair| match pair {
+				[0xba, 0xba] => '汉',
+				[0xd3, 0xef] => '语',
+				_ => '?',
+			}).collect()
+		}
+		let ifo = create_dict("custom_decoder", &[("XX", "YYYY"), ("cafe", "a coffee shop")], &[("ZZZZ", "XX")]);
+		for (ext, from, to) in [
+			("idx", &b"XX"[..], &[0xba, 0xba][..]),
+			("idx", b"cafe", b"caf\xe9"),
+			("dict", b"YYYY", &[0xba, 0xba, 0xd3, 0xef]),
+			("syn", b"ZZZZ", &[0xd3, 0xef, 0xba, 0xba]),
+		] {
+			let path = ifo.with_extension(ext);
+			let data = fs::read(&path).unwrap();
+			let start = data.windows(from.len()).position(|window| window == from).unwrap();
+			let data = [&data[..start], to, &data[start + from.len()..]].concat();
+			fs::write(&path, data).unwrap();
+		}
+
+		let dict = no_cache_with_encoding(&ifo, Encoding::Custom(two_bytes)).unwrap();
+		let definitions = dict.lookup("汉").unwrap().unwrap();
+		assert_eq!(definitions[0].word, "汉");
+		assert_eq!(definitions[0].segments[0].text, "汉语");
+		assert_eq!(dict.lookup("语汉").unwrap().unwrap()[0].word, "汉");
+
+		let dict = no_cache_with_encoding(&ifo, Encoding::Latin1).unwrap();
+		assert_eq!(dict.lookup("CAFÉ").unwrap().unwrap()[0].segments[0].text, "a coffee shop");
+		// utf-8 by default
+		let dict = no_cache(&ifo).unwrap();
+		assert!(dict.lookup("汉").unwrap().is_none());
+		assert!(dict.lookup("café").unwrap().is_none());
+	}
+
+	#[test]
+	fn lossy_definitions() {
+		use crate::{LossyPolicy, no_cache_with_lossy, with_memory_lossy};
+		let words: [(&str, &[u8]); 2] = [("apple", b"caf\xe9 \xef\xbf\xbd fruit"), ("banana", b"yellow")];
+		let ifo = create_dict_with("lossy_policy", "m", &words, &[]);
+		let text = |dict: &dyn StarDict, word| dict.lookup(word).map(|definitions| definitions.unwrap()[0].segments[0].text.clone());
+
+		assert_eq!(text(&no_cache(&ifo).unwrap(), "apple").unwrap(), "caf  fruit");
+		let dict = no_cache_with_lossy(&ifo, LossyPolicy::Keep).unwrap();
+		assert_eq!(text(&dict, "apple").unwrap(), "caf\u{fffd} \u{fffd} fruit");
+		let dict = no_cache_with_lossy(&ifo, LossyPolicy::Error).unwrap();
+		assert!(matches!(text(&dict, "apple"), Err(Error::InvalidDictContent(word)) if word == "apple"));
+		assert_eq!(text(&dict, "banana").unwrap(), "yellow");
+
+		let dict = with_memory_lossy(&ifo, LossyPolicy::Keep).unwrap();
+		assert_eq!(text(&dict, "apple").unwrap(), "caf\u{fffd} \u{fffd} fruit");
+		assert!(matches!(with_memory_lossy(&ifo, LossyPolicy::Error), Err(Error::InvalidDictContent(_))));
+	}
+}
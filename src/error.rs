@@ -14,6 +14,12 @@ pub enum Error {
 	#[error("Failed open {0} file")]
 	FailedOpenFile(&'static str, std::io::Error),
 
+	#[error("Failed write {0} file")]
+	FailedWriteFile(&'static str, std::io::Error),
+
+	#[error("Not a stardict ifo file")]
+	InvalidIfoMagic,
+
 	#[error("Invalid version")]
 	InvalidVersion(String),
 
@@ -23,18 +29,33 @@ pub enum Error {
 	#[error("Invalid idx element: {0}")]
 	InvalidIdxElement(&'static str),
 
+	#[error("Idx size {actual} mismatch idxfilesize {expected} in ifo")]
+	IdxSizeMismatch { expected: usize, actual: usize },
+
 	#[error("Invalid idx block: {0}")]
 	InvalidIdxBlock(String),
 
+	#[error("Syn records {actual} mismatch synwordcount {expected} in ifo")]
+	SynCountMismatch { expected: usize, actual: usize },
+
 	#[error("Invalid syn index for {0}")]
 	InvalidSynIndex(String),
 
-	#[error("Invalid dict content")]
-	InvalidDictContent,
+	#[error("Invalid dict content of {0}")]
+	InvalidDictContent(String),
 
 	#[error("Invalid dict file")]
 	InvalidDict,
 
+	#[error("Invalid dict block of {word} at offset {offset}")]
+	InvalidDictBlock { word: String, offset: usize },
+
+	#[error("Segment of type {0} is not xdxf")]
+	NotXdxfSegment(String),
+
+	#[error("Invalid xdxf: {0}")]
+	InvalidXdxf(String),
+
 	#[error("Invalid dict field: {0}")]
 	FailedParseDictHeader(&'static str),
 
@@ -50,12 +71,27 @@ pub enum Error {
 	#[error("Failed get user cache folder")]
 	NoCacheDir,
 
+	#[error("Failed create cache folder: {}", .0.display())]
+	FailedCreateCacheDir(std::path::PathBuf, std::io::Error),
+
 	#[error("Failed open cache: {0}")]
 	FailedOpenCache(String),
 
+	#[error("Encoding {0} not supported")]
+	UnsupportedEncoding(String),
+
+	#[error("Full text search not supported by sqlite")]
+	FullTextUnsupported,
+
 	#[error("Initiating dict cache")]
 	CacheInitiating,
 
+	#[error("No complete dictionary cache: {}", .0.display())]
+	CacheMissing(std::path::PathBuf),
+
+	#[error("Lookup not finished in time")]
+	LookupTimeout,
+
 	#[error("Invalid dictionary cache: {0}, remove and build it again")]
 	InvalidDictCache(String),
 }
@@ -0,0 +1,374 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::error::{Error, Result};
+use crate::writer::stardict_strcmp;
+use crate::{no_cache, StarDict, WordDefinition};
+
+/// several dictionaries queried together, results in the order added,
+/// shared with the lookup threads of lookup_each_timeout
+#[derive(Default)]
+pub struct DictGroup {
+	dicts: Vec<Arc<dyn StarDict + Send + Sync>>,
+}
+
+impl DictGroup {
+	#[inline]
+	pub fn new() -> Self
+	{
+		DictGroup { dicts: vec![] }
+	}
+
+	/// open every sub folder of dir containing an .ifo file without cache,
+	/// sorted by folder name, sub folders failed to open are skipped
+	pub fn open_dir(dir: impl AsRef<Path>) -> Result<Self>
+	{
+		let entries = fs::read_dir(dir.as_ref()).map_err(|_| Error::InvalidDictPath)?;
+		let mut folders: Vec<_> = entries
+			.filter_map(|entry| entry.ok())
+			.map(|entry| entry.path())
+			.filter(|path| path.is_dir())
+			.collect();
+		folders.sort();
+
+		let mut group = DictGroup::new();
+		for folder in folders {
+			let ifo = fs::read_dir(&folder)
+				.into_iter()
+				.flatten()
+				.filter_map(|entry| entry.ok())
+				.map(|entry| entry.path())
+				.find(|path| path.extension().is_some_and(|ext| ext == "ifo"));
+			if let Some(Ok(dict)) = ifo.map(no_cache) {
+				group.add(Box::new(dict));
+			}
+		}
+		Ok(group)
+	}
+
+	#[inline]
+	pub fn add(&mut self, dict: Box<dyn StarDict + Send + Sync>)
+	{
+		self.dicts.push(Arc::from(dict));
+	}
+
+	/// add a dictionary shared with others, e.g. one removed from a group
+	#[inline]
+	pub fn add_shared(&mut self, dict: Arc<dyn StarDict + Send + Sync>)
+	{
+		self.dicts.push(dict);
+	}
+
+	/// panics if index out of bounds, timed out lookups may still hold it
+	#[inline]
+	pub fn remove(&mut self, index: usize) -> Arc<dyn StarDict + Send + Sync>
+	{
+		self.dicts.remove(index)
+	}
+
+	#[inline]
+	pub fn len(&self) -> usize
+	{
+		self.dicts.len()
+	}
+
+	#[inline]
+	pub fn is_empty(&self) -> bool
+	{
+		self.dicts.is_empty()
+	}
+
+	#[inline]
+	pub fn dicts(&self) -> &[Arc<dyn StarDict + Send + Sync>]
+	{
+		&self.dicts
+	}
+
+	/// definitions tagged by dict name, dictionaries without the word
+	/// or failed lookup are skipped
+	pub fn lookup(&self, word: &str) -> Result<Vec<(String, Vec<WordDefinition>)>>
+	{
+		let results = self.lookup_each(word)
+			.into_iter()
+			.filter_map(|(name, result)| match result {
+				Ok(Some(definitions)) => Some((name, definitions)),
+				_ => None,
+			})
+			.collect();
+		Ok(results)
+	}
+
+	/// result of every dictionary tagged by dict name, errors included
+	pub fn lookup_each(&self, word: &str)
+		-> Vec<(String, Result<Option<Vec<WordDefinition>>>)>
+	{
+		self.dicts.iter()
+			.map(|dict| (dict.dict_name().to_owned(), dict.lookup(word)))
+			.collect()
+	}
+
+	/// same as lookup_each, every dictionary looked up in its own thread,
+	/// Error::LookupTimeout for the ones not finished within timeout,
+	/// their lookups left running in background
+	pub fn lookup_each_timeout(&self, word: &str, timeout: Duration)
+		-> Vec<(String, Result<Option<Vec<WordDefinition>>>)>
+	{
+		let deadline = Instant::now() + timeout;
+		let (sender, receiver) = mpsc::channel();
+		for (index, dict) in self.dicts.iter().enumerate() {
+			let dict = dict.clone();
+			let word = word.to_owned();
+			let sender = sender.clone();
+			thread::spawn(move || {
+				// receiver gone after the deadline
+				let _ = sender.send((index, dict.lookup(&word)));
+			});
+		}
+		drop(sender);
+		let mut results: Vec<Option<Result<Option<Vec<WordDefinition>>>>> =
+			self.dicts.iter().map(|_| None).collect();
+		let mut pending = self.dicts.len();
+		while pending > 0 {
+			let left = deadline.saturating_duration_since(Instant::now());
+			match receiver.recv_timeout(left) {
+				Ok((index, result)) => {
+					results[index] = Some(result);
+					pending -= 1;
+				}
+				Err(_) => break,
+			}
+		}
+		self.dicts.iter()
+			.zip(results)
+			.map(|(dict, result)| (dict.dict_name().to_owned(), result.unwrap_or(Err(Error::LookupTimeout))))
+			.collect()
+	}
+
+	/// headwords of all dictionaries starting with prefix, each tagged by
+	/// names of the dictionaries containing it, limit == 0 means unbounded
+	#[inline]
+	pub fn search_prefix(&self, prefix: &str, limit: usize) -> Result<Vec<(String, Vec<String>)>>
+	{
+		Ok(self.search_merged(limit, |dict| dict.search_prefix(prefix, limit)))
+	}
+
+	/// same as search_prefix, headwords matched by the glob pattern
+	#[inline]
+	pub fn search_glob(&self, pattern: &str, limit: usize) -> Result<Vec<(String, Vec<String>)>>
+	{
+		Ok(self.search_merged(limit, |dict| dict.search_glob(pattern, limit)))
+	}
+
+	/// words of every dictionary deduplicated in stardict order,
+	/// limit applied after merged, failed searches are skipped
+	fn search_merged<F>(&self, limit: usize, search: F) -> Vec<(String, Vec<String>)>
+		where F: Fn(&dyn StarDict) -> Result<Vec<String>>
+	{
+		let mut merged: Vec<(String, Vec<String>)> = vec![];
+		let mut positions: HashMap<String, usize> = HashMap::new();
+		for dict in &self.dicts {
+			let words = match search(dict.as_ref()) {
+				Ok(words) => words,
+				Err(_) => continue,
+			};
+			let name = dict.dict_name();
+			for word in words {
+				if let Some(&position) = positions.get(&word) {
+					let names = &mut merged[position].1;
+					// same spelled entries of one dictionary
+					if names.last().is_none_or(|last| last != name) {
+						names.push(name.to_owned());
+					}
+				} else {
+					positions.insert(word.clone(), merged.len());
+					merged.push((word, vec![name.to_owned()]));
+				}
+			}
+		}
+		merged.sort_by(|(a, _), (b, _)| stardict_strcmp(a, b));
+		if limit > 0 {
+			merged.truncate(limit);
+		}
+		merged
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+	use std::path::PathBuf;
+	use std::thread;
+	use std::time::{Duration, Instant};
+	use crate::dictzip::tests::temp_file;
+	use crate::error::{Error, Result};
+	use crate::tests::{create_dict, SAMPLE_WORDS};
+	use crate::{no_cache, DictGroup, DictWriter, Ifo, MatchedDefinition, StarDict, StarDictStd, WordDefinition,
+		WordDefinitionSegment};
+
+	/// lookup after a delay, e.g. a cache on a network drive
+	struct Slow {
+		dict: StarDictStd,
+		delay: Duration,
+	}
+
+	impl StarDict for Slow {
+		fn path(&self) -> &PathBuf {
+			self.dict.path()
+		}
+
+		fn ifo(&self) -> &Ifo {
+			self.dict.ifo()
+		}
+
+		fn dict_name(&self) -> &str {
+			"slow"
+		}
+
+		fn dict_size(&self) -> Result<u64> {
+			self.dict.dict_size()
+		}
+
+		fn lookup(&self, word: &str) -> Result<Option<Vec<WordDefinition>>> {
+			thread::sleep(self.delay);
+			self.dict.lookup(word)
+		}
+
+		fn lookup_detailed(&self, word: &str) -> Result<Option<Vec<MatchedDefinition>>> {
+			self.dict.lookup_detailed(word)
+		}
+
+		fn lookup_limited(&self, word: &str, max: usize) -> Result<Option<Vec<WordDefinition>>> {
+			self.dict.lookup_limited(word, max)
+		}
+
+		fn contains(&self, word: &str) -> Result<bool> {
+			self.dict.contains(word)
+		}
+
+		fn lookup_exact(&self, word: &str) -> Result<Option<Vec<WordDefinition>>> {
+			self.dict.lookup_exact(word)
+		}
+
+		fn search_prefix(&self, prefix: &str, limit: usize) -> Result<Vec<String>> {
+			self.dict.search_prefix(prefix, limit)
+		}
+
+		fn words(&self) -> Result<Vec<String>> {
+			self.dict.words()
+		}
+
+		fn neighbors(&self, word: &str, before: usize, after: usize) -> Result<Vec<String>> {
+			self.dict.neighbors(word, before, after)
+		}
+
+		fn suggest(&self, word: &str, max_distance: usize, limit: usize) -> Result<Vec<String>> {
+			self.dict.suggest(word, max_distance, limit)
+		}
+
+		fn search_glob(&self, pattern: &str, limit: usize) -> Result<Vec<String>> {
+			self.dict.search_glob(pattern, limit)
+		}
+
+		fn synonyms_of(&self, word: &str) -> Result<Vec<String>> {
+			self.dict.synonyms_of(word)
+		}
+	}
+
+	fn write_dict(group_dir: &std::path::Path, name: &str, words: &[(&str, &str)])
+	{
+		let dir = group_dir.join(name);
+		fs::create_dir_all(&dir).unwrap();
+		let mut writer = DictWriter::new(name);
+		for (word, definition) in words {
+			writer.add(*word, vec![WordDefinitionSegment::new("m".to_owned(), definition.as_bytes())]);
+		}
+		writer.write(dir.join(format!("{}.ifo", name))).unwrap();
+	}
+
+	#[test]
+	fn group() {
+		let dir = temp_file("group");
+		if dir.exists() {
+			fs::remove_dir_all(&dir).unwrap();
+		}
+		write_dict(&dir, "b_fruits", &[("apple", "a round fruit"), ("banana", "a long fruit")]);
+		write_dict(&dir, "a_companies", &[("apple", "a tech company")]);
+		// not a dictionary
+		fs::create_dir_all(dir.join("empty")).unwrap();
+
+		let mut group = DictGroup::open_dir(&dir).unwrap();
+		assert_eq!(group.len(), 2);
+
+		let results = group.lookup("Apple").unwrap();
+		assert_eq!(results.len(), 2);
+		assert_eq!(results[0].0, "a_companies");
+		assert_eq!(results[0].1[0].segments[0].text, "a tech company");
+		assert_eq!(results[1].0, "b_fruits");
+		assert_eq!(results[1].1[0].segments[0].text, "a round fruit");
+
+		let results = group.lookup("banana").unwrap();
+		assert_eq!(results.len(), 1);
+		assert_eq!(results[0].0, "b_fruits");
+		assert_eq!(group.lookup_each("banana").len(), 2);
+
+		let removed = group.remove(0);
+		assert_eq!(removed.dict_name(), "a_companies");
+		assert_eq!(group.lookup("apple").unwrap()[0].0, "b_fruits");
+		group.add_shared(removed);
+		assert_eq!(group.lookup("apple").unwrap()[1].0, "a_companies");
+	}
+
+	#[test]
+	fn group_search() {
+		let dir = temp_file("group_search");
+		if dir.exists() {
+			fs::remove_dir_all(&dir).unwrap();
+		}
+		write_dict(&dir, "a_fruits", &[("apple", "a round fruit"), ("apricot", "a small fruit"),
+			("banana", "a long fruit")]);
+		write_dict(&dir, "b_companies", &[("apple", "a tech company"), ("applied", "a materials company")]);
+		let group = DictGroup::open_dir(&dir).unwrap();
+
+		let tagged = |word: &str, names: &[&str]|
+			(word.to_owned(), names.iter().map(|name| name.to_string()).collect::<Vec<_>>());
+		assert_eq!(group.search_prefix("ap", 0).unwrap(), vec![
+			tagged("apple", &["a_fruits", "b_companies"]),
+			tagged("applied", &["b_companies"]),
+			tagged("apricot", &["a_fruits"]),
+		]);
+		assert_eq!(group.search_prefix("ap", 2).unwrap(), vec![
+			tagged("apple", &["a_fruits", "b_companies"]),
+			tagged("applied", &["b_companies"]),
+		]);
+		assert!(group.search_prefix("cherry", 0).unwrap().is_empty());
+		assert_eq!(group.search_glob("*a*", 0).unwrap(), vec![
+			tagged("apple", &["a_fruits", "b_companies"]),
+			tagged("applied", &["b_companies"]),
+			tagged("apricot", &["a_fruits"]),
+			tagged("banana", &["a_fruits"]),
+		]);
+		assert_eq!(group.search_glob("b?nana", 1).unwrap(), vec![tagged("banana", &["a_fruits"])]);
+	}
+
+	#[test]
+	fn lookup_timeout() {
+		let ifo = create_dict("lookup_timeout", &SAMPLE_WORDS, &[]);
+		let mut group = DictGroup::new();
+		group.add(Box::new(Slow { dict: no_cache(&ifo).unwrap(), delay: Duration::from_secs(5) }));
+		group.add(Box::new(no_cache(&ifo).unwrap()));
+		let start = Instant::now();
+		let results = group.lookup_each_timeout("apple", Duration::from_millis(200));
+		assert!(start.elapsed() < Duration::from_secs(2));
+		assert_eq!(results[0].0, "slow");
+		assert!(matches!(results[0].1, Err(Error::LookupTimeout)));
+		assert_eq!(results[1].0, "lookup_timeout");
+		assert!(results[1].1.as_ref().unwrap().is_some());
+		// all finished in time
+		group.remove(0);
+		let results = group.lookup_each_timeout("cherry", Duration::from_secs(5));
+		assert!(matches!(results[0].1, Ok(None)));
+	}
+}
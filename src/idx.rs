@@ -1,14 +1,22 @@
 use crate::error::{Error, Result};
 use crate::ifo::{Ifo, Version};
 
+use std::borrow::Cow;
+use std::cmp::Ordering;
 use std::fmt::Debug;
 use std::fs::File;
-use std::io::{BufRead, BufReader, Read};
-use std::path::PathBuf;
-use flate2::read::GzDecoder;
-use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::ops::{Bound, Range, RangeBounds};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use flate2::read::MultiGzDecoder;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use byteorder::{BigEndian, ReadBytesExt};
-use crate::buf_to_string;
+use crate::collation::Collation;
+use crate::encoding::Encoding;
+use crate::opener::OpenOptions;
+use crate::search::{Glob, Suggestions};
+use crate::shard::Shards;
 
 struct IdxRawEntry {
 	word: String,
@@ -20,103 +28,952 @@ struct IdxRawEntry {
 pub struct IdxEntryBlock {
 	pub offset: usize,
 	pub size: usize,
+	// headword of the block if cased other than the word of the entry
+	pub variant: Option<Box<str>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct IdxEntry {
 	pub word: String,
 	pub blocks: Vec<IdxEntryBlock>,
 }
 
 impl IdxEntry {
-	fn push_block(&mut self, offset: usize, size: usize)
+	fn push_block(&mut self, word: &str, offset: usize, size: usize)
+	{
+		let variant = if word == self.word {
+			None
+		} else {
+			Some(word.into())
+		};
+		self.blocks.push(IdxEntryBlock { offset, size, variant })
+	}
+
+	/// distinct headwords of the blocks in idx order
+	pub fn words(&self) -> Vec<&str>
 	{
-		self.blocks.push(IdxEntryBlock { offset, size })
+		let mut words: Vec<&str> = vec![];
+		for block in &self.blocks {
+			let word = self.block_word(block);
+			if !words.contains(&word) {
+				words.push(word);
+			}
+		}
+		words
 	}
+
+	/// original headword of the block
+	#[inline]
+	pub fn block_word<'a>(&'a self, block: &'a IdxEntryBlock) -> &'a str
+	{
+		block.variant.as_deref().unwrap_or(&self.word)
+	}
+}
+
+enum Items {
+	// keys shared with the sorted keys of idx
+	Map(HashMap<Arc<str>, IdxEntry>),
+	Sorted(SortedItems),
+	Paged(PagedItems),
+}
+
+/// low memory items, keep the raw idx data and binary search the records
+/// sorted by normalized word, not the idx order, so any collation works the same as the map
+struct SortedItems {
+	buf: Vec<u8>,
+	// start of every record in buf by normalized word,
+	// records of a word in idx order, empty words left out
+	records: Vec<usize>,
+	// bytes of offset
+	width: usize,
+	encoding: Encoding,
+}
+
+/// entries per page of the .idx.oft offset table, as stardict
+const ENTRIES_PER_PAGE: usize = 32;
+
+/// idx read from disk a page at a time by the offsets of .idx.oft,
+/// binary searched in the stardict_strcmp order the idx sorted by
+struct PagedItems {
+	reader: Mutex<BufReader<Shards>>,
+	// idx offset of every page start, and the idx size
+	pages: Vec<usize>,
+	// bytes of offset
+	width: usize,
+	encoding: Encoding,
+	// warnings of the pages read so far by entry index,
+	// the first entry of the page for failed reading
+	warnings: Mutex<BTreeMap<usize, String>>,
 }
 
-#[derive(Debug)]
 pub struct Idx {
-	pub(super) items: HashMap<String, IdxEntry>,
+	items: Items,
+	// keys of the map sorted, built on first use,
+	// not kept for the raw idx of low memory and paged items
+	sorted: OnceLock<Vec<Arc<str>>>,
 	pub(super) syn: Option<HashMap<String, HashSet<String>>>,
+	// normalized headword to synonyms in syn file order
+	pub(super) synonyms: Option<HashMap<String, Vec<String>>>,
+	pub(super) collation: Collation,
+	// suspicious entries skipped while reading
+	warnings: Vec<String>,
 }
 
 #[allow(unused)]
 impl Idx {
-	pub fn new(path: PathBuf, ifo: &Ifo, gz: bool, syn: Option<PathBuf>) -> Result<Idx>
+	/// with paged, the idx is read a page at a time by the .idx.oft next to it,
+	/// with low_memory, the raw idx is kept instead of building the whole map,
+	/// with verify_size, the uncompressed idx size must be the idxfilesize of ifo,
+	/// and syn records the synwordcount if declared
+	pub fn new(path: PathBuf, ifo: &Ifo, gz: bool, syn: Option<PathBuf>, syn_gz: bool,
+		options: &OpenOptions) -> Result<Idx>
 	{
-		let f = File::open(path).map_err(|e| Error::FailedOpenFile("idx", e))?;
+		let OpenOptions { low_memory, verify_size, encoding, paged, .. } = *options;
+		let collation = options.collation.clone();
+		let check_size = |actual: usize| if verify_size && actual != ifo.idxfilesize {
+			Err(Error::IdxSizeMismatch { expected: ifo.idxfilesize, actual })
+		} else {
+			Ok(())
+		};
+		let syn_count = (verify_size && ifo.synwordcount > 0).then_some(ifo.synwordcount);
+		let syn = syn.map(|syn| (syn, syn_gz));
+		// numbered shards read as one idx, a split .gz is the byte pieces of one gzip file,
+		// gzip members concatenated
+		let f = Shards::open(&path, "idx")?;
+		let file_size = f.len();
 		let mut reader = BufReader::new(f);
-		let mut idx = if gz {
-			let mut decoder = GzDecoder::new(reader);
+		let width = match ifo.version {
+			Version::V300 if ifo.idxoffsetbits == 64 => 8,
+			_ => 4,
+		};
+		// why the paged idx not used
+		let mut warnings = vec![];
+		let pages = if !paged || gz {
+			None
+		} else if collation != Collation::AsciiLower {
+			// keys of other collations not next to each other in the idx order
+			warnings.push("paged idx needs the ascii lower collation, idx read whole".to_owned());
+			None
+		} else {
+			read_offset_table(&path, ifo.wordcount, file_size as usize, &mut warnings)
+		};
+		let mut idx = if let Some(pages) = pages {
+			check_size(file_size as usize)?;
+			let paged = PagedItems { reader: Mutex::new(reader), pages, width, encoding,
+				warnings: Mutex::new(BTreeMap::new()) };
+			read_paged(paged, syn, syn_count, collation, encoding)
+		} else if low_memory {
 			let mut buf = vec![];
-			decoder.read_to_end(&mut buf);
-			read(&ifo.version, ifo.idxoffsetbits, buf.as_slice(), syn)
+			if gz {
+				MultiGzDecoder::new(reader).read_to_end(&mut buf)
+			} else {
+				reader.read_to_end(&mut buf)
+			}.map_err(|e| Error::FailedOpenFile("idx", e))?;
+			check_size(buf.len())?;
+			read_sorted(&ifo.version, ifo.idxoffsetbits, buf, syn, syn_count, collation, encoding)
+		} else if gz {
+			let mut decoder = MultiGzDecoder::new(reader);
+			let mut buf = vec![];
+			decoder.read_to_end(&mut buf).map_err(|e| Error::FailedOpenFile("idx", e))?;
+			check_size(buf.len())?;
+			read(&ifo.version, ifo.idxoffsetbits, buf.as_slice(), syn, syn_count, collation, encoding)
 		} else {
-			read(&ifo.version, ifo.idxoffsetbits, reader, syn)
+			check_size(file_size as usize)?;
+			read(&ifo.version, ifo.idxoffsetbits, reader, syn, syn_count, collation, encoding)
 		}?;
+		warnings.append(&mut idx.warnings);
+		idx.warnings = warnings;
 
 		Ok(idx)
 	}
 
-	pub fn lookup_blocks(&self, word: &str) -> Option<Vec<&IdxEntry>>
+	/// all entries by normalized word,
+	/// decoded from the raw idx in low memory mode
+	pub(super) fn items(&self) -> Cow<'_, HashMap<Arc<str>, IdxEntry>>
+	{
+		match &self.items {
+			Items::Map(items) => Cow::Borrowed(items),
+			Items::Sorted(sorted) => Cow::Owned(build_items(sorted.all(), &self.collation)),
+			Items::Paged(paged) => Cow::Owned(build_items(paged.all().into_iter(), &self.collation)),
+		}
+	}
+
+	fn get(&self, key: &str) -> Option<Cow<'_, IdxEntry>>
+	{
+		match &self.items {
+			Items::Map(items) => items.get(key).map(Cow::Borrowed),
+			Items::Sorted(sorted) => sorted.get(key, &self.collation).map(Cow::Owned),
+			Items::Paged(paged) => paged.get(key, &self.collation).map(Cow::Owned),
+		}
+	}
+
+	/// same keys found as lookup_blocks, without the entries
+	pub fn contains(&self, word: &str, follow_synonyms: bool) -> bool
+	{
+		let key = self.collation.normalize(word);
+		if self.get(&key).is_some() {
+			return true;
+		}
+		follow_synonyms && self.syn.as_ref()
+			.and_then(|syn| syn.get(&key))
+			.is_some_and(|alias| alias.iter().any(|key| self.get(key).is_some()))
+	}
+
+	/// headword of the entry for the normalized word, cased as the first
+	/// block in idx, without syn
+	#[inline]
+	pub fn canonical_word(&self, word: &str) -> Option<String>
+	{
+		Some(self.get(&self.collation.normalize(word))?.word.clone())
+	}
+
+	/// blocks of the headword cased exactly as word, without syn
+	pub fn lookup_exact(&self, word: &str) -> Option<IdxEntry>
 	{
-		let lowercase_word = word.to_lowercase();
+		let entry = self.get(&self.collation.normalize(word))?;
+		let blocks: Vec<IdxEntryBlock> = entry.blocks.iter()
+			.filter(|block| entry.block_word(block) == word)
+			.map(|block| IdxEntryBlock { offset: block.offset, size: block.size, variant: None })
+			.collect();
+		if blocks.is_empty() {
+			None
+		} else {
+			Some(IdxEntry { word: word.to_owned(), blocks })
+		}
+	}
+
+	/// entries with the matched key and whether found through syn,
+	/// syn skipped without follow_synonyms
+	pub fn lookup_blocks(&self, word: &str, follow_synonyms: bool) -> Option<Vec<(String, bool, Cow<'_, IdxEntry>)>>
+	{
+		let lowercase_word = self.collation.normalize(word);
 		let mut vec = vec![];
 		let mut found = HashSet::new();
-		if let Some(entry) = self.items.get(&lowercase_word) {
-			vec.push(entry);
+		if let Some(entry) = self.get(&lowercase_word) {
 			found.insert(entry.word.clone());
+			vec.push((lowercase_word.clone(), false, entry));
 		}
-		if let Some(syn) = &self.syn {
+		if let Some(syn) = self.syn.as_ref().filter(|_| follow_synonyms) {
 			if let Some(alias) = syn.get(&lowercase_word) {
 				for key in alias {
-					if let Some(entry) = self.items.get(key) {
+					if let Some(entry) = self.get(key) {
 						if !found.contains(&entry.word) {
-							vec.push(entry);
 							found.insert(entry.word.clone());
+							vec.push((key.clone(), true, entry));
 						}
 					}
 				}
 			}
 		}
-		if vec.len() == 0 {
+		if vec.is_empty() {
 			None
 		} else {
 			Some(vec)
 		}
 	}
+
+	pub fn search_prefix(&self, prefix: &str, limit: usize) -> Vec<String>
+	{
+		let lowercase_prefix = self.collation.normalize(prefix);
+		let limit = if limit > 0 { limit } else { usize::MAX };
+		let Some(view) = self.view() else {
+			return self.stream_sorted(|key| key.starts_with(&lowercase_prefix)).into_iter()
+				.take(limit)
+				.map(|(_, word)| word)
+				.collect();
+		};
+		let start = view.partition_point(|key| key < lowercase_prefix.as_str());
+		view.distinct(start..view.len())
+			.take_while(|(key, _)| key.starts_with(&lowercase_prefix))
+			.take(limit)
+			.map(|(_, pos)| view.word(pos))
+			.collect()
+	}
+
+	/// offset and size of the blocks found by lookup_blocks, in the same order
+	pub fn offsets_of(&self, word: &str, follow_synonyms: bool) -> Option<Vec<(usize, usize)>>
+	{
+		let blocks = self.lookup_blocks(word, follow_synonyms)?;
+		Some(blocks.iter()
+			.flat_map(|(_, _, entry)| entry.blocks.iter().map(|block| (block.offset, block.size)))
+			.collect())
+	}
+
+	/// blocks out of the dict of dict_size bytes, more than half of them
+	/// likely parsed with wrong idxoffsetbits, only warned with lenient
+	pub fn check_offsets(&mut self, dict_size: usize, lenient: bool) -> Result<()>
+	{
+		let mut total = 0;
+		let mut out_of_range = 0;
+		let mut check = |offset: usize, size: usize| {
+			total += 1;
+			if offset.checked_add(size).is_none_or(|end| end > dict_size) {
+				out_of_range += 1;
+			}
+		};
+		match &self.items {
+			Items::Map(items) => for entry in items.values() {
+				for block in &entry.blocks {
+					check(block.offset, block.size);
+				}
+			}
+			Items::Sorted(sorted) => for entry in sorted.all() {
+				check(entry.offset, entry.size);
+			}
+			// only the first page, not to read the whole idx while opening
+			Items::Paged(paged) => for entry in paged.page(0).unwrap_or_default() {
+				check(entry.offset, entry.size);
+			}
+		}
+		if out_of_range * 2 <= total {
+			Ok(())
+		} else if lenient {
+			self.warnings.push(format!("{} of {} idx offsets out of the dict, check idxoffsetbits",
+				out_of_range, total));
+			Ok(())
+		} else {
+			Err(Error::InvalidIdxElement("offsets out of range, check idxoffsetbits"))
+		}
+	}
+
+	/// headword, offset and size of every block, sorted by offset
+	pub fn blocks(&self) -> Vec<(String, usize, usize)>
+	{
+		let mut blocks: Vec<(String, usize, usize)> = match &self.items {
+			Items::Map(items) => items.values()
+				.flat_map(|entry| entry.blocks.iter()
+					.map(|block| (entry.block_word(block).to_owned(), block.offset, block.size)))
+				.collect(),
+			Items::Sorted(sorted) => sorted.all()
+				.map(|entry| (entry.word, entry.offset, entry.size))
+				.collect(),
+			Items::Paged(paged) => paged.all().into_iter()
+				.map(|entry| (entry.word, entry.offset, entry.size))
+				.collect(),
+		};
+		blocks.sort_by_key(|(_, offset, _)| *offset);
+		blocks
+	}
+
+	/// every distinct headword sorted by key, those of a key in idx order
+	pub fn words(&self) -> Vec<String>
+	{
+		if let Some(view) = self.view() {
+			let mut words: Vec<String> = vec![];
+			// start of the words of the key
+			let mut key_words = 0;
+			let mut last_key = None;
+			for pos in 0..view.len() {
+				let key = view.key(pos);
+				if last_key.as_ref() != Some(&key) {
+					key_words = words.len();
+					last_key = Some(key);
+				}
+				for word in view.words(pos) {
+					if !words[key_words..].contains(&word) {
+						words.push(word);
+					}
+				}
+			}
+			return words;
+		}
+		let mut words = vec![];
+		self.for_each_word(|word| if !word.is_empty() {
+			words.push((self.collation.normalize(&word), word));
+		});
+		// stable, so words of a key kept in idx order
+		words.sort_by(|(a, _), (b, _)| a.cmp(b));
+		let mut seen = HashSet::new();
+		words.into_iter()
+			.filter(|(_, word)| seen.insert(word.clone()))
+			.map(|(_, word)| word)
+			.collect()
+	}
+
+	pub fn search_glob(&self, pattern: &str, limit: usize) -> Vec<String>
+	{
+		let glob = Glob::new(&self.collation.normalize(pattern));
+		let limit = if limit > 0 { limit } else { usize::MAX };
+		match self.view() {
+			Some(view) => view.distinct(0..view.len())
+				.filter(|(key, _)| glob.matches(key))
+				.take(limit)
+				.map(|(_, pos)| view.word(pos))
+				.collect(),
+			None => self.stream_sorted(|key| glob.matches(key)).into_iter()
+				.take(limit)
+				.map(|(_, word)| word)
+				.collect(),
+		}
+	}
+
+	pub fn suggest(&self, word: &str, max_distance: usize, limit: usize) -> Vec<String>
+	{
+		let mut suggestions = Suggestions::new(&self.collation.normalize(word), max_distance);
+		match &self.items {
+			Items::Map(items) => for (key, entry) in items {
+				suggestions.push(key, || entry.word.clone());
+			}
+			Items::Sorted(sorted) => {
+				let view = SortedView::Records(sorted, &self.collation);
+				for (key, pos) in view.distinct(0..view.len()) {
+					suggestions.push(&key, || view.word(pos));
+				}
+			}
+			Items::Paged(_) => self.for_each_word(|word| if !word.is_empty() {
+				suggestions.push(&self.collation.normalize(&word), || word);
+			}),
+		}
+		suggestions.finish(limit)
+	}
+
+	/// synonyms from the syn file pointing to the word
+	pub fn synonyms_of(&self, word: &str) -> Vec<String>
+	{
+		self.synonyms.as_ref()
+			.and_then(|synonyms| synonyms.get(&self.collation.normalize(word)))
+			.cloned()
+			.unwrap_or_default()
+	}
+
+	/// suspicious entries skipped while reading, e.g. empty headwords,
+	/// and the offset table not used, for paged items those of the pages read so far
+	pub fn warnings(&self) -> Vec<String>
+	{
+		let mut warnings = self.warnings.clone();
+		if let Items::Paged(paged) = &self.items {
+			warnings.extend(paged.warnings.lock().unwrap_or_else(PoisonError::into_inner)
+				.values()
+				.cloned());
+		}
+		warnings
+	}
+
+	/// keys in order to binary search, of the map or the low memory records,
+	/// None for the paged idx
+	fn view(&self) -> Option<SortedView<'_>>
+	{
+		match &self.items {
+			Items::Map(items) => Some(SortedView::Map(items, self.sorted.get_or_init(|| {
+				let mut keys: Vec<Arc<str>> = items.keys().cloned().collect();
+				keys.sort_unstable();
+				keys
+			}))),
+			Items::Sorted(sorted) => Some(SortedView::Records(sorted, &self.collation)),
+			Items::Paged(_) => None,
+		}
+	}
+
+	/// headword of every record, empty ones included for the paged idx
+	fn for_each_word(&self, mut f: impl FnMut(String))
+	{
+		match &self.items {
+			Items::Map(items) => items.values().for_each(|entry| f(entry.word.clone())),
+			Items::Sorted(sorted) => sorted.all().for_each(|raw| f(raw.word)),
+			Items::Paged(paged) => paged.for_each(|_, raw| f(raw.word)),
+		}
+	}
+
+	/// same as sorted_range for the keys matched, streamed over the paged idx
+	/// without keeping every key, the first headword of a key in idx order
+	fn stream_sorted(&self, matched: impl Fn(&str) -> bool) -> Vec<(String, String)>
+	{
+		let mut sorted = vec![];
+		self.for_each_word(|word| if !word.is_empty() {
+			let key = self.collation.normalize(&word);
+			if matched(&key) {
+				sorted.push((key, word));
+			}
+		});
+		// stable, so the first of the key kept
+		sorted.sort_by(|(a, _), (b, _)| a.cmp(b));
+		sorted.dedup_by(|(b, _), (a, _)| a == b);
+		sorted
+	}
+
+	/// normalized key and headword of every key in range, sorted by key,
+	/// the first headword of a key in idx order, keys normalized by the caller
+	pub fn sorted_range<'a>(&self, range: impl RangeBounds<&'a str>) -> Vec<(String, String)>
+	{
+		let Some(view) = self.view() else {
+			return self.stream_sorted(|key| range.contains(&key));
+		};
+		let start = match range.start_bound() {
+			Bound::Included(key) => view.partition_point(|k| k < *key),
+			Bound::Excluded(key) => view.partition_point(|k| k <= *key),
+			Bound::Unbounded => 0,
+		};
+		let end = match range.end_bound() {
+			Bound::Included(key) => view.partition_point(|k| k <= *key),
+			Bound::Excluded(key) => view.partition_point(|k| k < *key),
+			Bound::Unbounded => view.len(),
+		};
+		view.distinct(start..end.max(start))
+			.map(|(key, pos)| (key.into_owned(), view.word(pos)))
+			.collect()
+	}
+
+	/// headwords sorted right before and after the word, the word itself excluded
+	pub fn neighbors(&self, word: &str, before: usize, after: usize) -> Vec<String>
+	{
+		let key = self.collation.normalize(word);
+		let Some(view) = self.view() else {
+			// only the nearest keys kept while streaming
+			let mut lower = BTreeMap::new();
+			let mut upper = BTreeMap::new();
+			self.for_each_word(|word| if !word.is_empty() {
+				let word_key = self.collation.normalize(&word);
+				match word_key.cmp(&key) {
+					Ordering::Less if before > 0 => {
+						lower.entry(word_key).or_insert(word);
+						if lower.len() > before {
+							lower.pop_first();
+						}
+					}
+					Ordering::Greater if after > 0 => {
+						upper.entry(word_key).or_insert(word);
+						if upper.len() > after {
+							upper.pop_last();
+						}
+					}
+					_ => {}
+				}
+			});
+			return lower.into_values().chain(upper.into_values()).collect();
+		};
+		let start = view.partition_point(|k| k < key.as_str());
+		let end = view.partition_point(|k| k <= key.as_str());
+		// first position of every key before, nearest first
+		let mut lower = vec![];
+		let mut pos = start;
+		while pos > 0 && lower.len() < before {
+			let previous = view.key(pos - 1);
+			pos = view.partition_point(|k| k < &*previous);
+			lower.push(pos);
+		}
+		lower.into_iter().rev()
+			.chain(view.distinct(end..view.len()).take(after).map(|(_, pos)| pos))
+			.map(|pos| view.word(pos))
+			.collect()
+	}
+}
+
+/// keys sorted to binary search, one position for every key of the map,
+/// one for every record of the low memory items, records of a key next to each other
+enum SortedView<'a> {
+	Map(&'a HashMap<Arc<str>, IdxEntry>, &'a [Arc<str>]),
+	Records(&'a SortedItems, &'a Collation),
+}
+
+impl<'a> SortedView<'a> {
+	#[inline]
+	fn len(&self) -> usize
+	{
+		match self {
+			SortedView::Map(_, keys) => keys.len(),
+			SortedView::Records(sorted, _) => sorted.records.len(),
+		}
+	}
+
+	#[inline]
+	fn key(&self, pos: usize) -> Cow<'a, str>
+	{
+		match self {
+			SortedView::Map(_, keys) => Cow::Borrowed(&keys[pos]),
+			SortedView::Records(sorted, collation) => Cow::Owned(collation.normalize(&sorted.word(pos))),
+		}
+	}
+
+	/// first headword of the position in idx order
+	#[inline]
+	fn word(&self, pos: usize) -> String
+	{
+		match self {
+			SortedView::Map(items, keys) => items[&keys[pos]].word.clone(),
+			SortedView::Records(sorted, _) => sorted.word(pos),
+		}
+	}
+
+	/// every cased headword of the position
+	fn words(&self, pos: usize) -> Vec<String>
+	{
+		match self {
+			SortedView::Map(items, keys) => items[&keys[pos]].words().into_iter().map(str::to_owned).collect(),
+			SortedView::Records(sorted, _) => vec![sorted.word(pos)],
+		}
+	}
+
+	/// first position with the key not matching before,
+	/// keys decoded only along the binary search
+	fn partition_point(&self, before: impl Fn(&str) -> bool) -> usize
+	{
+		let (mut low, mut high) = (0, self.len());
+		while low < high {
+			let middle = (low + high) / 2;
+			if before(&self.key(middle)) {
+				low = middle + 1;
+			} else {
+				high = middle;
+			}
+		}
+		low
+	}
+
+	/// key and first position of every key in range
+	fn distinct(&self, range: Range<usize>) -> impl Iterator<Item = (Cow<'a, str>, usize)> + '_
+	{
+		let mut last: Option<Cow<'a, str>> = None;
+		range.filter_map(move |pos| {
+			let key = self.key(pos);
+			if last.as_ref() == Some(&key) {
+				None
+			} else {
+				last = Some(key.clone());
+				Some((key, pos))
+			}
+		})
+	}
 }
 
 #[inline]
-fn read(version: &Version, idxoffsetbits: usize, reader: impl BufRead, syn: Option<PathBuf>) -> Result<Idx>
+fn read(version: &Version, idxoffsetbits: usize, reader: impl BufRead, syn: Option<(PathBuf, bool)>,
+	syn_count: Option<usize>, collation: Collation, encoding: Encoding) -> Result<Idx>
 {
 	let vec = match version {
-		Version::V242 => read_items(reader, |r| Ok(r.read_u32::<BigEndian>()? as usize))?,
+		Version::V242 => read_items(reader, encoding, |r| Ok(r.read_u32::<BigEndian>()? as usize))?,
 		Version::V300 => if idxoffsetbits == 64 {
-			read_items(reader, |r| Ok(r.read_u64::<BigEndian>()? as usize))?
+			read_items(reader, encoding, |r| Ok(r.read_u64::<BigEndian>()? as usize))?
 		} else {
-			read_items(reader, |r| Ok(r.read_u32::<BigEndian>()? as usize))?
+			read_items(reader, encoding, |r| Ok(r.read_u32::<BigEndian>()? as usize))?
 		}
 	};
-	let mut items = HashMap::new();
-	vec.iter().for_each(|raw| {
+	let items = build_items(vec.iter().map(|raw| IdxRawEntry {
+		word: raw.word.clone(),
+		offset: raw.offset,
+		size: raw.size,
+	}), &collation);
+	let (syn, synonyms) = if let Some((syn, gz)) = syn {
+		let (syn, synonyms) = load_syn(
+			syn,
+			gz,
+			syn_count,
+			|index| vec.get(index).map(|raw| raw.word.clone()),
+			|key| items.contains_key(key),
+			&collation,
+			encoding)?;
+		(Some(syn), Some(synonyms))
+	} else {
+		(None, None)
+	};
+	let warnings = vec.iter().enumerate()
+		.filter(|(_, raw)| raw.word.is_empty())
+		.map(|(index, raw)| empty_word_warning(index, raw))
+		.collect();
+	Ok(Idx { items: Items::Map(items), sorted: OnceLock::new(), syn, synonyms, collation, warnings })
+}
+
+fn build_items(raws: impl Iterator<Item=IdxRawEntry>, collation: &Collation)
+	-> HashMap<Arc<str>, IdxEntry>
+{
+	let mut items: HashMap<Arc<str>, IdxEntry> = HashMap::new();
+	raws.for_each(|raw| {
 		if raw.word.is_empty() {
 			return;
 		}
-		let entry = items.entry(raw.word.to_lowercase())
-			.or_insert(IdxEntry { word: raw.word.clone(), blocks: vec![] });
-		entry.push_block(raw.offset, raw.size);
+		let key = collation.normalize(&raw.word);
+		let entry = match items.get_mut(key.as_str()) {
+			Some(entry) => entry,
+			None => items.entry(key.into())
+				.or_insert_with(|| IdxEntry { word: raw.word.clone(), blocks: vec![] }),
+		};
+		entry.push_block(&raw.word, raw.offset, raw.size);
 	});
-	let syn = if let Some(syn) = syn {
-		Some(load_syn(&vec, syn, &items)?)
+	items
+}
+
+/// e.g. a null byte inside a word splitting it
+#[inline]
+fn empty_word_warning(index: usize, raw: &IdxRawEntry) -> String
+{
+	format!("idx entry {} with empty headword skipped, block at {} of {} bytes", index, raw.offset, raw.size)
+}
+
+/// bytes of the size in idx entries, offset width defined by idxoffsetbits
+const SIZE_WIDTH: usize = 4;
+
+fn read_sorted(version: &Version, idxoffsetbits: usize, buf: Vec<u8>, syn: Option<(PathBuf, bool)>,
+	syn_count: Option<usize>, collation: Collation, encoding: Encoding) -> Result<Idx>
+{
+	let width = match version {
+		Version::V300 if idxoffsetbits == 64 => 8,
+		_ => 4,
+	};
+	// in idx order, for the syn indexes
+	let mut starts = vec![];
+	let mut start = 0;
+	while start < buf.len() {
+		let end = buf[start..].iter()
+			.position(|&b| b == 0)
+			.map_or(buf.len(), |pos| start + pos);
+		if end + 1 + width > buf.len() {
+			return Err(Error::InvalidIdxElement("offset"));
+		}
+		if end + 1 + width + SIZE_WIDTH > buf.len() {
+			return Err(Error::InvalidIdxElement("size"));
+		}
+		starts.push(start);
+		start = end + 1 + width + SIZE_WIDTH;
+	}
+
+	let mut sorted = SortedItems { buf, records: vec![], width, encoding };
+	let mut warnings = vec![];
+	let mut records = Vec::with_capacity(starts.len());
+	for (index, start) in starts.iter().enumerate() {
+		let raw = sorted.record_at(*start);
+		if raw.word.is_empty() {
+			warnings.push(empty_word_warning(index, &raw));
+		} else {
+			records.push(*start);
+		}
+	}
+	// keys decoded while sorted, not kept, the idx order of most collations
+	// already sorted, stable for the records of a key in idx order
+	let key = |start: usize| collation.normalize(&sorted.word_at(start));
+	if records.windows(2).any(|pair| key(pair[0]) > key(pair[1])) {
+		records.sort_by_key(|start| key(*start));
+	}
+	sorted.records = records;
+
+	let (syn, synonyms) = if let Some((syn, gz)) = syn {
+		let (syn, synonyms) = load_syn(
+			syn,
+			gz,
+			syn_count,
+			|index| starts.get(index).map(|start| sorted.word_at(*start)),
+			|key| sorted.get(key, &collation).is_some(),
+			&collation,
+			encoding)?;
+		(Some(syn), Some(synonyms))
 	} else {
-		None
+		(None, None)
 	};
-	Ok(Idx { items, syn })
+	Ok(Idx { items: Items::Sorted(sorted), sorted: OnceLock::new(), syn, synonyms, collation, warnings })
 }
 
-fn read_items<F>(mut reader: impl BufRead, f: F) -> Result<Vec<IdxRawEntry>>
+impl SortedItems {
+	#[inline]
+	fn word_end(&self, start: usize) -> usize
+	{
+		self.buf[start..].iter()
+			.position(|&b| b == 0)
+			.map_or(self.buf.len(), |pos| start + pos)
+	}
+
+	#[inline]
+	fn word_at(&self, start: usize) -> String
+	{
+		self.encoding.decode(&self.buf[start..self.word_end(start)])
+	}
+
+	/// headword of the record at the sorted position
+	#[inline]
+	fn word(&self, pos: usize) -> String
+	{
+		self.word_at(self.records[pos])
+	}
+
+	fn record_at(&self, start: usize) -> IdxRawEntry
+	{
+		let end = self.word_end(start);
+		let word = self.encoding.decode(&self.buf[start..end]);
+		let offset = self.number(end + 1, self.width);
+		let size = self.number(end + 1 + self.width, SIZE_WIDTH);
+		IdxRawEntry { word, offset, size }
+	}
+
+	/// every record by normalized word
+	#[inline]
+	fn all(&self) -> impl Iterator<Item = IdxRawEntry> + '_
+	{
+		self.records.iter().map(|start| self.record_at(*start))
+	}
+
+	#[inline]
+	fn number(&self, start: usize, width: usize) -> usize
+	{
+		self.buf[start..start + width].iter()
+			.fold(0usize, |n, &b| (n << 8) | b as usize)
+	}
+
+	/// merge all records with the same normalized word, same as the map
+	fn get(&self, key: &str, collation: &Collation) -> Option<IdxEntry>
+	{
+		let view = SortedView::Records(self, collation);
+		let start = view.partition_point(|k| k < key);
+		let mut entry: Option<IdxEntry> = None;
+		for pos in start..self.records.len() {
+			let raw = self.record_at(self.records[pos]);
+			if collation.normalize(&raw.word) != key {
+				break;
+			}
+			entry.get_or_insert_with(|| IdxEntry { word: raw.word.clone(), blocks: vec![] })
+				.push_block(&raw.word, raw.offset, raw.size);
+		}
+		entry
+	}
+}
+
+/// page offsets from the .idx.oft next to the idx, a header starting with
+/// "StarDict's Cache" then little endian u32 offsets of every 32 entries
+/// and the idx size, None if missing or not matching the idx, warned if not matching
+fn read_offset_table(idx: &Path, wordcount: usize, idx_size: usize, warnings: &mut Vec<String>)
+	-> Option<Vec<usize>>
+{
+	let mut path = idx.as_os_str().to_owned();
+	path.push(".oft");
+	let buf = std::fs::read(PathBuf::from(path)).ok()?;
+	let count = wordcount.div_ceil(ENTRIES_PER_PAGE) + 1;
+	let header = buf.len().checked_sub(count * 4)?;
+	if wordcount == 0 || !buf[..header].starts_with(b"StarDict's Cache") {
+		return None;
+	}
+	let pages: Vec<usize> = buf[header..].chunks_exact(4)
+		.map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+		.collect();
+	if pages[0] != 0 || pages.last() != Some(&idx_size) || pages.windows(2).any(|w| w[0] >= w[1]) {
+		warnings.push("offset table not matching the idx, idx read whole".to_owned());
+		return None;
+	}
+	Some(pages)
+}
+
+fn read_paged(paged: PagedItems, syn: Option<(PathBuf, bool)>, syn_count: Option<usize>,
+	collation: Collation, encoding: Encoding) -> Result<Idx>
+{
+	let (syn, synonyms) = if let Some((syn, gz)) = syn {
+		let records = read_syn(syn, gz, syn_count, encoding)?;
+		// headwords of the syn indexes and the syn words found as headwords,
+		// in one pass over the pages instead of a read for every record
+		let indexes: HashSet<usize> = records.iter().map(|(_, index)| *index).collect();
+		let keys: HashSet<String> = records.iter().map(|(word, _)| collation.normalize(word)).collect();
+		let mut words = HashMap::new();
+		let mut found = HashSet::new();
+		paged.for_each(|index, raw| {
+			let key = collation.normalize(&raw.word);
+			if keys.contains(&key) {
+				found.insert(key);
+			}
+			if indexes.contains(&index) {
+				words.insert(index, raw.word);
+			}
+		});
+		let (syn, synonyms) = resolve_syn(
+			records,
+			|index| words.get(&index).cloned(),
+			|key| found.contains(key),
+			&collation);
+		(Some(syn), Some(synonyms))
+	} else {
+		(None, None)
+	};
+	Ok(Idx { items: Items::Paged(paged), sorted: OnceLock::new(), syn, synonyms, collation, warnings: vec![] })
+}
+
+impl PagedItems {
+	/// records of the page, None if failed reading
+	fn page(&self, page: usize) -> Option<Vec<IdxRawEntry>>
+	{
+		let start = *self.pages.get(page)?;
+		let end = *self.pages.get(page + 1)?;
+		let mut buf = vec![0; end - start];
+		let mut reader = self.reader.lock().unwrap_or_else(PoisonError::into_inner);
+		if let Err(e) = reader.seek(SeekFrom::Start(start as u64))
+			.and_then(|_| reader.read_exact(&mut buf)) {
+			drop(reader);
+			self.warn(page, format!("failed read idx page {}: {}", page, e));
+			return None;
+		}
+		drop(reader);
+		let width = self.width;
+		let records = read_items(buf.as_slice(), self.encoding, |r| {
+			let mut b = [0; 8];
+			r.read_exact(&mut b[..width])?;
+			Ok(b[..width].iter().fold(0usize, |n, &b| (n << 8) | b as usize))
+		});
+		match records {
+			Ok(records) => {
+				let mut warnings = self.warnings.lock().unwrap_or_else(PoisonError::into_inner);
+				for (i, raw) in records.iter().enumerate().filter(|(_, raw)| raw.word.is_empty()) {
+					let index = page * ENTRIES_PER_PAGE + i;
+					warnings.entry(index).or_insert_with(|| empty_word_warning(index, raw));
+				}
+				Some(records)
+			}
+			Err(e) => {
+				self.warn(page, format!("invalid idx page {}: {}", page, e));
+				None
+			}
+		}
+	}
+
+	#[inline]
+	fn warn(&self, page: usize, warning: String)
+	{
+		self.warnings.lock().unwrap_or_else(PoisonError::into_inner)
+			.insert(page * ENTRIES_PER_PAGE, warning);
+	}
+
+	/// f called with the index and record of every entry, page by page
+	fn for_each(&self, mut f: impl FnMut(usize, IdxRawEntry))
+	{
+		for page in 0..self.pages.len() - 1 {
+			for (i, raw) in self.page(page).unwrap_or_default().into_iter().enumerate() {
+				f(page * ENTRIES_PER_PAGE + i, raw);
+			}
+		}
+	}
+
+	fn all(&self) -> Vec<IdxRawEntry>
+	{
+		(0..self.pages.len() - 1).filter_map(|page| self.page(page)).flatten().collect()
+	}
+
+	/// binary search the pages in the stardict_strcmp order, ASCII lowercased
+	/// bytes first, words of the ascii lower key are next to each other
+	fn get(&self, key: &str, collation: &Collation) -> Option<IdxEntry>
+	{
+		let mut entry: Option<IdxEntry> = None;
+		let mut push = |raw: IdxRawEntry| if collation.normalize(&raw.word) == key {
+			entry.get_or_insert_with(|| IdxEntry { word: raw.word.clone(), blocks: vec![] })
+				.push_block(&raw.word, raw.offset, raw.size);
+		};
+		let target = key.to_ascii_lowercase();
+		let lower = |word: &str| word.to_ascii_lowercase();
+		// pages with the first word before the key, the key may start in the last of them
+		let (mut low, mut high) = (0, self.pages.len() - 1);
+		while low < high {
+			let middle = (low + high) / 2;
+			let first = self.page(middle)?.into_iter().next()?;
+			if lower(&first.word) < target {
+				low = middle + 1;
+			} else {
+				high = middle;
+			}
+		}
+		for page in low.saturating_sub(1)..self.pages.len() - 1 {
+			for raw in self.page(page)? {
+				match lower(&raw.word).cmp(&target) {
+					Ordering::Less => {}
+					Ordering::Equal => push(raw),
+					Ordering::Greater => return entry,
+				}
+			}
+		}
+		entry
+	}
+}
+
+fn read_items<F>(mut reader: impl BufRead, encoding: Encoding, f: F) -> Result<Vec<IdxRawEntry>>
 	where F: Fn(&mut dyn BufRead) -> std::io::Result<usize>
 {
 	let mut items = vec![];
@@ -132,22 +989,39 @@ fn read_items<F>(mut reader: impl BufRead, f: F) -> Result<Vec<IdxRawEntry>>
 			buf.pop();
 		}
 
-		let word = buf_to_string(&buf);
+		let word = encoding.decode(&buf);
 		let offset: usize = f(&mut reader).map_err(|_| Error::InvalidIdxElement("offset"))?;
-		let size: usize = f(&mut reader).map_err(|_| Error::InvalidIdxElement("size"))?;
+		// size is 32 bits even with 64 bits offset
+		let size = reader.read_u32::<BigEndian>().map_err(|_| Error::InvalidIdxElement("size"))? as usize;
 
 		items.push(IdxRawEntry { word, offset, size })
 	}
 	Ok(items)
 }
 
-fn load_syn(vec: &Vec<IdxRawEntry>, syn: PathBuf, items: &HashMap<String, IdxEntry>) -> Result<HashMap<String, HashSet<String>>>
+/// aliases by normalized word, and synonyms of every normalized headword,
+/// number of records must be count if given, duplicated records counted
+#[allow(clippy::type_complexity)]
+fn load_syn<W, C>(syn: PathBuf, gz: bool, count: Option<usize>, word_at: W, contains: C, collation: &Collation,
+	encoding: Encoding) -> Result<(HashMap<String, HashSet<String>>, HashMap<String, Vec<String>>)>
+	where W: Fn(usize) -> Option<String>, C: Fn(&str) -> bool
+{
+	let records = read_syn(syn, gz, count, encoding)?;
+	Ok(resolve_syn(records, word_at, contains, collation))
+}
+
+/// word and idx index of every syn record
+fn read_syn(syn: PathBuf, gz: bool, count: Option<usize>, encoding: Encoding) -> Result<Vec<(String, usize)>>
 {
 	let file = File::open(syn)
 		.map_err(|e| Error::FailedOpenFile("syn", e))?;
-	let mut reader = BufReader::new(file);
+	let mut reader: Box<dyn BufRead> = if gz {
+		Box::new(BufReader::new(MultiGzDecoder::new(file)))
+	} else {
+		Box::new(BufReader::new(file))
+	};
 
-	let mut syn = HashMap::new();
+	let mut records = vec![];
 	loop {
 		let mut buf = vec![];
 
@@ -160,31 +1034,376 @@ fn load_syn(vec: &Vec<IdxRawEntry>, syn: PathBuf, items: &HashMap<String, IdxEnt
 			break;
 		}
 
-		let word = buf_to_string(&buf);
+		let word = encoding.decode(&buf);
 
 		let mut b = [0; 4];
-		if let Err(_) = reader.read(&mut b) {
+		if reader.read_exact(&mut b).is_err() {
 			return Err(Error::InvalidSynIndex(word));
 		}
+		records.push((word, u32::from_be_bytes(b) as usize));
+	}
+	if let Some(expected) = count {
+		if records.len() != expected {
+			return Err(Error::SynCountMismatch { expected, actual: records.len() });
+		}
+	}
+	Ok(records)
+}
 
-		if !word.is_empty() {
-			let index = u32::from_be_bytes(b) as usize;
-			let lowercase_word = word.to_lowercase();
-			if let Some(raw) = vec.get(index) {
-				let alias = syn.entry(lowercase_word)
+/// records of empty word skipped
+#[allow(clippy::type_complexity)]
+fn resolve_syn<W, C>(records: Vec<(String, usize)>, word_at: W, contains: C, collation: &Collation)
+	-> (HashMap<String, HashSet<String>>, HashMap<String, Vec<String>>)
+	where W: Fn(usize) -> Option<String>, C: Fn(&str) -> bool
+{
+	let mut syn = HashMap::new();
+	let mut synonyms: HashMap<String, Vec<String>> = HashMap::new();
+	for (word, index) in records {
+		if word.is_empty() {
+			continue;
+		}
+		let lowercase_word = collation.normalize(&word);
+		if let Some(raw_word) = word_at(index) {
+			let alias = syn.entry(lowercase_word)
+				.or_insert(HashSet::new());
+			alias.insert(collation.normalize(&raw_word));
+			let list = synonyms.entry(collation.normalize(&raw_word)).or_default();
+			if !list.contains(&word) {
+				list.push(word.clone());
+			}
+
+			// setup the reverse alias if the alias exists in items
+			let items_lowercase_key = collation.normalize(&word);
+			if contains(&items_lowercase_key) {
+				let alias = syn.entry(collation.normalize(&raw_word))
 					.or_insert(HashSet::new());
-				alias.insert(raw.word.to_lowercase());
-
-				// setup the reverse alias if the alias exists in items
-				let items_lowercase_key = word.to_lowercase();
-				if items.contains_key(&items_lowercase_key) {
-					let alias = syn.entry(raw.word.to_lowercase())
-						.or_insert(HashSet::new());
-					alias.insert(items_lowercase_key);
-				}
+				alias.insert(items_lowercase_key);
+			}
+		}
+	}
+	(syn, synonyms)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+	use std::path::{Path, PathBuf};
+	use crate::collation::{Collation, Normalizer};
+	use crate::error::Error;
+	use crate::idx::{Idx, Items, ENTRIES_PER_PAGE};
+	use crate::ifo::Ifo;
+	use crate::opener::OpenOptions;
+	use crate::{no_cache, StarDict};
+	use crate::tests::{assert_synonyms_of, create_dict, SAMPLE_WORDS, SYNONYMS};
+
+	/// offset table of a 32 bits idx as stardict writes it
+	fn write_offset_table(idx: &Path, oft: &Path)
+	{
+		let buf = fs::read(idx).unwrap();
+		let mut starts = vec![];
+		let mut start = 0;
+		while start < buf.len() {
+			starts.push(start);
+			start += buf[start..].iter().position(|&b| b == 0).unwrap() + 1 + 8;
+		}
+		let mut table = b"StarDict's Cache, Version: 0.2".to_vec();
+		for start in starts.iter().step_by(ENTRIES_PER_PAGE).chain(Some(&buf.len())) {
+			table.extend_from_slice(&(*start as u32).to_le_bytes());
+		}
+		fs::write(oft, table).unwrap();
+	}
+
+	#[inline]
+	fn open(ifo: &Path) -> Idx
+	{
+		open_with(ifo, &OpenOptions::default())
+	}
+
+	fn open_with(ifo: &Path, options: &OpenOptions) -> Idx
+	{
+		Idx::new(ifo.with_extension("idx"), &Ifo::new(ifo.to_path_buf()).unwrap(), false,
+			Some(ifo.with_extension("syn")).filter(|syn| syn.exists()), false, options).unwrap()
+	}
+
+	#[test]
+	fn sorted() {
+		let ifo = create_dict("idx_sorted", &[("Banana", "b"), ("apple", "a"), ("Apple", "A"), ("cherry", "c"),
+			("äpfel", "ä"), ("apricot", "r")], &[]);
+		let idx = open(&ifo);
+		// not built before used
+		assert!(idx.sorted.get().is_none());
+		let sorted = idx.sorted_range(..);
+		assert!(sorted.windows(2).all(|pair| pair[0].0 < pair[1].0));
+		// apple and Apple share one key
+		assert_eq!(sorted.len(), idx.items().len());
+		assert_eq!(sorted.len(), 5);
+		let keys = |entries: &[(String, String)]| entries.iter().map(|(key, _)| key.clone()).collect::<Vec<_>>();
+		let low_memory = open_with(&ifo, &OpenOptions { low_memory: true, ..OpenOptions::default() });
+		// records binary searched by key, sorted other than the idx
+		let reversed = Collation::Custom(Normalizer::new("reversed",
+			|word: &str| word.to_lowercase().chars().rev().collect()));
+		let idx_reversed = open_with(&ifo, &OpenOptions { low_memory: true, collation: reversed, ..OpenOptions::default() });
+		let Items::Sorted(records) = &idx_reversed.items else { panic!("not low memory") };
+		let words: Vec<String> = (0..records.records.len()).map(|pos| records.word(pos)).collect();
+		assert_eq!(words, ["Banana", "apple", "Apple", "äpfel", "apricot", "cherry"]);
+		assert_eq!(idx_reversed.offsets_of("APPLE", false).unwrap().len(), 2);
+		assert_eq!(idx_reversed.search_prefix("ple", 0), ["apple"]);
+		for idx in [idx, low_memory] {
+			// both cased headwords of the apple key
+			assert_eq!(idx.words(), ["apple", "Apple", "apricot", "Banana", "cherry", "äpfel"]);
+			assert_eq!(idx.neighbors("apricot", 2, 2), ["apple", "Banana", "cherry"]);
+			assert_eq!(idx.neighbors("äpfel", 9, 0), ["apple", "apricot", "Banana", "cherry"]);
+			assert_eq!(idx.search_prefix("AP", 0), ["apple", "apricot"]);
+			assert_eq!(idx.lookup_exact("Apple").unwrap().blocks.len(), 1);
+			assert_eq!(idx.offsets_of("APPLE", false).unwrap().len(), 2);
+			assert_eq!(keys(&idx.sorted_range("apple".."banana")), ["apple", "apricot"]);
+			assert_eq!(keys(&idx.sorted_range("apple"..="banana")), ["apple", "apricot", "banana"]);
+			assert_eq!(keys(&idx.sorted_range("c"..)), ["cherry", "äpfel"]);
+			assert!(idx.sorted_range("z".."a").is_empty());
+		}
+	}
+
+	#[test]
+	fn empty_word_warnings() {
+		let ifo = create_dict("empty_word_warnings", &[("apple", "a round fruit"), ("banana", "a long fruit")], &[]);
+		assert!(open(&ifo).warnings().is_empty());
+		// an entry with empty word before banana
+		let idx_path = ifo.with_extension("idx");
+		let mut buf = fs::read(&idx_path).unwrap();
+		let start = buf.windows(6).position(|w| w == b"banana").unwrap();
+		buf.splice(start..start, [0, 0, 0, 0, 13, 0, 0, 0, 12]);
+		fs::write(&idx_path, &buf).unwrap();
+		let content = fs::read_to_string(&ifo).unwrap()
+			.replace("wordcount=2", "wordcount=3")
+			.replace(&format!("idxfilesize={}", buf.len() - 9), &format!("idxfilesize={}", buf.len()));
+		fs::write(&ifo, content).unwrap();
+		for low_memory in [false, true] {
+			let options = OpenOptions { low_memory, ..OpenOptions::default() };
+			let idx = Idx::new(idx_path.clone(), &Ifo::new(ifo.clone()).unwrap(), false, None, false, &options).unwrap();
+			assert_eq!(idx.warnings(), ["idx entry 1 with empty headword skipped, block at 13 of 12 bytes"]);
+			// still skipped
+			assert_eq!(idx.words(), ["apple", "banana"]);
+		}
+
+		let idx_path = ifo.with_extension("idx");
+		let mut oft = idx_path.as_os_str().to_owned();
+		oft.push(".oft");
+		write_offset_table(&idx_path, &PathBuf::from(oft));
+		let options = OpenOptions { paged: true, collation: Collation::AsciiLower, ..OpenOptions::default() };
+		let idx = open_with(&ifo, &options);
+		assert!(matches!(idx.items, Items::Paged(_)));
+		// page not read yet
+		assert!(idx.warnings().is_empty());
+		assert!(idx.offsets_of("banana", false).is_some());
+		assert_eq!(idx.warnings(), ["idx entry 1 with empty headword skipped, block at 13 of 12 bytes"]);
+		assert_eq!(idx.words(), ["apple", "banana"]);
+		assert_eq!(idx.warnings().len(), 1);
+	}
+
+	#[test]
+	fn offset_table() {
+		let fillers: Vec<String> = (0..31).map(|i| format!("a{:02}", i))
+			.chain((0..41).map(|i| format!("m{:02}", i)))
+			.collect();
+		let mut words: Vec<(&str, &str)> = fillers.iter().map(|word| (word.as_str(), "filler")).collect();
+		// cased pair across the first page boundary
+		words.extend([("Kiwi", "a bird"), ("kiwi", "a fruit"), ("Ärger", "trouble")]);
+		let ifo = create_dict("offset_table", &words, &[("kiwifruit", "kiwi")]);
+		let idx_path = ifo.with_extension("idx");
+		let mut oft = idx_path.as_os_str().to_owned();
+		oft.push(".oft");
+		let oft = PathBuf::from(oft);
+		let ascii_lower = OpenOptions { collation: Collation::AsciiLower, ..OpenOptions::default() };
+		let full = open_with(&ifo, &ascii_lower);
+		write_offset_table(&idx_path, &oft);
+		// only when asked
+		assert!(matches!(open_with(&ifo, &ascii_lower).items, Items::Map(_)));
+		// other collations not binary searched, parsed fully
+		let lowercase = open_with(&ifo, &OpenOptions { paged: true, ..OpenOptions::default() });
+		assert!(matches!(lowercase.items, Items::Map(_)));
+		assert_eq!(lowercase.warnings(), ["paged idx needs the ascii lower collation, idx read whole"]);
+		let paged_options = OpenOptions { paged: true, ..ascii_lower };
+		let paged = open_with(&ifo, &paged_options);
+		assert!(matches!(paged.items, Items::Paged(_)));
+
+		for word in ["KIWI", "a00", "a30", "m40", "Ärger", "ärger", "Kiwifruit", "cherry", "zzz"] {
+			assert_eq!(paged.offsets_of(word, true), full.offsets_of(word, true), "{}", word);
+		}
+		assert_eq!(paged.offsets_of("kiwi", false).unwrap().len(), 2);
+		assert_eq!(paged.synonyms_of("kiwi"), ["kiwifruit"]);
+		assert_eq!(paged.words(), full.words());
+		assert_eq!(paged.blocks(), full.blocks());
+
+		// not matching the idx, parsed fully
+		let mut table = fs::read(&oft).unwrap();
+		let len = table.len();
+		table[len - 4] += 1;
+		fs::write(&oft, table).unwrap();
+		let mismatched = open_with(&ifo, &paged_options);
+		assert!(matches!(mismatched.items, Items::Map(_)));
+		assert_eq!(mismatched.warnings(), ["offset table not matching the idx, idx read whole"]);
+	}
+
+	#[test]
+	fn idx_gz_members() {
+		use std::io::Write;
+		use flate2::Compression;
+		use flate2::write::GzEncoder;
+
+		let ifo = create_dict("idx_gz_members", &SAMPLE_WORDS, &SYNONYMS);
+		let idx = fs::read(ifo.with_extension("idx")).unwrap();
+		fs::remove_file(ifo.with_extension("idx")).unwrap();
+		// second member starts in the middle of an entry
+		let mut gz = vec![];
+		for part in [&idx[..7], &idx[7..]] {
+			let mut encoder = GzEncoder::new(vec![], Compression::default());
+			encoder.write_all(part).unwrap();
+			gz.extend(encoder.finish().unwrap());
+		}
+		fs::write(ifo.with_extension("idx.gz"), &gz).unwrap();
+		for dict in [no_cache(&ifo).unwrap(), crate::no_cache_low_memory(&ifo).unwrap()] {
+			assert_eq!(dict.words().unwrap().len(), SAMPLE_WORDS.len());
+			for (word, definition) in SAMPLE_WORDS {
+				assert_eq!(dict.lookup(word).unwrap().unwrap()[0].segments[0].text, definition);
 			}
+			assert!(dict.lookup("pomme").unwrap().is_some());
 		}
+
+		// truncated in the last member
+		fs::write(ifo.with_extension("idx.gz"), &gz[..gz.len() - 12]).unwrap();
+		assert!(matches!(no_cache(&ifo), Err(Error::FailedOpenFile("idx", _))));
+		assert!(matches!(crate::no_cache_low_memory(&ifo), Err(Error::FailedOpenFile("idx", _))));
 	}
 
-	Ok(syn)
+	#[test]
+	fn idx_size() {
+		use crate::no_cache_lenient;
+		let ifo = create_dict("idx_size", &SAMPLE_WORDS, &[]);
+		// drop the last entry, "Band" with null, offset and size
+		let idx_path = ifo.with_extension("idx");
+		let idx = fs::read(&idx_path).unwrap();
+		let last = idx.len() - "Band".len() - 9;
+		fs::write(&idx_path, &idx[..last]).unwrap();
+
+		assert!(matches!(no_cache(&ifo),
+			Err(Error::IdxSizeMismatch { expected, actual }) if expected == idx.len() && actual == last));
+		let dict = no_cache_lenient(&ifo).unwrap();
+		assert!(dict.lookup("apple").unwrap().is_some());
+		assert!(dict.lookup("band").unwrap().is_none());
+	}
+
+	#[test]
+	fn syn_count() {
+		use crate::no_cache_lenient;
+		let ifo = create_dict("syn_count", &SAMPLE_WORDS, &SYNONYMS);
+		// drop the last record, "malus" with null and index
+		let syn_path = ifo.with_extension("syn");
+		let syn = fs::read(&syn_path).unwrap();
+		fs::write(&syn_path, &syn[..syn.len() - "malus".len() - 5]).unwrap();
+
+		assert!(matches!(no_cache(&ifo),
+			Err(Error::SynCountMismatch { expected, actual }) if expected == SYNONYMS.len() && actual == SYNONYMS.len() - 1));
+		assert!(matches!(crate::no_cache_low_memory(&ifo), Err(Error::SynCountMismatch { .. })));
+		let dict = no_cache_lenient(&ifo).unwrap();
+		assert!(dict.lookup("pomme").unwrap().is_some());
+		assert!(dict.lookup("malus").unwrap().is_none());
+	}
+
+	#[test]
+	fn offset_64_bits() {
+		let ifo = create_dict("offset_64_bits", &SAMPLE_WORDS, &[("pomme", "Apple")]);
+		let mut words = SAMPLE_WORDS.to_vec();
+		words.sort_by_key(|(word, _)| word.to_lowercase());
+		let mut idx = vec![];
+		let mut offset = 0u64;
+		for (word, definition) in &words {
+			idx.extend_from_slice(word.as_bytes());
+			idx.push(0);
+			idx.extend_from_slice(&offset.to_be_bytes());
+			idx.extend_from_slice(&(definition.len() as u32).to_be_bytes());
+			offset += definition.len() as u64;
+		}
+		fs::write(ifo.with_extension("idx"), &idx).unwrap();
+		let content = fs::read_to_string(&ifo).unwrap()
+			.replace("version=2.4.2", "version=3.0.0\nidxoffsetbits=64")
+			.replace(&format!("idxfilesize={}", idx.len() - 4 * words.len()),
+				&format!("idxfilesize={}", idx.len()));
+		fs::write(&ifo, content).unwrap();
+
+		for dict in [no_cache(&ifo).unwrap(), crate::no_cache_low_memory(&ifo).unwrap()] {
+			for (word, definition) in &words {
+				let definitions = dict.lookup(word).unwrap().unwrap();
+				assert_eq!(definitions[0].segments[0].text, *definition);
+			}
+			let definitions = dict.lookup("pomme").unwrap().unwrap();
+			assert_eq!(definitions[0].word, "Apple");
+		}
+	}
+
+	#[test]
+	fn offset_bits_mismatch() {
+		let words = [("apple", "a round fruit"), ("banana", "a long curved fruit"), ("cherry", "a small red fruit")];
+		let ifo = create_dict("offset_bits_mismatch", &words, &[]);
+		// 32 bits offsets read as 64 bits, offset and size taken as the offset,
+		// the first 4 bytes of the next word as the size
+		let mut idx = vec![];
+		let mut offset = 0u32;
+		for (word, definition) in &words {
+			idx.extend_from_slice(word.as_bytes());
+			idx.push(0);
+			idx.extend_from_slice(&offset.to_be_bytes());
+			idx.extend_from_slice(&(definition.len() as u32).to_be_bytes());
+			offset += definition.len() as u32;
+		}
+		// size of the last one
+		idx.extend_from_slice(&[0; 4]);
+		fs::write(ifo.with_extension("idx"), &idx).unwrap();
+		let content = fs::read_to_string(&ifo).unwrap()
+			.replace("version=2.4.2", "version=3.0.0\nidxoffsetbits=64")
+			.replace(&format!("idxfilesize={}", idx.len() - 4), &format!("idxfilesize={}", idx.len()));
+		fs::write(&ifo, content).unwrap();
+		match no_cache(&ifo) {
+			Err(Error::InvalidIdxElement(msg)) => assert!(msg.contains("idxoffsetbits")),
+			Err(e) => panic!("unexpected error: {:?}", e),
+			Ok(_) => panic!("offsets out of range not reported"),
+		}
+		// warned only
+		let dict = crate::no_cache_lenient(&ifo).unwrap();
+		assert!(dict.contains("apple").unwrap());
+		assert_eq!(dict.idx_warnings(), ["3 of 3 idx offsets out of the dict, check idxoffsetbits"]);
+	}
+
+	#[test]
+	fn compressed_syn() {
+		use std::io::Write;
+		use flate2::Compression;
+		use flate2::write::GzEncoder;
+		let ifo = create_dict("compressed_syn", &SAMPLE_WORDS, &SYNONYMS);
+		let syn_path = ifo.with_extension("syn");
+		let syn = fs::read(&syn_path).unwrap();
+		fs::remove_file(&syn_path).unwrap();
+
+		let mut encoder = GzEncoder::new(vec![], Compression::default());
+		encoder.write_all(&syn).unwrap();
+		fs::write(ifo.with_extension("syn.gz"), encoder.finish().unwrap()).unwrap();
+		let dict = no_cache(&ifo).unwrap();
+		assert_eq!(dict.lookup("pomme").unwrap().unwrap()[0].word, "Apple");
+		assert_synonyms_of(&dict, true);
+		fs::remove_file(ifo.with_extension("syn.gz")).unwrap();
+
+		let mut dz = vec![];
+		crate::DictZipWriter::new(8).write(&syn, &mut dz).unwrap();
+		fs::write(ifo.with_extension("syn.dz"), &dz).unwrap();
+		let dict = crate::no_cache_low_memory(&ifo).unwrap();
+		assert_eq!(dict.lookup("malus").unwrap().unwrap()[0].word, "Apple");
+		assert_synonyms_of(&dict, true);
+
+		// explicit files, compressed by the gzip magic, not the name
+		let renamed = ifo.with_extension("synonyms");
+		fs::rename(ifo.with_extension("syn.dz"), &renamed).unwrap();
+		let dict = crate::no_cache_from(&ifo, ifo.with_extension("idx"), ifo.with_extension("dict"),
+			Some(renamed)).unwrap();
+		assert_eq!(dict.lookup("malus").unwrap().unwrap()[0].word, "Apple");
+		assert_synonyms_of(&dict, true);
+	}
 }
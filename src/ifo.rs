@@ -1,15 +1,26 @@
 use crate::error::{Error, Result};
 
+use std::collections::HashMap;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::path::PathBuf;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
 
-#[derive(Clone,Debug)]
+const MAGIC: &str = "StarDict's dict ifo file";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Version {
 	V242,
 	V300,
 }
 
+/// meaning of dicttype in ifo
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DictType {
+	None,
+	WordNet,
+	Other(String),
+}
+
 /// bookname=      // required
 /// wordcount=     // required
 /// synwordcount=  // required if ".syn" file exists.
@@ -18,11 +29,11 @@ pub enum Version {
 /// author=
 /// email=
 /// website=
-/// description=	// You can use <br> for new line.
+/// description=  // You can use <br> for new line.
 /// date=
 /// sametypesequence= // very important.
 /// dicttype=
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Ifo {
 	pub version: Version,
 	pub bookname: String,
@@ -37,11 +48,25 @@ pub struct Ifo {
 	pub date: String,
 	pub sametypesequence: String,
 	pub dicttype: String,
+	// unrecognized keys
+	extra: HashMap<String, String>,
 }
 
 #[allow(unused)]
 impl Ifo {
+	/// the first non-empty line must be the magic
+	#[inline]
 	pub fn new(path: PathBuf) -> Result<Ifo> {
+		Self::parse(path, false)
+	}
+
+	/// for files without the magic line
+	#[inline]
+	pub fn new_lenient(path: PathBuf) -> Result<Ifo> {
+		Self::parse(path, true)
+	}
+
+	fn parse(path: PathBuf, lenient: bool) -> Result<Ifo> {
 		let mut ifo = Ifo {
 			version: Version::V242,
 			bookname: String::new(),
@@ -56,17 +81,37 @@ impl Ifo {
 			date: String::new(),
 			sametypesequence: String::new(),
 			dicttype: String::new(),
+			extra: HashMap::new(),
 		};
 
 		let lines = BufReader::new(
 			File::open(path)
 				.map_err(|e| Error::FailedOpenFile("ifo", e))?)
 			.lines();
-		for line in lines {
+		let mut magic_checked = lenient;
+		for (i, line) in lines.enumerate() {
 			let line = line.map_err(|e| Error::FailedOpenFile("ifo", e))?;
+			// files from windows may have BOM and CRLF
+			let line = if i == 0 {
+				line.trim_start_matches('\u{feff}')
+			} else {
+				&line
+			};
+			let line = line.trim_end_matches('\r');
+			if !magic_checked {
+				if line.trim().is_empty() {
+					continue;
+				}
+				if line.trim_end() != MAGIC {
+					return Err(Error::InvalidIfoMagic);
+				}
+				magic_checked = true;
+				continue;
+			}
 			if let Some(id) = line.find('=') {
-				let key = &line[..id];
-				let val = String::from(&line[id + 1..]);
+				// spaces around = of hand edited ifo, inner ones kept
+				let key = line[..id].trim();
+				let val = String::from(line[id + 1..].trim());
 				match key {
 					"version" =>
 						match val.as_str() {
@@ -93,10 +138,169 @@ impl Ifo {
 					"date" => ifo.date = val,
 					"sametypesequence" => ifo.sametypesequence = val,
 					"dicttype" => ifo.dicttype = val,
-					_ => (),
+					_ => { ifo.extra.insert(key.to_owned(), val); }
 				};
 			}
 		}
+		if !magic_checked {
+			return Err(Error::InvalidIfoMagic);
+		}
 		Ok(ifo)
 	}
+
+	/// dicttype parsed, None if not set
+	pub fn dict_type(&self) -> DictType
+	{
+		let dicttype = self.dicttype.trim();
+		if dicttype.is_empty() {
+			DictType::None
+		} else if dicttype.eq_ignore_ascii_case("wordnet") {
+			DictType::WordNet
+		} else {
+			DictType::Other(dicttype.to_owned())
+		}
+	}
+
+	/// keys not in the spec, kept for writing back
+	#[inline]
+	pub fn extra(&self) -> &HashMap<String, String>
+	{
+		&self.extra
+	}
+
+	/// magic, version and fields in the order of the spec, empty optional
+	/// fields omitted, then extra keys sorted
+	pub fn write(&self, path: &Path) -> Result<()>
+	{
+		let mut ifo = format!("{}\n", MAGIC);
+		let version = match self.version {
+			Version::V242 => "2.4.2",
+			Version::V300 => "3.0.0",
+		};
+		ifo.push_str(&format!("version={}\nbookname={}\nwordcount={}\n", version, self.bookname, self.wordcount));
+		if self.synwordcount > 0 {
+			ifo.push_str(&format!("synwordcount={}\n", self.synwordcount));
+		}
+		ifo.push_str(&format!("idxfilesize={}\n", self.idxfilesize));
+		if self.idxoffsetbits != 32 {
+			ifo.push_str(&format!("idxoffsetbits={}\n", self.idxoffsetbits));
+		}
+		for (key, value) in [
+			("author", &self.author),
+			("email", &self.email),
+			("website", &self.website),
+			("description", &self.description),
+			("date", &self.date),
+			("sametypesequence", &self.sametypesequence),
+			("dicttype", &self.dicttype),
+		] {
+			if !value.is_empty() {
+				ifo.push_str(&format!("{}={}\n", key, value));
+			}
+		}
+		let mut extra: Vec<(&String, &String)> = self.extra.iter().collect();
+		extra.sort();
+		for (key, value) in extra {
+			ifo.push_str(&format!("{}={}\n", key, value));
+		}
+		File::create(path)
+			.and_then(|mut file| file.write_all(ifo.as_bytes()))
+			.map_err(|e| Error::FailedWriteFile("ifo", e))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+	use crate::dictzip::tests::temp_file;
+	use crate::error::Error;
+	use crate::ifo::{DictType, Ifo, Version};
+
+	#[test]
+	fn extra() {
+		let path = temp_file("extra.ifo");
+		fs::write(&path, "StarDict's dict ifo file\nversion=2.4.2\nbookname=extra\n\
+			wordcount=1\nidxfilesize=10\nfoo=bar\nlang=en=fr\n").unwrap();
+		let ifo = Ifo::new(path).unwrap();
+		assert_eq!(ifo.bookname, "extra");
+		assert_eq!(ifo.extra().len(), 2);
+		assert_eq!(ifo.extra().get("foo").map(String::as_str), Some("bar"));
+		assert_eq!(ifo.extra().get("lang").map(String::as_str), Some("en=fr"));
+	}
+
+	#[test]
+	fn spaced() {
+		let path = temp_file("spaced.ifo");
+		fs::write(&path, "StarDict's dict ifo file\nversion = 3.0.0\nbookname = My  Dict \n\
+			wordcount =1\n idxfilesize= 10\t\nfoo bar = a b\n").unwrap();
+		let ifo = Ifo::new(path).unwrap();
+		assert_eq!(ifo.version, Version::V300);
+		assert_eq!(ifo.bookname, "My  Dict");
+		assert_eq!(ifo.wordcount, 1);
+		assert_eq!(ifo.idxfilesize, 10);
+		assert_eq!(ifo.extra().get("foo bar").map(String::as_str), Some("a b"));
+	}
+
+	#[test]
+	fn magic() {
+		let path = temp_file("magic.ifo");
+		fs::write(&path, "\nStarDict's dict ifo file\nversion=2.4.2\nbookname=magic\n").unwrap();
+		assert_eq!(Ifo::new(path).unwrap().bookname, "magic");
+
+		let path = temp_file("wrong_magic.ifo");
+		fs::write(&path, "some other file\nversion=2.4.2\nbookname=wrong\n").unwrap();
+		assert!(matches!(Ifo::new(path.clone()), Err(Error::InvalidIfoMagic)));
+		assert_eq!(Ifo::new_lenient(path).unwrap().bookname, "wrong");
+
+		let path = temp_file("empty.ifo");
+		fs::write(&path, "").unwrap();
+		assert!(matches!(Ifo::new(path), Err(Error::InvalidIfoMagic)));
+	}
+
+	#[test]
+	fn crlf_bom() {
+		let path = temp_file("crlf_bom.ifo");
+		fs::write(&path, "\u{feff}StarDict's dict ifo file\r\nversion=3.0.0\r\nbookname=windows\r\n\
+			wordcount=1\r\nidxfilesize=10\r\n").unwrap();
+		let ifo = Ifo::new(path).unwrap();
+		assert!(matches!(ifo.version, Version::V300));
+		assert_eq!(ifo.bookname, "windows");
+		assert_eq!(ifo.idxfilesize, 10);
+		assert!(ifo.extra().is_empty());
+	}
+
+	#[test]
+	fn write() {
+		let path = temp_file("write.ifo");
+		fs::write(&path, "StarDict's dict ifo file\nversion=3.0.0\nbookname=write\nwordcount=3\n\
+			synwordcount=1\nidxfilesize=42\nidxoffsetbits=64\nauthor=someone\n\
+			description=line<br>line\nsametypesequence=m\ndicttype=wordnet\nlang=en=fr\nfoo=bar\n").unwrap();
+		let mut ifo = Ifo::new(path.clone()).unwrap();
+		ifo.author = "another".to_owned();
+		let written = temp_file("write_back.ifo");
+		ifo.write(&written).unwrap();
+		assert_eq!(Ifo::new(written.clone()).unwrap(), ifo);
+		assert_eq!(fs::read_to_string(&written).unwrap(), "StarDict's dict ifo file\nversion=3.0.0\n\
+			bookname=write\nwordcount=3\nsynwordcount=1\nidxfilesize=42\nidxoffsetbits=64\nauthor=another\n\
+			description=line<br>line\nsametypesequence=m\ndicttype=wordnet\nfoo=bar\nlang=en=fr\n");
+
+		// empty optional fields omitted
+		fs::write(&path, "StarDict's dict ifo file\nversion=2.4.2\nbookname=plain\nwordcount=1\nidxfilesize=10\n").unwrap();
+		let ifo = Ifo::new(path).unwrap();
+		ifo.write(&written).unwrap();
+		assert_eq!(fs::read_to_string(&written).unwrap(), "StarDict's dict ifo file\nversion=2.4.2\n\
+			bookname=plain\nwordcount=1\nidxfilesize=10\n");
+		assert_eq!(Ifo::new(written).unwrap(), ifo);
+	}
+
+	#[test]
+	fn dict_type() {
+		let path = temp_file("dict_type.ifo");
+		fs::write(&path, "StarDict's dict ifo file\nversion=2.4.2\nbookname=wordnet\ndicttype=wordnet\n").unwrap();
+		assert_eq!(Ifo::new(path.clone()).unwrap().dict_type(), DictType::WordNet);
+		fs::write(&path, "StarDict's dict ifo file\nversion=2.4.2\nbookname=other\ndicttype=mdict\n").unwrap();
+		assert_eq!(Ifo::new(path.clone()).unwrap().dict_type(), DictType::Other("mdict".to_owned()));
+		fs::write(&path, "StarDict's dict ifo file\nversion=2.4.2\nbookname=none\n").unwrap();
+		assert_eq!(Ifo::new(path).unwrap().dict_type(), DictType::None);
+	}
 }
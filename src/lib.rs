@@ -1,25 +1,64 @@
 pub mod error;
+mod collation;
+mod encoding;
 mod stardict;
+mod stardict_mem;
 mod idx;
 mod ifo;
 mod dict;
 mod dictzip;
+mod writer;
+mod search;
+mod group;
+mod xdxf;
+mod plain;
+mod mime;
+mod resource;
+mod shard;
+mod opener;
+mod cached_lookup;
+#[cfg(all(feature = "mmap", unix))]
+mod mmap;
+#[cfg(all(feature = "mmap", not(unix)))]
+compile_error!("the mmap feature maps the dict with libc, only supported on unix");
+#[cfg(feature = "async")]
+mod async_dict;
 #[cfg(feature = "sled")]
 mod stardict_sled;
 #[cfg(feature = "sqlite")]
 mod stardict_sqlite;
 
+use std::borrow::Cow;
+use std::collections::HashSet;
 use std::fs;
 use std::fs::OpenOptions;
-use std::io::Read;
-use std::path::PathBuf;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+#[cfg(any(feature = "sled", feature = "sqlite"))]
+use std::time::UNIX_EPOCH;
+#[cfg(any(feature = "sled", feature = "sqlite"))]
 use dirs::cache_dir;
-#[cfg(feature = "sqlite")]
+#[cfg(feature = "serde")]
 use serde::{Serialize, Deserialize};
 
 use crate::error::{Error, Result};
-pub use crate::ifo::Ifo;
-pub use crate::stardict::StarDictStd;
+use crate::plain::PlainText;
+use crate::resource::ResourceDb;
+use crate::shard::shard_paths;
+pub use crate::cached_lookup::CachedLookup;
+pub use crate::collation::{Collation, Normalizer};
+pub use crate::dictzip::{decompress_dict, DictZipWriter};
+pub use crate::encoding::{Encoding, LossyPolicy};
+pub use crate::group::DictGroup;
+pub use crate::ifo::{DictType, Ifo};
+pub use crate::opener::{Backend, StarDictOpener};
+pub use crate::plain::strip_pango;
+pub use crate::stardict::{StarDictStd, VerifyReport};
+pub use crate::stardict_mem::StarDictCachedMem;
+pub use crate::writer::DictWriter;
+pub use crate::xdxf::XdxfNode;
+#[cfg(feature = "async")]
+pub use crate::async_dict::AsyncStarDict;
 #[cfg(feature = "sled")]
 pub use crate::stardict_sled::StarDictCachedSled;
 #[cfg(feature = "sqlite")]
@@ -33,66 +72,664 @@ fn buf_to_string(buf: &[u8]) -> String {
 		.collect()
 }
 
-#[derive(Debug)]
-#[cfg_attr(feature = "sqlite", derive(Serialize, Deserialize))]
+#[inline]
+fn is_binary_type(types: &str) -> bool {
+	types.chars().next().is_some_and(|ch| ch.is_ascii_uppercase())
+}
+
+/// type letter of a segment, see segment_type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SegmentType {
+	/// 'g', pango markup
+	Pango,
+	/// 'h', html
+	Html,
+	/// 'm' utf-8 text, or 'l' text in the locale charset
+	Plain,
+	/// 'e', english text
+	English,
+	/// 't', phonetic, e.g. IPA
+	Phonetic,
+	/// 'x', xdxf markup
+	Xdxf,
+	/// 'W', wav audio
+	Wav,
+	/// 'P', picture
+	Picture,
+	/// 'r', list of resource files
+	Resource,
+	/// any other letter
+	Other(char),
+}
+
+impl From<char> for SegmentType {
+	fn from(types: char) -> Self
+	{
+		match types {
+			'g' => SegmentType::Pango,
+			'h' => SegmentType::Html,
+			'm' | 'l' => SegmentType::Plain,
+			'e' => SegmentType::English,
+			't' => SegmentType::Phonetic,
+			'x' => SegmentType::Xdxf,
+			'W' => SegmentType::Wav,
+			'P' => SegmentType::Picture,
+			'r' => SegmentType::Resource,
+			_ => SegmentType::Other(types),
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct WordDefinitionSegment {
 	pub types: String,
+	/// empty for binary segment
 	pub text: String,
+	/// raw bytes of binary segment, which type is an uppercase letter,
+	/// e.g. 'W' for wav audio, 'P' for picture
+	#[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+	pub data: Option<Vec<u8>>,
 }
 
-#[derive(Debug)]
-#[cfg_attr(feature = "sqlite", derive(Serialize, Deserialize))]
+impl WordDefinitionSegment {
+	#[cfg(test)]
+	#[inline]
+	pub(crate) fn new(types: String, buf: &[u8]) -> Self {
+		let types = types.chars().next().unwrap();
+		WordDefinitionSegmentRef::decode(types, buf, Encoding::Utf8, LossyPolicy::Strip).unwrap().into_owned()
+	}
+
+	/// the type letter as SegmentType, Other('\0') for empty types
+	#[inline]
+	pub fn segment_type(&self) -> SegmentType {
+		self.types.chars().next().map_or(SegmentType::Other('\0'), SegmentType::from)
+	}
+
+	/// parse text of segment with type 'x' as xdxf
+	pub fn parse_xdxf(&self) -> Result<XdxfNode> {
+		if self.types != "x" {
+			return Err(Error::NotXdxfSegment(self.types.clone()));
+		}
+		xdxf::parse(&self.text)
+	}
+}
+
+/// segment borrowing from the dict data, text copied only when
+/// decoded from other charsets or invalid utf-8
+#[derive(Debug, Clone, PartialEq)]
+pub struct WordDefinitionSegmentRef<'a> {
+	pub types: char,
+	/// empty for binary segment
+	pub text: Cow<'a, str>,
+	/// raw bytes of binary segment
+	pub data: Option<&'a [u8]>,
+}
+
+impl<'a> WordDefinitionSegmentRef<'a> {
+	/// None if text not decoded with the lossy policy
+	pub(crate) fn decode(types: char, buf: &'a [u8], encoding: Encoding, lossy: LossyPolicy) -> Option<Self> {
+		let segment = if types.is_ascii_uppercase() {
+			WordDefinitionSegmentRef { types, text: Cow::Borrowed(""), data: Some(buf) }
+		} else {
+			WordDefinitionSegmentRef { types, text: encoding.decode_cow(buf, lossy)?, data: None }
+		};
+		Some(segment)
+	}
+
+	#[inline]
+	pub fn into_owned(self) -> WordDefinitionSegment {
+		WordDefinitionSegment {
+			types: self.types.to_string(),
+			text: self.text.into_owned(),
+			data: self.data.map(<[u8]>::to_vec),
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct WordDefinition {
 	pub word: String,
 	pub segments: Vec<WordDefinitionSegment>,
 }
 
+impl WordDefinition {
+	/// text of all segments with markup stripped, segments separated by new line,
+	/// binary segments skipped
+	pub fn to_plain_text(&self) -> String {
+		let mut text = PlainText::new();
+		for segment in &self.segments {
+			match segment.types.as_str() {
+				"g" | "x" => text.push_markup(&segment.text, false),
+				"h" => text.push_markup(&segment.text, true),
+				types if is_binary_type(types) => continue,
+				_ => text.push_text(&segment.text, false),
+			}
+			text.newline();
+		}
+		text.finish()
+	}
+
+	/// text of the first phonetic(t) segment, e.g. IPA
+	#[inline]
+	pub fn pronunciation(&self) -> Option<&str> {
+		self.phonetics().next()
+	}
+
+	/// texts of all phonetic(t) segments
+	pub fn phonetics(&self) -> impl Iterator<Item=&str> {
+		self.segments.iter()
+			.filter(|segment| segment.types == "t")
+			.map(|segment| segment.text.as_str())
+	}
+
+	/// raw bytes of inline audio, wav(W) segments and other binary segments
+	/// with audio magic bytes, e.g. for an audio sink
+	pub fn audio(&self) -> Vec<&[u8]> {
+		self.segments.iter()
+			.filter_map(|segment| {
+				let data = segment.data.as_deref()?;
+				(segment.types == "W" || mime::resource_mime("", data).starts_with("audio/")).then_some(data)
+			})
+			.collect()
+	}
+
+	/// hrefs of audio not inline for get_resource, from snd: lines of
+	/// resource(r) segments and sound:// links of html(h) segments
+	pub fn audio_resources(&self) -> Vec<&str> {
+		let mut hrefs = vec![];
+		for segment in &self.segments {
+			match segment.types.as_str() {
+				"r" => hrefs.extend(segment.text.lines()
+					.filter_map(|line| line.trim().strip_prefix("snd:"))),
+				"h" => {
+					let mut remain = segment.text.as_str();
+					while let Some(start) = remain.find("sound://") {
+						remain = &remain[start + "sound://".len()..];
+						let end = remain.find(['"', '\'', '>', ' ']).unwrap_or(remain.len());
+						hrefs.push(&remain[..end]);
+						remain = &remain[end..];
+					}
+				}
+				_ => {}
+			}
+		}
+		hrefs
+	}
+}
+
+/// definition found by lookup_detailed
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MatchedDefinition {
+	pub definition: WordDefinition,
+	/// found through a synonym in .syn instead of the headword
+	pub via_synonym: bool,
+	/// normalized key of the headword found
+	pub matched_key: String,
+}
+
+#[inline]
+fn into_definitions(matched: Vec<MatchedDefinition>) -> Vec<WordDefinition> {
+	matched.into_iter().map(|matched| matched.definition).collect()
+}
+
 pub trait StarDict {
 	fn path(&self) -> &PathBuf;
 	fn ifo(&self) -> &Ifo;
 	fn dict_name(&self) -> &str {
 		&self.ifo().bookname
 	}
-	fn lookup(&mut self, word: &str) -> Result<Option<Vec<WordDefinition>>>;
+	/// dicttype of the ifo, e.g. wordnet needs special rendering
+	fn dict_type(&self) -> DictType {
+		self.ifo().dict_type()
+	}
+	fn author(&self) -> &str {
+		&self.ifo().author
+	}
+	fn website(&self) -> &str {
+		&self.ifo().website
+	}
+	/// may contain <br> for new line, see description_plain
+	fn description(&self) -> &str {
+		&self.ifo().description
+	}
+	/// description with <br> converted to \n
+	fn description_plain(&self) -> String {
+		self.ifo().description
+			.replace("<br>", "\n")
+			.replace("<br/>", "\n")
+			.replace("<br />", "\n")
+	}
+	fn date(&self) -> &str {
+		&self.ifo().date
+	}
+	fn word_count(&self) -> usize {
+		self.ifo().wordcount
+	}
+	fn sametypesequence(&self) -> &str {
+		&self.ifo().sametypesequence
+	}
+	/// original file name stored in the header of .dict.dz
+	fn dict_source_filename(&self) -> Option<&str> {
+		None
+	}
+	/// comment stored in the header of .dict.dz
+	fn dict_source_comment(&self) -> Option<&str> {
+		None
+	}
+	/// bytes of the idx, dict and syn files, and the resources
+	/// in the res folder or resource storage
+	fn dict_size(&self) -> Result<u64>;
+	/// bytes of the cache files, 0 without cache files
+	fn cache_size(&self) -> Result<u64> {
+		Ok(0)
+	}
+	fn lookup(&self, word: &str) -> Result<Option<Vec<WordDefinition>>>;
+	/// same as lookup, with whether every definition found through .syn
+	fn lookup_detailed(&self, word: &str) -> Result<Option<Vec<MatchedDefinition>>>;
+	/// same as lookup, stop after max definitions including the direct hit,
+	/// synonyms after that not read, max == 0 means unbounded
+	fn lookup_limited(&self, word: &str, max: usize) -> Result<Option<Vec<WordDefinition>>>;
+	/// word is a headword, or a synonym when following synonyms,
+	/// without reading definitions, true for entries with empty definitions too
+	fn contains(&self, word: &str) -> Result<bool>;
+	/// headword with the capitalization stored in the dictionary for word,
+	/// e.g. "English" for "english", the first one of several cased variants,
+	/// synonyms not followed
+	fn canonical_word(&self, word: &str) -> Result<Option<String>> {
+		Ok(self.lookup_detailed(word)?.and_then(|definitions| definitions.into_iter()
+			.find(|matched| !matched.via_synonym)
+			.map(|matched| matched.definition.word)))
+	}
+	/// headword cased exactly as word, synonyms not followed
+	fn lookup_exact(&self, word: &str) -> Result<Option<Vec<WordDefinition>>>;
+	/// plain text of every definition found joined by new line,
+	/// see WordDefinition::to_plain_text
+	fn definition_text(&self, word: &str) -> Result<Option<String>> {
+		Ok(self.lookup(word)?.map(|definitions| definitions.iter()
+			.map(WordDefinition::to_plain_text)
+			.collect::<Vec<_>>()
+			.join("\n")))
+	}
+	/// same as lookup, definitions put into out cleared first,
+	/// so its capacity reused by lookups in a loop, false if not found
+	fn lookup_into(&self, word: &str, out: &mut Vec<WordDefinition>) -> Result<bool> {
+		out.clear();
+		if let Some(definitions) = self.lookup(word)? {
+			out.extend(definitions);
+		}
+		Ok(!out.is_empty())
+	}
+	/// same as lookup, definitions with the same segments as an earlier one
+	/// dropped even if their words differ, e.g. spelling variants by syn
+	fn lookup_dedup(&self, word: &str) -> Result<Option<Vec<WordDefinition>>> {
+		Ok(self.lookup(word)?.map(|definitions| {
+			let mut unique: Vec<WordDefinition> = Vec::with_capacity(definitions.len());
+			for definition in definitions {
+				if !unique.iter().any(|kept| kept.segments == definition.segments) {
+					unique.push(definition);
+				}
+			}
+			unique
+		}))
+	}
+	/// lookup every word, results in the same order as words
+	fn lookup_many(&self, words: &[&str]) -> Result<Vec<(String, Option<Vec<WordDefinition>>)>> {
+		let mut results = Vec::with_capacity(words.len());
+		for word in words {
+			results.push((word.to_string(), self.lookup(word)?));
+		}
+		Ok(results)
+	}
+	/// list headwords starting with prefix, sorted by lowercase key,
+	/// matching is case-insensitive since all keys are stored lowercased,
+	/// limit == 0 means unbounded
+	fn search_prefix(&self, prefix: &str, limit: usize) -> Result<Vec<String>>;
+	/// every distinct headword with original capitalization, sorted by lowercase key,
+	/// headwords sharing a key, e.g. Apple and apple, in idx order
+	fn words(&self) -> Result<Vec<String>>;
+	/// headwords sorted right before and after the word, in key order,
+	/// the word itself excluded and need not exist
+	fn neighbors(&self, word: &str, before: usize, after: usize) -> Result<Vec<String>>;
+	/// headwords within max_distance edits of the word,
+	/// sorted by distance then lowercase key, limit == 0 means unbounded
+	fn suggest(&self, word: &str, max_distance: usize, limit: usize) -> Result<Vec<String>>;
+	/// headwords matching the glob pattern, * for any run of characters,
+	/// ? for a single character, sorted by lowercase key,
+	/// limit == 0 means unbounded
+	fn search_glob(&self, pattern: &str, limit: usize) -> Result<Vec<String>>;
+	/// synonyms pointing to the headword in syn file order,
+	/// empty if no .syn file
+	fn synonyms_of(&self, word: &str) -> Result<Vec<String>>;
+	/// storage of res.rifo, res.ridx and res.rdict, used without res folder
+	#[doc(hidden)]
+	fn resource_db(&self) -> Result<Option<&ResourceDb>> {
+		Ok(None)
+	}
+	/// file in the res folder, or from the resource storage without the folder,
+	/// FailedLoadResource if the storage is broken
 	fn get_resource(&self, href: &str) -> Result<Option<Vec<u8>>> {
-		let mut path_str = href;
-		if let Some(ch) = path_str.chars().nth(0) {
-			if ch == '/' {
-				path_str = &path_str[1..];
+		if let Some(db) = resource_storage(self)? {
+			if let Some(data) = db.get(resource_name(href))? {
+				return Ok(Some(data));
+			}
+		} else if let Some(path) = resource_path(self.path(), href) {
+			if path.exists() {
+				let mut file = OpenOptions::new()
+					.read(true)
+					.open(path)
+					.map_err(|e| Error::FailedLoadResource(href.to_owned(), e.to_string()))?;
+				let mut buf = vec![];
+				file.read_to_end(&mut buf)
+					.map_err(|e| Error::FailedLoadResource(href.to_owned(), e.to_string()))?;
+				return Ok(Some(buf));
 			}
-			if path_str.len() > 0 {
-				let mut path = self.path().join("res");
-				for sub in path_str.split("/") {
-					path = path.join(sub);
+		}
+		Err(Error::NoResourceFound(href.to_owned()))
+	}
+	/// every headword and its plain text definition as word\tdefinition lines,
+	/// in key order, backslash, new line and tab in words and definitions escaped
+	/// as \\, \n and \t, definitions by syn not included
+	fn export_tabfile(&self, out: &mut dyn Write) -> Result<()> {
+		// headwords of a key written once, each with its own definitions
+		let mut written = HashSet::new();
+		for word in self.words()? {
+			let definitions = if let Some(definitions) = self.lookup_detailed(&word)? {
+				definitions
+			} else {
+				continue;
+			};
+			let mut texts: Vec<(&str, String)> = vec![];
+			for matched in definitions.iter().filter(|matched| !matched.via_synonym) {
+				let headword = matched.definition.word.as_str();
+				let text = matched.definition.to_plain_text();
+				match texts.iter_mut().find(|(word, _)| *word == headword) {
+					Some((_, joined)) => {
+						joined.push('\n');
+						joined.push_str(&text);
+					}
+					None => texts.push((headword, text)),
 				}
-				if path.exists() {
-					let mut file = OpenOptions::new()
-						.read(true)
-						.open(path)
-						.map_err(|e| Error::FailedLoadResource(href.to_owned(), e.to_string()))?;
-					let mut buf = vec![];
-					file.read_to_end(&mut buf)
-						.map_err(|e| Error::FailedLoadResource(href.to_owned(), e.to_string()))?;
-					return Ok(Some(buf));
+			}
+			for (headword, text) in texts {
+				if written.insert(headword.to_owned()) {
+					write_tab_line(out, headword, &text)?;
 				}
 			}
 		}
-		Err(Error::NoResourceFound(href.to_owned()))
+		Ok(())
+	}
+	/// resource with mime type from the extension, or magic bytes
+	/// for unknown extension, application/octet-stream if not detected
+	fn get_resource_typed(&self, href: &str) -> Result<Option<(String, Vec<u8>)>> {
+		Ok(self.get_resource(href)?
+			.map(|data| (mime::resource_mime(href, &data).to_owned(), data)))
+	}
+	/// resource file exists, href as in get_resource, false if the storage is broken
+	fn has_resource(&self, href: &str) -> bool {
+		match resource_storage(self) {
+			Ok(Some(db)) => db.contains(resource_name(href)),
+			Ok(None) => resource_path(self.path(), href).is_some_and(|path| path.is_file()),
+			Err(_) => false,
+		}
+	}
+	/// files in the res folder recursively, paths relative to it
+	/// separated by /, sorted, empty if no res folder
+	fn list_resources(&self) -> Result<Vec<String>> {
+		fn walk(dir: &Path, prefix: &str, resources: &mut Vec<String>) -> Result<()> {
+			let entries = fs::read_dir(dir)
+				.map_err(|e| Error::FailedLoadResource(prefix.to_owned(), e.to_string()))?;
+			for entry in entries {
+				let entry = entry
+					.map_err(|e| Error::FailedLoadResource(prefix.to_owned(), e.to_string()))?;
+				let name = entry.file_name();
+				let name = if let Some(name) = name.to_str() {
+					name
+				} else {
+					continue;
+				};
+				let href = format!("{}{}", prefix, name);
+				let path = entry.path();
+				if path.is_dir() {
+					walk(&path, &format!("{}/", href), resources)?;
+				} else {
+					resources.push(href);
+				}
+			}
+			Ok(())
+		}
+
+		if let Some(db) = resource_storage(self)? {
+			return Ok(db.names());
+		}
+		let res = self.path().join("res");
+		let mut resources = vec![];
+		if res.is_dir() {
+			walk(&res, "", &mut resources)?;
+			resources.sort();
+		}
+		Ok(resources)
 	}
 }
 
-fn get_cache_dir<'a>(path: &'a PathBuf, cache_name: &str,
-	idx_cache_suffix: &str, syn_cache_suffix: Option<&str>)
-	-> Result<(PathBuf, Option<PathBuf>)>
+/// entry without definition skipped by the cache import, with the error if failed reading
+#[cfg(any(feature = "sled", feature = "sqlite"))]
+fn skipped_warning(word: &str, error: Option<Error>) -> String {
+	match error {
+		Some(e) => format!("entry {} skipped, failed reading: {}", word, e),
+		None => format!("entry {} skipped, no definition", word),
+	}
+}
+
+#[inline]
+fn write_tab_line(out: &mut dyn Write, word: &str, text: &str) -> Result<()> {
+	writeln!(out, "{}\t{}", escape_tab_text(word), escape_tab_text(text))
+		.map_err(|e| Error::FailedWriteFile("tab", e))
+}
+
+fn escape_tab_text(text: &str) -> String {
+	let mut escaped = String::with_capacity(text.len());
+	for ch in text.chars() {
+		match ch {
+			'\\' => escaped.push_str("\\\\"),
+			'\n' => escaped.push_str("\\n"),
+			'\t' => escaped.push_str("\\t"),
+			'\r' => {}
+			_ => escaped.push(ch),
+		}
+	}
+	escaped
+}
+
+/// resource storage if no res folder
+fn resource_storage<T: StarDict + ?Sized>(dict: &T) -> Result<Option<&ResourceDb>> {
+	if dict.path().join("res").is_dir() {
+		Ok(None)
+	} else {
+		dict.resource_db()
+	}
+}
+
+/// leading / ignored
+#[inline]
+fn resource_name(href: &str) -> &str {
+	href.strip_prefix('/').unwrap_or(href)
+}
+
+/// file of the href in the res folder
+fn resource_path(dict_path: &Path, href: &str) -> Option<PathBuf> {
+	let path_str = resource_name(href);
+	if path_str.is_empty() {
+		return None;
+	}
+	let mut path = dict_path.join("res");
+	for sub in path_str.split('/') {
+		path = path.join(sub);
+	}
+	Some(path)
+}
+
+/// definitions collected up to max of lookup_limited
+#[inline]
+fn limit_reached(definitions: &[MatchedDefinition], max: usize) -> bool {
+	max > 0 && definitions.len() >= max
+}
+
+/// bytes of the file or all files in the folder recursively, 0 if not exists
+fn disk_usage(path: &Path) -> Result<u64> {
+	let metadata = match fs::metadata(path) {
+		Ok(metadata) => metadata,
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+		Err(e) => return Err(e.into()),
+	};
+	if metadata.is_dir() {
+		let mut size = 0;
+		for entry in fs::read_dir(path)? {
+			size += disk_usage(&entry?.path())?;
+		}
+		Ok(size)
+	} else {
+		Ok(metadata.len())
+	}
+}
+
+/// bytes of the source files, and resources of the dict folder
+fn source_size<'a>(dict_path: &Path, files: impl IntoIterator<Item = &'a Path>) -> Result<u64> {
+	let mut size = 0;
+	for file in files {
+		for shard in shard_paths(file) {
+			size += disk_usage(&shard)?;
+		}
+	}
+	for name in ["res", "res.rifo", "res.ridx", "res.ridx.gz", "res.rdict", "res.rdict.dz"] {
+		size += disk_usage(&dict_path.join(name))?;
+	}
+	Ok(size)
+}
+
+/// called with entries imported and wordcount of the ifo while building cache
+#[cfg(any(feature = "sled", feature = "sqlite"))]
+type ImportProgress = Box<dyn FnMut(usize, usize) + Send>;
+/// entries imported between progress calls
+#[cfg(any(feature = "sled", feature = "sqlite"))]
+const PROGRESS_STEP: usize = 1000;
+
+/// source files and cache location, kept by cached backends to rebuild the cache
+#[cfg(any(feature = "sled", feature = "sqlite"))]
+#[derive(Clone)]
+struct CacheSource {
+	idx: PathBuf,
+	idx_gz: bool,
+	syn: Option<PathBuf>,
+	syn_gz: bool,
+	dict: PathBuf,
+	dict_dz: bool,
+	cache_name: String,
+	// cache root, collation and the checks of opening
+	options: opener::OpenOptions,
+}
+
+#[cfg(any(feature = "sled", feature = "sqlite"))]
+impl CacheSource {
+	/// idx, dict and syn files
+	#[inline]
+	fn files(&self) -> impl Iterator<Item = &Path> {
+		[Some(&self.idx), Some(&self.dict), self.syn.as_ref()].into_iter().flatten().map(PathBuf::as_path)
+	}
+}
+
+/// poll ready until true or timeout passed, for wait_ready of cached backends
+#[cfg(any(feature = "sled", feature = "sqlite"))]
+fn wait_until<F>(timeout: std::time::Duration, mut ready: F) -> Result<bool>
+	where F: FnMut() -> Result<bool>
+{
+	let start = std::time::Instant::now();
+	loop {
+		if ready()? {
+			return Ok(true);
+		}
+		let elapsed = start.elapsed();
+		if elapsed >= timeout {
+			return Ok(false);
+		}
+		std::thread::sleep((timeout - elapsed).min(std::time::Duration::from_millis(10)));
+	}
+}
+
+/// modification time and size of the idx and dict files with their shards,
+/// and the encoding other than utf-8, cache should be rebuilt when changed
+#[cfg(any(feature = "sled", feature = "sqlite"))]
+fn source_stamp(idx: &Path, dict: &Path, encoding: &Encoding) -> Result<String>
+{
+	let mut stamp = String::new();
+	let shards = [(idx, "idx"), (dict, "dict")].into_iter()
+		.flat_map(|(path, name)| shard_paths(path).into_iter().map(move |path| (path, name)));
+	for (path, name) in shards {
+		let metadata = fs::metadata(path).map_err(|e| Error::FailedOpenFile(name, e))?;
+		let mtime = metadata.modified()
+			.map_err(|e| Error::FailedOpenFile(name, e))?
+			.duration_since(UNIX_EPOCH)
+			.unwrap_or_default();
+		if !stamp.is_empty() {
+			stamp.push(';');
+		}
+		stamp.push_str(&format!("{}.{:09}:{}", mtime.as_secs(), mtime.subsec_nanos(), metadata.len()));
+	}
+	// caches of utf-8 stamped as before
+	match encoding.id() {
+		Some("utf-8") => {}
+		Some(id) => stamp.push_str(&format!(";encoding={}", id)),
+		None => return Err(Error::UnsupportedEncoding("custom".to_owned())),
+	}
+	Ok(stamp)
+}
+
+/// the user cache folder, or $XDG_CACHE_HOME then a folder in temp
+/// if not resolved, e.g. no $HOME on headless systems,
+/// with_sled_in and with_sqlite_in to choose the folder instead
+#[cfg(any(feature = "sled", feature = "sqlite"))]
+fn cache_root_or_fallback(user_cache: Option<PathBuf>) -> Result<PathBuf>
+{
+	if let Some(dir) = user_cache {
+		return Ok(dir);
+	}
+	std::env::var_os("XDG_CACHE_HOME")
+		.map(PathBuf::from)
+		.filter(|dir| dir.is_absolute())
+		.or_else(|| Some(std::env::temp_dir().join("stardict-cache"))
+			.filter(|dir| dir.is_absolute()))
+		.ok_or(Error::NoCacheDir)
+}
+
+/// folder of the cache files and the dict name, nothing created
+#[cfg(any(feature = "sled", feature = "sqlite"))]
+fn cache_location<'a>(path: &'a Path, cache_name: &str, cache_root: Option<&Path>)
+	-> Result<(PathBuf, &'a str)>
 {
 	let dict_name = path.file_name()
 		.ok_or(Error::InvalidDictPath)?
 		.to_str()
 		.ok_or(Error::InvalidDictPath)?;
-	let cache_dir = cache_dir().ok_or_else(|| Error::NoCacheDir)?;
-	let cache_dir = cache_dir.join(cache_name);
+	let cache_dir = if let Some(cache_root) = cache_root {
+		cache_root.to_path_buf()
+	} else {
+		cache_root_or_fallback(cache_dir())?
+	};
+	Ok((cache_dir.join(cache_name), dict_name))
+}
+
+/// cache files in cache_root/cache_name, cache_root default to the user cache folder
+#[cfg(any(feature = "sled", feature = "sqlite"))]
+fn get_cache_dir(path: &Path, cache_name: &str, cache_root: Option<&Path>,
+	idx_cache_suffix: &str, syn_cache_suffix: Option<&str>)
+	-> Result<(PathBuf, Option<PathBuf>)>
+{
+	let (cache_dir, dict_name) = cache_location(path, cache_name, cache_root)?;
 	if !cache_dir.exists() {
-		fs::create_dir_all(&cache_dir)?;
+		fs::create_dir_all(&cache_dir)
+			.map_err(|e| Error::FailedCreateCacheDir(cache_dir.clone(), e))?;
 	}
 	let idx_cache_str = format!("{}.{}", dict_name, idx_cache_suffix);
 	let idx_cache = cache_dir.join(&idx_cache_str);
@@ -110,25 +747,201 @@ fn get_cache_dir<'a>(path: &'a PathBuf, cache_name: &str,
 #[cfg(feature = "sled")]
 pub fn with_sled(path: impl Into<PathBuf>, cache_name: &str)
 	-> Result<StarDictCachedSled> {
-	create(path, |path, ifo, idx, idx_gz, syn, dict, dict_bz|
-		StarDictCachedSled::new(path, ifo, idx, idx_gz, syn, dict, dict_bz, cache_name))
+	StarDictOpener::new().open_sled(path, cache_name)
+}
+
+/// cache built with another collation will be removed and built again
+#[inline]
+#[cfg(feature = "sled")]
+pub fn with_sled_collation(path: impl Into<PathBuf>, cache_name: &str, collation: Collation)
+	-> Result<StarDictCachedSled> {
+	StarDictOpener::new().collation(collation).open_sled(path, cache_name)
+}
+
+/// cache in cache_root/cache_name instead of the user cache folder
+#[inline]
+#[cfg(feature = "sled")]
+pub fn with_sled_in(path: impl Into<PathBuf>, cache_name: &str, cache_root: impl AsRef<Path>)
+	-> Result<StarDictCachedSled> {
+	StarDictOpener::new().cache_dir(cache_root.as_ref()).open_sled(path, cache_name)
+}
+
+/// files in any location or name, syn is optional,
+/// cache named after the folder of the ifo
+#[inline]
+#[cfg(feature = "sled")]
+pub fn with_sled_from(ifo: impl Into<PathBuf>, idx: impl Into<PathBuf>, dict: impl Into<PathBuf>,
+	syn: Option<PathBuf>, cache_name: &str) -> Result<StarDictCachedSled> {
+	StarDictOpener::new().files(idx, dict, syn).open_sled(ifo, cache_name)
+}
+
+/// progress called with (current, total) while building the cache
+#[inline]
+#[cfg(feature = "sled")]
+pub fn with_sled_progress<P>(path: impl Into<PathBuf>, cache_name: &str, progress: P)
+	-> Result<StarDictCachedSled>
+	where P: FnMut(usize, usize) + Send + 'static
+{
+	StarDictOpener::new().progress(progress).open_sled(path, cache_name)
 }
 
 #[inline]
 #[cfg(feature = "sqlite")]
 pub fn with_sqlite(path: impl Into<PathBuf>, cache_name: &str)
 	-> Result<StarDictCachedSqlite> {
-	create(path, |path, ifo, idx, idx_gz, syn, dict, dict_bz|
-		StarDictCachedSqlite::new(path, ifo, idx, idx_gz, syn, dict, dict_bz, cache_name))
+	StarDictOpener::new().open_sqlite(path, cache_name)
+}
+
+/// cache built with another collation will be removed and built again
+#[inline]
+#[cfg(feature = "sqlite")]
+pub fn with_sqlite_collation(path: impl Into<PathBuf>, cache_name: &str, collation: Collation)
+	-> Result<StarDictCachedSqlite> {
+	StarDictOpener::new().collation(collation).open_sqlite(path, cache_name)
+}
+
+/// cache in cache_root/cache_name instead of the user cache folder
+#[inline]
+#[cfg(feature = "sqlite")]
+pub fn with_sqlite_in(path: impl Into<PathBuf>, cache_name: &str, cache_root: impl AsRef<Path>)
+	-> Result<StarDictCachedSqlite> {
+	StarDictOpener::new().cache_dir(cache_root.as_ref()).open_sqlite(path, cache_name)
+}
+
+/// open an existing complete cache without import, removal or any write,
+/// e.g. caches built in advance on a read only file system,
+/// CacheMissing if not found or not complete
+#[inline]
+#[cfg(feature = "sqlite")]
+pub fn with_sqlite_readonly(path: impl Into<PathBuf>, cache_name: &str)
+	-> Result<StarDictCachedSqlite> {
+	StarDictOpener::new().readonly(true).open_sqlite(path, cache_name)
+}
+
+/// files in any location or name, syn is optional,
+/// cache named after the folder of the ifo
+#[inline]
+#[cfg(feature = "sqlite")]
+pub fn with_sqlite_from(ifo: impl Into<PathBuf>, idx: impl Into<PathBuf>, dict: impl Into<PathBuf>,
+	syn: Option<PathBuf>, cache_name: &str) -> Result<StarDictCachedSqlite> {
+	StarDictOpener::new().files(idx, dict, syn).open_sqlite(ifo, cache_name)
+}
+
+/// progress called with (current, total) from the background import thread
+#[inline]
+#[cfg(feature = "sqlite")]
+pub fn with_sqlite_progress<P>(path: impl Into<PathBuf>, cache_name: &str, progress: P)
+	-> Result<StarDictCachedSqlite>
+	where P: FnMut(usize, usize) + Send + 'static
+{
+	StarDictOpener::new().progress(progress).open_sqlite(path, cache_name)
+}
+
+/// remove sled and sqlite caches of the dict in the user cache folder
+#[inline]
+#[cfg(any(feature = "sled", feature = "sqlite"))]
+pub fn clear_cache(path: impl Into<PathBuf>, cache_name: &str) -> Result<()>
+{
+	clear_cache_in_root(path.into(), cache_name, None)
+}
+
+/// remove sled and sqlite caches of the dict in cache_root
+#[inline]
+#[cfg(any(feature = "sled", feature = "sqlite"))]
+pub fn clear_cache_in(path: impl Into<PathBuf>, cache_name: &str, cache_root: impl AsRef<Path>)
+	-> Result<()>
+{
+	clear_cache_in_root(path.into(), cache_name, Some(cache_root.as_ref()))
+}
+
+#[cfg(any(feature = "sled", feature = "sqlite"))]
+fn clear_cache_in_root(ifo_path: PathBuf, cache_name: &str, cache_root: Option<&Path>) -> Result<()>
+{
+	let path = ifo_path.parent().ok_or(Error::InvalidDictPath)?;
+	#[cfg(feature = "sled")]
+	{
+		use crate::stardict_sled::{IDX_SLED_SUFFIX, SYN_SLED_SUFFIX};
+		let (idx_cache, syn_cache) = get_cache_dir(
+			path, cache_name, cache_root, IDX_SLED_SUFFIX, Some(SYN_SLED_SUFFIX))?;
+		stardict_sled::remove_cache(&idx_cache, syn_cache.as_ref())?;
+	}
+	#[cfg(feature = "sqlite")]
+	{
+		use crate::stardict_sqlite::IDX_SQLITE_SUFFIX;
+		let (idx_cache, _) = get_cache_dir(path, cache_name, cache_root, IDX_SQLITE_SUFFIX, None)?;
+		stardict_sqlite::remove_cache(&idx_cache)?;
+	}
+	Ok(())
 }
 
 #[inline]
 pub fn no_cache(path: impl Into<PathBuf>) -> Result<StarDictStd> {
-	create(path, StarDictStd::new)
+	StarDictOpener::new().open_std(path)
+}
+
+#[inline]
+pub fn no_cache_with_collation(path: impl Into<PathBuf>, collation: Collation) -> Result<StarDictStd> {
+	StarDictOpener::new().collation(collation).open_std(path)
+}
+
+/// headwords and text segments decoded with the encoding instead of utf-8
+#[inline]
+pub fn no_cache_with_encoding(path: impl Into<PathBuf>, encoding: Encoding) -> Result<StarDictStd> {
+	StarDictOpener::new().decoder(encoding).open_std(path)
+}
+
+/// invalid utf-8 in text segments handled with the policy,
+/// instead of stripped
+#[inline]
+pub fn no_cache_with_lossy(path: impl Into<PathBuf>, lossy: LossyPolicy) -> Result<StarDictStd> {
+	StarDictOpener::new().lossy(lossy).open_std(path)
+}
+
+/// files in any location or name, syn is optional
+#[inline]
+pub fn no_cache_from(ifo: impl Into<PathBuf>, idx: impl Into<PathBuf>, dict: impl Into<PathBuf>,
+	syn: Option<PathBuf>) -> Result<StarDictStd> {
+	StarDictOpener::new().files(idx, dict, syn).open_std(ifo)
+}
+
+/// not check the idx size with idxfilesize, and syn records with synwordcount of ifo
+#[inline]
+pub fn no_cache_lenient(path: impl Into<PathBuf>) -> Result<StarDictStd> {
+	StarDictOpener::new().lenient(true).open_std(path)
+}
+
+/// definition with type chars while sametypesequence set is an error,
+/// instead of logged and parsed with the type chars
+#[inline]
+pub fn no_cache_strict(path: impl Into<PathBuf>) -> Result<StarDictStd> {
+	StarDictOpener::new().strict(true).open_std(path)
+}
+
+/// keep the raw idx in memory and binary search its records sorted by key,
+/// instead of building a map of all words, words decoded on every search
+#[inline]
+pub fn no_cache_low_memory(path: impl Into<PathBuf>) -> Result<StarDictStd> {
+	StarDictOpener::new().low_memory(true).open_std(path)
+}
+
+/// all definitions decoded into memory while opening, no cache files,
+/// lookup without file access
+#[inline]
+pub fn with_memory(path: impl Into<PathBuf>) -> Result<StarDictCachedMem> {
+	StarDictOpener::new().open_memory(path)
 }
 
+/// with_memory, invalid utf-8 handled with the policy, with LossyPolicy::Error
+/// any invalid definition fails the opening
+#[inline]
+pub fn with_memory_lossy(path: impl Into<PathBuf>, lossy: LossyPolicy) -> Result<StarDictCachedMem> {
+	StarDictOpener::new().lossy(lossy).open_memory(path)
+}
+
+/// files found by the ifo name, numbered shards as foo.idx.2 or foo.dict.dz.2
+/// next to them are read with the first one by Idx and Dict
 fn create<C, T>(ifo_path: impl Into<PathBuf>, creator: C) -> Result<T>
-	where C: FnOnce(PathBuf, Ifo, PathBuf, bool, Option<PathBuf>, PathBuf, bool) -> Result<T>
+	where C: FnOnce(PathBuf, Ifo, PathBuf, bool, Option<PathBuf>, bool, PathBuf, bool) -> Result<T>
 {
 	fn get_sub_file(
 		prefix: &str,
@@ -152,7 +965,7 @@ fn create<C, T>(ifo_path: impl Into<PathBuf>, creator: C) -> Result<T>
 	}
 
 	let ifo_path = ifo_path.into();
-	if !ifo_path.is_file() || ifo_path.extension().map_or(true, |ext| ext != "ifo") {
+	if !ifo_path.is_file() || ifo_path.extension().is_none_or(|ext| ext != "ifo") {
 		return Err(Error::InvalidDictPath);
 	}
 
@@ -162,34 +975,964 @@ fn create<C, T>(ifo_path: impl Into<PathBuf>, creator: C) -> Result<T>
 	let dict_path_str = &ifo_path_str[0..ifo_path_str.len() - 4];
 	let (idx, idx_gz) = get_sub_file(dict_path_str, "idx", "gz")?;
 	let (dict, dict_bz) = get_sub_file(dict_path_str, "dict", "dz")?;
-	// optional syn file
-	let syn_path = PathBuf::from(&format!("{}.syn", dict_path_str));
-	let syn = if syn_path.exists() {
-		Some(syn_path)
-	} else {
-		None
-	};
+	// optional syn file, may be compressed
+	let (syn, syn_gz) = ["dz", "gz"].iter()
+		.find_map(|suffix| get_sub_file(dict_path_str, "syn", suffix).ok())
+		.map_or((None, false), |(syn, syn_gz)| (Some(syn), syn_gz));
 
 	let ifo = Ifo::new(ifo_path)?;
-	creator(dict_path, ifo, idx, idx_gz, syn, dict, dict_bz)
+	creator(dict_path, ifo, idx, idx_gz, syn, syn_gz, dict, dict_bz)
+}
+
+/// open with explicit file paths instead of looking up files by the ifo name,
+/// idx, dict and syn compressed when .gz or .dz or started with the gzip magic
+fn create_from<C, T>(ifo_path: PathBuf, idx: PathBuf, dict: PathBuf, syn: Option<PathBuf>,
+	creator: C) -> Result<T>
+	where C: FnOnce(PathBuf, Ifo, PathBuf, bool, Option<PathBuf>, bool, PathBuf, bool) -> Result<T>
+{
+	fn compressed(path: &Path, name: &'static str, suffix: &str) -> Result<bool>
+	{
+		if !path.is_file() {
+			return Err(Error::NoFileFound(name));
+		}
+		if path.extension().is_some_and(|ext| ext == suffix) {
+			return Ok(true);
+		}
+		let mut file = fs::File::open(path).map_err(|e| Error::FailedOpenFile(name, e))?;
+		let mut magic = [0; 2];
+		let compressed = match file.read_exact(&mut magic) {
+			Ok(()) => magic == [0x1f, 0x8b],
+			Err(_) => false,
+		};
+		Ok(compressed)
+	}
+
+	if !ifo_path.is_file() {
+		return Err(Error::InvalidDictPath);
+	}
+	let dict_path = ifo_path.parent().ok_or(Error::InvalidDictPath)?.to_path_buf();
+	let idx_gz = compressed(&idx, "idx", "gz")?;
+	let dict_bz = compressed(&dict, "dict", "dz")?;
+	let syn_gz = match &syn {
+		Some(syn) => compressed(syn, "syn", "dz")?,
+		None => false,
+	};
+	let ifo = Ifo::new(ifo_path)?;
+	creator(dict_path, ifo, idx, idx_gz, syn, syn_gz, dict, dict_bz)
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
+	use std::fs;
+	use std::path::{Path, PathBuf};
 	use std::thread;
 	use std::time::Duration;
-	use crate::error::Error;
-	use crate::StarDict;
-	use crate::no_cache;
+	#[cfg(any(feature = "sled", feature = "sqlite"))]
+	use std::time::SystemTime;
+	use crate::dictzip::tests::temp_file;
+	use crate::error::{Error, Result};
+	use crate::{into_definitions, no_cache, Collation, StarDict, StarDictOpener, WordDefinitionSegment};
 
-	const CACHE_NAME: &str = "test";
+	#[cfg(any(feature = "sled", feature = "sqlite"))]
+	pub(crate) const CACHE_NAME: &str = "test";
 	const DICT: &str = "/home/zl/tmp/stardict-chibigenc-2.4.2/chibigenc.ifo";
 	const WORD: &str = "汉";
 	const WORD_DEFINITION: &str = "漢";
 
+	pub(crate) const SAMPLE_WORDS: [(&str, &str); 5] = [
+		("Apple", "a round fruit"),
+		("application", "a formal request"),
+		("apply", "make a formal request"),
+		("banana", "a long curved fruit"),
+		("Band", "a group of musicians"),
+	];
+
+	/// create a plain dictionary with sametypesequence=m in a new temp folder,
+	/// syn entries are (synonym, headword) pairs, return the ifo path
+	pub(crate) fn create_dict(name: &str, words: &[(&str, &str)], syn: &[(&str, &str)]) -> PathBuf
+	{
+		let words: Vec<(&str, &[u8])> = words.iter()
+			.map(|(word, definition)| (*word, definition.as_bytes()))
+			.collect();
+		create_dict_with(name, "m", &words, syn)
+	}
+
+	pub(crate) fn create_dict_with(name: &str, sametypesequence: &str, words: &[(&str, &[u8])],
+		syn: &[(&str, &str)]) -> PathBuf
+	{
+		let dir = temp_file(name);
+		if dir.exists() {
+			fs::remove_dir_all(&dir).unwrap();
+		}
+		fs::create_dir_all(&dir).unwrap();
+
+		let mut words = words.to_vec();
+		words.sort_by_key(|(word, _)| word.to_lowercase());
+		let mut idx = vec![];
+		let mut dict = vec![];
+		for (word, definition) in &words {
+			idx.extend_from_slice(word.as_bytes());
+			idx.push(0);
+			idx.extend_from_slice(&(dict.len() as u32).to_be_bytes());
+			idx.extend_from_slice(&(definition.len() as u32).to_be_bytes());
+			dict.extend_from_slice(definition);
+		}
+		fs::write(dir.join(format!("{}.idx", name)), &idx).unwrap();
+		fs::write(dir.join(format!("{}.dict", name)), &dict).unwrap();
+		if !syn.is_empty() {
+			let mut buf = vec![];
+			for (alias, word) in syn {
+				let index = words.iter().position(|(w, _)| w == word).unwrap();
+				buf.extend_from_slice(alias.as_bytes());
+				buf.push(0);
+				buf.extend_from_slice(&(index as u32).to_be_bytes());
+			}
+			fs::write(dir.join(format!("{}.syn", name)), &buf).unwrap();
+		}
+		let ifo = format!("StarDict's dict ifo file\nversion=2.4.2\nbookname={}\n\
+			wordcount={}\nsynwordcount={}\nidxfilesize={}\nsametypesequence={}\n",
+			name, words.len(), syn.len(), idx.len(), sametypesequence);
+		let ifo_path = dir.join(format!("{}.ifo", name));
+		fs::write(&ifo_path, ifo).unwrap();
+		ifo_path
+	}
+
+	/// retry while the cache is initiating in background
+	pub(crate) fn wait_cache<T, F>(mut f: F) -> T
+		where F: FnMut() -> Result<T>
+	{
+		loop {
+			match f() {
+				Ok(value) => break value,
+				Err(Error::CacheInitiating) => thread::sleep(Duration::from_millis(100)),
+				Err(e) => panic!("{}", e),
+			}
+		}
+	}
+
+	/// the check run on the dict opened with the opener by every backend: std, low memory
+	/// and memory, then sled and sqlite of the enabled features while importing and
+	/// again from the cache, checks retry the first lookup with wait_cache for sqlite
+	pub(crate) fn check_backends_with<F>(opener: StarDictOpener, ifo: &Path, check: F)
+		where F: Fn(&(dyn StarDict + Sync))
+	{
+		check(&opener.clone().open_std(ifo).unwrap());
+		check(&opener.clone().low_memory(true).open_std(ifo).unwrap());
+		check(&opener.clone().open_memory(ifo).unwrap());
+		#[cfg(feature = "sled")]
+		for _ in 0..2 {
+			check(&wait_unlocked(|| opener.clone().open_sled(ifo, CACHE_NAME)));
+		}
+		#[cfg(feature = "sqlite")]
+		for _ in 0..2 {
+			check(&opener.clone().open_sqlite(ifo, CACHE_NAME).unwrap());
+		}
+	}
+
+	/// retry while the lock of the cache just dropped not released yet by sled threads
+	#[cfg(feature = "sled")]
+	pub(crate) fn wait_unlocked<T, F>(mut open: F) -> T
+		where F: FnMut() -> Result<T>
+	{
+		for _ in 0..100 {
+			match open() {
+				Err(Error::FailedOpenCache(e)) if e.contains("could not acquire lock") =>
+					thread::sleep(Duration::from_millis(20)),
+				result => return result.unwrap(),
+			}
+		}
+		panic!("cache still locked");
+	}
+
+	/// check_backends_with the default options
+	#[inline]
+	pub(crate) fn check_backends<F>(ifo: &Path, check: F)
+		where F: Fn(&(dyn StarDict + Sync))
+	{
+		check_backends_with(StarDictOpener::new(), ifo, check)
+	}
+
+	fn assert_search_prefix(dict: &dyn StarDict) {
+		assert_eq!(wait_cache(|| dict.search_prefix("app", 0)),
+			vec!["Apple", "application", "apply"]);
+		assert_eq!(dict.search_prefix("APP", 2).unwrap(), vec!["Apple", "application"]);
+		assert_eq!(dict.search_prefix("ban", 0).unwrap(), vec!["banana", "Band"]);
+		assert!(dict.search_prefix("c", 0).unwrap().is_empty());
+		assert!(dict.search_prefix("a%", 0).unwrap().is_empty());
+	}
+
+	pub(crate) const COLLATION_WORDS: [(&str, &str); 2] = [
+		("ÄPFEL", "apples"),
+		("DİYARBAKIR", "a city"),
+	];
+
+	/// accents of the words below stripped and lowercased
+	pub(crate) fn strip_accents() -> Collation {
+		Collation::Custom(crate::Normalizer::new("strip_accents", |word: &str| word.chars()
+			.map(|ch| match ch {
+				'á' | 'à' | 'â' | 'ä' | 'Á' | 'À' | 'Â' | 'Ä' => 'a',
+				'é' | 'è' | 'ê' | 'É' | 'È' | 'Ê' => 'e',
+				'ç' | 'Ç' => 'c',
+				_ => ch,
+			})
+			.flat_map(char::to_lowercase)
+			.collect()))
+	}
+
+	pub(crate) const ACCENT_WORDS: [(&str, &str); 2] = [("Café", "a coffee shop"), ("façade", "the front of a building")];
+
+	/// same size but new definition, with the modification time changed
+	#[cfg(any(feature = "sled", feature = "sqlite"))]
+	pub(crate) fn update_source(name: &str) -> PathBuf {
+		let ifo = create_dict(name, &[("apple", "new fruit")], &[]);
+		let dict = fs::File::options().write(true).open(ifo.with_extension("dict")).unwrap();
+		dict.set_modified(SystemTime::now() + Duration::from_secs(10)).unwrap();
+		ifo
+	}
+
+	fn binary_dict(name: &str) -> PathBuf {
+		let mut definition = vec![];
+		definition.extend_from_slice(&4u32.to_be_bytes());
+		definition.extend_from_slice(&[0, 1, 2, 255]);
+		definition.extend_from_slice(b"greeting");
+		create_dict_with(name, "Wm", &[("hello", &definition)], &[])
+	}
+
+	fn assert_binary(dict: &dyn StarDict) {
+		let definitions = wait_cache(|| dict.lookup("hello")).unwrap();
+		let segments = &definitions[0].segments;
+		assert_eq!(segments.len(), 2);
+		assert_eq!(segments[0].types, "W");
+		assert_eq!(segments[0].data.as_deref(), Some(&[0u8, 1, 2, 255][..]));
+		assert_eq!(segments[1].text, "greeting");
+		assert_eq!(segments[1].data, None);
+	}
+
+	#[test]
+	fn audio() {
+		let wav = b"RIFF\x24\0\0\0WAVEfmt \x10\0\0\0";
+		let mut definition = vec![];
+		for data in [&wav[..], b"\x89PNG", b"ID3\x03\0"] {
+			definition.extend_from_slice(&(data.len() as u32).to_be_bytes());
+			definition.extend_from_slice(data);
+		}
+		definition.extend_from_slice(b"img:apple.png\nsnd:en/apple.wav\n\0");
+		definition.extend_from_slice(b"<a href=\"sound://apple.mp3\">play</a> <img src=\"apple.png\">");
+		let ifo = create_dict_with("audio", "WPXrh", &[("apple", &definition)], &[]);
+		let dict = no_cache(&ifo).unwrap();
+		let definition = &dict.lookup("apple").unwrap().unwrap()[0];
+		// the picture skipped, other binary with audio magic included
+		assert_eq!(definition.audio(), [&wav[..], b"ID3\x03\0"]);
+		assert_eq!(definition.audio_resources(), ["en/apple.wav", "apple.mp3"]);
+	}
+
+	#[test]
+	fn binary_segment() {
+		let ifo = binary_dict("binary_segment");
+		check_backends(&ifo, |dict| assert_binary(dict));
+		#[cfg(feature = "sqlite")]
+		{
+			let json = serde_json::to_string(&no_cache(&ifo).unwrap().lookup("hello").unwrap()).unwrap();
+			let definitions: Vec<crate::WordDefinition> = serde_json::from_str(&json).unwrap();
+			assert_eq!(definitions[0].segments[0].data.as_deref(), Some(&[0u8, 1, 2, 255][..]));
+		}
+	}
+
+	#[test]
+	#[cfg(feature = "serde")]
+	fn serde_round_trip() {
+		use crate::WordDefinition;
+		let definition = WordDefinition {
+			word: "hello".to_owned(),
+			segments: vec![
+				WordDefinitionSegment::new("m".to_owned(), b"greeting"),
+				WordDefinitionSegment::new("W".to_owned(), &[0, 1, 2, 255]),
+			],
+		};
+		let json = serde_json::to_string(&definition).unwrap();
+		assert!(!json.contains("\"data\":null"));
+		assert_eq!(serde_json::from_str::<WordDefinition>(&json).unwrap(), definition);
+	}
+
+	/// sample words with a headword cased other than Apple, sharing its key
+	pub(crate) fn cased_words() -> Vec<(&'static str, &'static str)> {
+		let mut words = SAMPLE_WORDS.to_vec();
+		words.push(("apple", "a fruit tree"));
+		words
+	}
+
+	fn assert_words(dict: &dyn StarDict) {
+		let words = wait_cache(|| dict.words());
+		assert_eq!(words.len(), dict.ifo().wordcount);
+		assert_eq!(words, vec!["Apple", "apple", "application", "apply", "banana", "Band"]);
+	}
+
+	#[test]
+	fn words() {
+		let ifo = create_dict("words", &cased_words(), &[]);
+		check_backends(&ifo, |dict| assert_words(dict));
+	}
+
+	#[test]
+	fn search_prefix() {
+		let ifo = create_dict("search_prefix", &SAMPLE_WORDS, &[]);
+		check_backends(&ifo, |dict| assert_search_prefix(dict));
+	}
+
+	fn assert_neighbors(dict: &dyn StarDict) {
+		assert_eq!(wait_cache(|| dict.neighbors("apply", 1, 1)), vec!["application", "banana"]);
+		assert_eq!(dict.neighbors("APPLY", 2, 2).unwrap(),
+			vec!["Apple", "application", "banana", "Band"]);
+		// word not exists
+		assert_eq!(dict.neighbors("b", 1, 1).unwrap(), vec!["apply", "banana"]);
+		// start and end of the word list
+		assert_eq!(dict.neighbors("apple", 3, 1).unwrap(), vec!["application"]);
+		assert_eq!(dict.neighbors("band", 1, 3).unwrap(), vec!["banana"]);
+		assert_eq!(dict.neighbors("zoo", 10, 10).unwrap().len(), 5);
+	}
+
+	#[test]
+	fn neighbors() {
+		let ifo = create_dict("neighbors", &SAMPLE_WORDS, &[]);
+		check_backends(&ifo, |dict| assert_neighbors(dict));
+	}
+
+	fn assert_suggest(dict: &dyn StarDict) {
+		assert_eq!(wait_cache(|| dict.suggest("aple", 1, 0)), vec!["Apple"]);
+		assert_eq!(dict.suggest("bannana", 1, 0).unwrap(), vec!["banana"]);
+		assert_eq!(dict.suggest("APPLY", 1, 0).unwrap(), vec!["apply", "Apple"]);
+		assert_eq!(dict.suggest("bandd", 2, 1).unwrap(), vec!["Band"]);
+		assert!(dict.suggest("cherry", 1, 0).unwrap().is_empty());
+	}
+
+	#[test]
+	fn suggest() {
+		let ifo = create_dict("suggest", &SAMPLE_WORDS, &[]);
+		check_backends(&ifo, |dict| assert_suggest(dict));
+	}
+
+	fn assert_search_glob(dict: &dyn StarDict) {
+		// trailing wildcard
+		assert_eq!(wait_cache(|| dict.search_glob("ban*", 0)), vec!["banana", "Band"]);
+		// leading wildcard
+		assert_eq!(dict.search_glob("*LE", 0).unwrap(), vec!["Apple"]);
+		assert_eq!(dict.search_glob("*an*", 1).unwrap(), vec!["banana"]);
+		// middle wildcard
+		assert_eq!(dict.search_glob("app*n", 0).unwrap(), vec!["application"]);
+		assert_eq!(dict.search_glob("app?y", 0).unwrap(), vec!["apply"]);
+		assert_eq!(dict.search_glob("appl?", 0).unwrap(), vec!["Apple", "apply"]);
+		assert!(dict.search_glob("app_y", 0).unwrap().is_empty());
+		assert!(dict.search_glob("%", 0).unwrap().is_empty());
+	}
+
+	#[test]
+	fn search_glob() {
+		let ifo = create_dict("search_glob", &SAMPLE_WORDS, &[]);
+		check_backends(&ifo, |dict| assert_search_glob(dict));
+	}
+
+	#[test]
+	fn ifo_accessors() {
+		use crate::DictWriter;
+		let dir = temp_file("ifo_accessors");
+		fs::create_dir_all(&dir).unwrap();
+		let ifo = dir.join("accessors.ifo");
+		let mut writer = DictWriter::new("accessors");
+		writer.author = "someone".to_owned();
+		writer.website = "https://example.com".to_owned();
+		writer.description = "first line<br>second line".to_owned();
+		writer.date = "2024.01.01".to_owned();
+		for (word, definition) in SAMPLE_WORDS {
+			writer.add(word, vec![WordDefinitionSegment::new("m".to_owned(), definition.as_bytes())]);
+		}
+		writer.write(&ifo).unwrap();
+
+		let dict = no_cache(&ifo).unwrap();
+		assert_eq!(dict.dict_name(), "accessors");
+		assert_eq!(dict.author(), "someone");
+		assert_eq!(dict.website(), "https://example.com");
+		assert_eq!(dict.description(), "first line<br>second line");
+		assert_eq!(dict.description_plain(), "first line\nsecond line");
+		assert_eq!(dict.date(), "2024.01.01");
+		assert_eq!(dict.word_count(), SAMPLE_WORDS.len());
+		assert_eq!(dict.sametypesequence(), "m");
+	}
+
+	#[cfg(any(feature = "sled", feature = "sqlite"))]
+	pub(crate) fn cache_file_exists(cache_root: &std::path::Path, suffix: &str) -> bool {
+		fs::read_dir(cache_root.join(CACHE_NAME)).unwrap()
+			.any(|entry| entry.unwrap().file_name().to_string_lossy().ends_with(suffix))
+	}
+
+	/// dict with syn and nested resources, and the expected dict_size
+	pub(crate) fn create_sized_dict(name: &str) -> (PathBuf, u64) {
+		let ifo = create_dict(name, &SAMPLE_WORDS, &SYNONYMS);
+		let dir = ifo.parent().unwrap();
+		fs::create_dir_all(dir.join("res/sound")).unwrap();
+		fs::write(dir.join("res/apple.png"), [0; 100]).unwrap();
+		fs::write(dir.join("res/sound/apple.wav"), [0; 1000]).unwrap();
+		let size = ["idx", "dict", "syn"].iter()
+			.map(|ext| fs::metadata(ifo.with_extension(ext)).unwrap().len())
+			.sum::<u64>();
+		(ifo, size + 1100)
+	}
+
+	#[test]
+	fn dict_size() {
+		let (ifo, size) = create_sized_dict("dict_size");
+		let dict = no_cache(&ifo).unwrap();
+		assert_eq!(dict.dict_size().unwrap(), size);
+		assert_eq!(dict.cache_size().unwrap(), 0);
+		let dict = crate::with_memory(&ifo).unwrap();
+		assert_eq!(dict.dict_size().unwrap(), size);
+		assert_eq!(dict.cache_size().unwrap(), 0);
+	}
+
+	#[cfg(any(feature = "sled", feature = "sqlite"))]
+	pub(crate) fn cache_file(cache_root: &std::path::Path, suffix: &str) -> PathBuf {
+		fs::read_dir(cache_root.join(CACHE_NAME)).unwrap()
+			.map(|entry| entry.unwrap().path())
+			.find(|path| path.to_string_lossy().ends_with(suffix))
+			.unwrap()
+	}
+
+	fn assert_concurrent(dict: &(dyn StarDict + Sync)) {
+		thread::scope(|scope| {
+			for i in 0..8 {
+				scope.spawn(move || {
+					for j in 0..50 {
+						let (word, definition) = SAMPLE_WORDS[(i + j) % SAMPLE_WORDS.len()];
+						let definitions = wait_cache(|| dict.lookup(word)).unwrap();
+						assert_eq!(definitions[0].segments[0].text, definition);
+					}
+				});
+			}
+		});
+	}
+
+	#[test]
+	fn concurrent() {
+		let ifo = create_dict("concurrent", &SAMPLE_WORDS, &[]);
+		check_backends(&ifo, assert_concurrent);
+	}
+
+	#[test]
+	fn concurrent_dictzip() {
+		use crate::DictWriter;
+		let dir = temp_file("concurrent_dictzip");
+		fs::create_dir_all(&dir).unwrap();
+		let ifo = dir.join("concurrent.ifo");
+		let mut writer = DictWriter::new("concurrent");
+		writer.compress = true;
+		for (word, definition) in SAMPLE_WORDS {
+			writer.add(word, vec![WordDefinitionSegment::new("m".to_owned(), definition.as_bytes())]);
+		}
+		writer.write(&ifo).unwrap();
+		check_backends(&ifo, assert_concurrent);
+		// chunks read again and again with the smallest cache
+		assert_concurrent(&StarDictOpener::new().chunk_cache(0).open_std(&ifo).unwrap());
+	}
+
+	#[test]
+	fn dict_source_comment() {
+		let ifo = create_dict("dict_source_comment", &SAMPLE_WORDS, &[]);
+		let dict_path = ifo.with_extension("dict");
+		check_backends(&ifo, |dict| {
+			assert_eq!(dict.dict_source_filename(), None);
+			assert_eq!(dict.dict_source_comment(), None);
+		});
+		let mut dz = vec![];
+		crate::DictZipWriter::new(8).filename("source.dict").comment("built by hand")
+			.write(&fs::read(&dict_path).unwrap(), &mut dz).unwrap();
+		fs::remove_file(&dict_path).unwrap();
+		fs::write(ifo.with_extension("dict.dz"), &dz).unwrap();
+		check_backends(&ifo, |dict| {
+			assert_eq!(dict.dict_source_filename(), Some("source.dict"));
+			assert_eq!(dict.dict_source_comment(), Some("built by hand"));
+		});
+	}
+
+	#[test]
+	#[cfg(any(feature = "sled", feature = "sqlite"))]
+	fn cache_root_fallback() {
+		use crate::{cache_root_or_fallback, get_cache_dir};
+		let user = temp_file("cache_root_fallback");
+		assert_eq!(cache_root_or_fallback(Some(user.clone())).unwrap(), user);
+		// no user cache folder
+		let root = cache_root_or_fallback(None).unwrap();
+		match std::env::var_os("XDG_CACHE_HOME").map(PathBuf::from).filter(|dir| dir.is_absolute()) {
+			Some(xdg) => assert_eq!(root, xdg),
+			None => assert!(root.starts_with(std::env::temp_dir())),
+		}
+		let dict = temp_file("cache_root_fallback.dict");
+		let (idx_cache, _) = get_cache_dir(&dict, "stardict-fallback-test", Some(&root), "idx", None).unwrap();
+		assert!(idx_cache.parent().unwrap().is_dir());
+		assert!(idx_cache.starts_with(&root));
+		fs::remove_dir_all(root.join("stardict-fallback-test")).unwrap();
+	}
+
+	fn assert_contains(dict: &dyn StarDict) {
+		for word in ["apple", "APPLY", "Band", "pomme", "fruit", "cherry", "", "app"] {
+			assert_eq!(wait_cache(|| dict.contains(word)), dict.lookup(word).unwrap().is_some(), "{}", word);
+		}
+		assert!(dict.contains("Pomme").unwrap());
+		assert!(!dict.contains("cherry").unwrap());
+	}
+
+	#[test]
+	fn contains() {
+		let ifo = create_dict("contains", &SAMPLE_WORDS, &SYNONYMS);
+		check_backends(&ifo, |dict| assert_contains(dict));
+		check_backends_with(StarDictOpener::new().follow_synonyms(false), &ifo, |dict| {
+			assert!(!wait_cache(|| dict.contains("pomme")));
+			assert!(dict.contains("banana").unwrap());
+		});
+	}
+
+	#[test]
+	fn empty_definition() {
+		let mut words = SAMPLE_WORDS.to_vec();
+		words.push(("Empty", ""));
+		let ifo = create_dict("empty_definition", &words, &[("void", "Empty"), ("void", "Apple")]);
+		for dict in [no_cache(&ifo).unwrap(), crate::no_cache_low_memory(&ifo).unwrap()] {
+			assert!(dict.lookup("empty").unwrap().is_none());
+			assert!(dict.lookup_detailed("empty").unwrap().is_none());
+			let definitions = dict.lookup("void").unwrap().unwrap();
+			assert_eq!(definitions.len(), 1);
+			assert_eq!(definitions[0].word, "Apple");
+		}
+	}
+
+	#[test]
+	fn resources() {
+		let ifo = create_dict("resources", &SAMPLE_WORDS, &[]);
+		let dict = no_cache(&ifo).unwrap();
+		assert!(dict.list_resources().unwrap().is_empty());
+		assert!(!dict.has_resource("apple.png"));
+
+		let res = ifo.parent().unwrap().join("res");
+		fs::create_dir_all(res.join("sound/en")).unwrap();
+		fs::write(res.join("apple.png"), b"png").unwrap();
+		fs::write(res.join("sound/en/apple.wav"), b"wav").unwrap();
+		assert_eq!(dict.list_resources().unwrap(), vec!["apple.png", "sound/en/apple.wav"]);
+		for href in dict.list_resources().unwrap() {
+			assert!(dict.has_resource(&href));
+			assert!(dict.get_resource(&href).unwrap().is_some());
+		}
+		assert!(dict.has_resource("/sound/en/apple.wav"));
+		let (mime, data) = dict.get_resource_typed("apple.png").unwrap().unwrap();
+		assert_eq!(mime, "image/png");
+		assert_eq!(data, b"png");
+		assert_eq!(dict.get_resource_typed("/sound/en/apple.wav").unwrap().unwrap().0, "audio/wav");
+		assert_eq!(dict.get_resource("/sound/en/apple.wav").unwrap().unwrap(), b"wav");
+		assert!(!dict.has_resource("sound"));
+		assert!(!dict.has_resource("/"));
+		assert!(!dict.has_resource("banana.png"));
+		assert!(matches!(dict.get_resource("banana.png"), Err(Error::NoResourceFound(_))));
+	}
+
+	pub(crate) const SYNONYMS: [(&str, &str); 4] = [
+		("Pomme", "Apple"),
+		("fruit", "banana"),
+		("fruit", "Apple"),
+		("malus", "Apple"),
+	];
+
+	pub(crate) fn assert_synonyms_of(dict: &dyn StarDict, has_syn: bool) {
+		let synonyms = wait_cache(|| dict.synonyms_of("APPLE"));
+		if has_syn {
+			assert_eq!(synonyms, vec!["Pomme", "fruit", "malus"]);
+			assert_eq!(dict.synonyms_of("banana").unwrap(), vec!["fruit"]);
+		} else {
+			assert!(synonyms.is_empty());
+		}
+		assert!(dict.synonyms_of("band").unwrap().is_empty());
+		assert!(dict.synonyms_of("cherry").unwrap().is_empty());
+	}
+
+	#[test]
+	fn synonyms_of() {
+		let ifo = create_dict("synonyms_of", &SAMPLE_WORDS, &SYNONYMS);
+		check_backends(&ifo, |dict| assert_synonyms_of(dict, true));
+		let ifo = create_dict("synonyms_of_no_syn", &SAMPLE_WORDS, &[]);
+		check_backends(&ifo, |dict| assert_synonyms_of(dict, false));
+	}
+
+	fn assert_lookup_many(dict: &dyn StarDict) {
+		let words = ["banana", "cherry", "APPLE", "fruit", "banana"];
+		let results = wait_cache(|| dict.lookup_many(&words));
+		assert_eq!(results.len(), words.len());
+		for ((word, definitions), expected) in results.iter().zip(words) {
+			assert_eq!(word, expected);
+			assert_eq!(definitions, &dict.lookup(expected).unwrap());
+		}
+		assert!(results[1].1.is_none());
+		assert!(dict.lookup_many(&[]).unwrap().is_empty());
+	}
+
+	#[test]
+	fn pronunciation() {
+		let words: [(&str, &[u8]); 2] = [("apple", b"\xcb\x88\xc3\xa6p.\xc9\x99l\0a round fruit"), ("banana", b"\0fruit")];
+		let ifo = create_dict_with("pronunciation", "tm", &words, &[]);
+		let dict = no_cache(&ifo).unwrap();
+		let definition = &dict.lookup("apple").unwrap().unwrap()[0];
+		assert_eq!(definition.pronunciation(), Some("ˈæp.əl"));
+		assert_eq!(definition.phonetics().collect::<Vec<_>>(), vec!["ˈæp.əl"]);
+
+		let definition = crate::WordDefinition {
+			word: "read".to_owned(),
+			segments: vec![
+				WordDefinitionSegment::new("m".to_owned(), b"to look at words"),
+				WordDefinitionSegment::new("t".to_owned(), b"ri:d"),
+				WordDefinitionSegment::new("t".to_owned(), b"red"),
+			],
+		};
+		assert_eq!(definition.pronunciation(), Some("ri:d"));
+		assert_eq!(definition.phonetics().collect::<Vec<_>>(), vec!["ri:d", "red"]);
+		let definition = &dict.lookup("banana").unwrap().unwrap()[0];
+		assert_eq!(definition.pronunciation(), Some(""));
+		let definition = &no_cache(create_dict("no_pronunciation", &SAMPLE_WORDS, &[])).unwrap()
+			.lookup("apple").unwrap().unwrap()[0];
+		assert_eq!(definition.pronunciation(), None);
+	}
+
+	pub(crate) fn assert_follow_synonyms(dict: &dyn StarDict, follow: bool) {
+		let definitions = wait_cache(|| dict.lookup("pomme"));
+		if follow {
+			assert_eq!(definitions.unwrap()[0].word, "Apple");
+			assert!(dict.lookup_detailed("apple").unwrap().unwrap().iter().all(|matched| !matched.via_synonym));
+		} else {
+			assert!(definitions.is_none());
+			assert!(dict.lookup_detailed("pomme").unwrap().is_none());
+			assert_eq!(dict.lookup_many(&["malus"]).unwrap()[0].1, None);
+		}
+		assert_eq!(dict.lookup("apple").unwrap().unwrap().len(), 1);
+		assert_eq!(dict.synonyms_of("apple").unwrap().len(), 3);
+	}
+
+	#[test]
+	fn follow_synonyms() {
+		let ifo = create_dict("follow_synonyms", &SAMPLE_WORDS, &SYNONYMS);
+		check_backends(&ifo, |dict| assert_follow_synonyms(dict, true));
+		check_backends_with(StarDictOpener::new().follow_synonyms(false), &ifo,
+			|dict| assert_follow_synonyms(dict, false));
+		// changed after opened
+		let mut dict = no_cache(&ifo).unwrap();
+		dict.set_follow_synonyms(false);
+		assert_follow_synonyms(&dict, false);
+		let mut dict = crate::with_memory(&ifo).unwrap();
+		dict.set_follow_synonyms(false);
+		assert_follow_synonyms(&dict, false);
+	}
+
+	/// cafe of the idx and dict in latin1
+	#[cfg(any(feature = "sled", feature = "sqlite"))]
+	pub(crate) fn latin1_dict(name: &str) -> PathBuf {
+		let ifo = create_dict(name, &[("cafe", "a cafe")], &[]);
+		for ext in ["idx", "dict"] {
+			let path = ifo.with_extension(ext);
+			let data = fs::read(&path).unwrap();
+			let start = data.windows(4).position(|window| window == b"cafe").unwrap();
+			let data = [&data[..start], b"caf\xe9", &data[start + 4..]].concat();
+			fs::write(&path, data).unwrap();
+		}
+		ifo
+	}
+
+	#[test]
+	fn export_tabfile() {
+		use crate::DictWriter;
+		let mut words = SAMPLE_WORDS.to_vec();
+		words.push(("Tab", "line one\nline\ttwo \\ back"));
+		// sharing the key of Apple
+		words.push(("apple", "a tech company"));
+		words.push(("tab\tword", "escaped"));
+		let ifo = create_dict("export_tabfile", &words, &SYNONYMS);
+		let dict = no_cache(&ifo).unwrap();
+		let export = |dict: &dyn StarDict| {
+			let mut out = vec![];
+			dict.export_tabfile(&mut out).unwrap();
+			String::from_utf8(out).unwrap()
+		};
+		let out = export(&dict);
+		assert_eq!(out.lines().count(), words.len());
+		// tab collapsed by plain text
+		assert!(out.contains("Tab\tline one\\nline two \\\\ back\n"));
+		assert!(out.contains("\napple\ta tech company\n"));
+		assert!(out.contains("\ntab\\tword\tescaped\n"));
+		// by lookup of every key, same lines in the same order by every backend
+		assert_eq!(export(&crate::no_cache_low_memory(&ifo).unwrap()), out);
+		assert_eq!(export(&crate::with_memory(&ifo).unwrap()), out);
+		#[cfg(feature = "sled")]
+		{
+			let dict = crate::with_sled(&ifo, CACHE_NAME).unwrap();
+			assert!(dict.wait_ready(Duration::from_secs(30)).unwrap());
+			assert_eq!(export(&dict), out);
+		}
+		#[cfg(feature = "sqlite")]
+		{
+			let dict = crate::with_sqlite(&ifo, CACHE_NAME).unwrap();
+			assert!(dict.wait_ready(Duration::from_secs(30)).unwrap());
+			assert_eq!(export(&dict), out);
+		}
+
+		// import the tab file again
+		let mut writer = DictWriter::new("export_tabfile");
+		let unescape = |text: &str| text.replace("\\n", "\n").replace("\\t", "\t").replace("\\\\", "\\");
+		for line in out.lines() {
+			let (word, text) = line.split_once('\t').unwrap();
+			let (word, text) = (unescape(word), unescape(text));
+			writer.add(word, vec![WordDefinitionSegment::new("m".to_owned(), text.as_bytes())]);
+		}
+		let dir = temp_file("export_tabfile_import");
+		fs::create_dir_all(&dir).unwrap();
+		let path = dir.join("export_tabfile.ifo");
+		writer.write(&path).unwrap();
+		let imported = no_cache(&path).unwrap();
+		assert_eq!(imported.words().unwrap(), dict.words().unwrap());
+		for word in dict.words().unwrap() {
+			assert_eq!(imported.lookup_exact(&word).unwrap().unwrap()[0].to_plain_text(),
+				dict.lookup_exact(&word).unwrap().unwrap()[0].to_plain_text());
+		}
+	}
+
+	/// the zebra block out of the dict, the empty block without definition
+	#[cfg(any(feature = "sled", feature = "sqlite"))]
+	pub(crate) fn broken_block_dict(name: &str) -> PathBuf {
+		let mut words = SAMPLE_WORDS.to_vec();
+		words.push(("Empty", ""));
+		words.push(("zebra", "striped animal"));
+		let ifo = create_dict(name, &words, &[]);
+		let dict_path = ifo.with_extension("dict");
+		let data = fs::read(&dict_path).unwrap();
+		fs::write(&dict_path, &data[..data.len() - 4]).unwrap();
+		ifo
+	}
+
+	#[cfg(any(feature = "sled", feature = "sqlite"))]
+	pub(crate) fn assert_broken_block(dict: &dyn StarDict, mut warnings: Vec<String>) {
+		// from the cache, zebra is an error while lookup with the source files
+		assert!(dict.lookup("zebra").unwrap().is_none());
+		assert!(dict.lookup("empty").unwrap().is_none());
+		assert_eq!(dict.lookup("apple").unwrap().unwrap()[0].word, "Apple");
+		assert!(dict.lookup("band").unwrap().is_some());
+		// in the order of the map
+		warnings.sort();
+		assert_eq!(warnings.len(), 2);
+		assert_eq!(warnings[0], "entry Empty skipped, no definition");
+		assert!(warnings[1].starts_with("entry zebra skipped, failed reading: "), "{}", warnings[1]);
+	}
+
+	#[test]
+	fn lookup_many() {
+		let ifo = create_dict("lookup_many", &SAMPLE_WORDS, &SYNONYMS);
+		check_backends(&ifo, |dict| assert_lookup_many(dict));
+	}
+
+	fn assert_lookup_detailed(dict: &dyn StarDict) {
+		let matched = wait_cache(|| dict.lookup_detailed("pomme")).unwrap();
+		assert_eq!(matched.len(), 1);
+		assert_eq!(matched[0].definition.word, "Apple");
+		assert!(matched[0].via_synonym);
+		assert_eq!(matched[0].matched_key, "apple");
+
+		let matched = dict.lookup_detailed("APPLE").unwrap().unwrap();
+		assert_eq!(matched.len(), 1);
+		assert!(!matched[0].via_synonym);
+		assert_eq!(matched[0].matched_key, "apple");
+		assert_eq!(dict.lookup_detailed("apple").unwrap().map(into_definitions),
+			dict.lookup("apple").unwrap());
+		assert!(dict.lookup_detailed("cherry").unwrap().is_none());
+	}
+
+	#[test]
+	fn lookup_detailed() {
+		let ifo = create_dict("lookup_detailed", &SAMPLE_WORDS, &SYNONYMS);
+		check_backends(&ifo, |dict| assert_lookup_detailed(dict));
+	}
+
+	const ALIASED_WORDS: [(&str, &str); 6] = [("fruit", "edible part"), ("apple", "red fruit"),
+		("banana", "yellow fruit"), ("cherry", "small fruit"), ("grape", "vine fruit"), ("lemon", "sour fruit")];
+	const FRUIT_SYNONYMS: [(&str, &str); 5] = [("fruit", "apple"), ("fruit", "banana"), ("fruit", "cherry"),
+		("fruit", "grape"), ("fruit", "lemon")];
+
+	fn assert_lookup_limited(dict: &dyn StarDict) {
+		let all = wait_cache(|| dict.lookup("fruit")).unwrap();
+		assert_eq!(all.len(), 6);
+		let limited = dict.lookup_limited("fruit", 3).unwrap().unwrap();
+		assert_eq!(limited.len(), 3);
+		assert_eq!(limited[0].word, "fruit");
+		assert_eq!(limited[..], all[..3]);
+		assert_eq!(dict.lookup_limited("fruit", 1).unwrap().unwrap(), all[..1]);
+		assert_eq!(dict.lookup_limited("fruit", 0).unwrap().unwrap(), all);
+		assert_eq!(dict.lookup_limited("fruit", 100).unwrap().unwrap(), all);
+		assert!(dict.lookup_limited("melon", 3).unwrap().is_none());
+	}
+
+	#[test]
+	fn lookup_limited() {
+		let ifo = create_dict("lookup_limited", &ALIASED_WORDS, &FRUIT_SYNONYMS);
+		check_backends(&ifo, |dict| assert_lookup_limited(dict));
+	}
+
+	fn assert_lookup_into(dict: &dyn StarDict) {
+		let mut out = Vec::with_capacity(8);
+		let buf = out.as_ptr();
+		assert!(wait_cache(|| dict.lookup_into("fruit", &mut out)));
+		assert_eq!(Some(out.clone()), dict.lookup("fruit").unwrap());
+		assert!(dict.lookup_into("banana", &mut out).unwrap());
+		assert_eq!(Some(out.clone()), dict.lookup("banana").unwrap());
+		// cleared even if not found
+		assert!(!dict.lookup_into("cherry", &mut out).unwrap());
+		assert!(out.is_empty());
+		assert_eq!(out.as_ptr(), buf);
+		assert_eq!(out.capacity(), 8);
+	}
+
+	#[test]
+	fn lookup_into() {
+		let ifo = create_dict("lookup_into", &SAMPLE_WORDS, &SYNONYMS);
+		check_backends(&ifo, |dict| assert_lookup_into(dict));
+	}
+
+	#[test]
+	fn segment_type() {
+		use crate::SegmentType;
+		for (types, expected) in [
+			("g", SegmentType::Pango),
+			("h", SegmentType::Html),
+			("m", SegmentType::Plain),
+			("l", SegmentType::Plain),
+			("e", SegmentType::English),
+			("t", SegmentType::Phonetic),
+			("x", SegmentType::Xdxf),
+			("W", SegmentType::Wav),
+			("P", SegmentType::Picture),
+			("r", SegmentType::Resource),
+			("n", SegmentType::Other('n')),
+			("X", SegmentType::Other('X')),
+			("", SegmentType::Other('\0')),
+		] {
+			let segment = WordDefinitionSegment { types: types.to_owned(), text: String::new(), data: None };
+			assert_eq!(segment.segment_type(), expected, "{}", types);
+		}
+	}
+
+	#[test]
+	fn definition_text() {
+		use crate::strip_pango;
+		let words: [(&str, &[u8]); 2] = [
+			("apple", "/æpl/\0<b>a round</b> fruit".as_bytes()),
+			("pear", "/peər/\0a <i>green</i> &amp; sweet fruit".as_bytes()),
+		];
+		let ifo = create_dict_with("definition_text", "tg", &words, &[("fruit", "apple"), ("fruit", "pear")]);
+		let dict = no_cache(&ifo).unwrap();
+		let manual = dict.lookup("fruit").unwrap().unwrap().iter()
+			.flat_map(|definition| definition.segments.iter())
+			.map(|segment| if segment.types == "g" { strip_pango(&segment.text) } else { segment.text.clone() })
+			.collect::<Vec<_>>()
+			.join("\n");
+		assert_eq!(dict.definition_text("fruit").unwrap().unwrap(), manual);
+		assert_eq!(dict.definition_text("apple").unwrap().unwrap(), "/æpl/\na round fruit");
+		assert!(dict.definition_text("cherry").unwrap().is_none());
+	}
+
+	#[test]
+	fn lookup_dedup() {
+		let ifo = create_dict("lookup_dedup", &[
+			("color", "a visual property"),
+			("colour", "a visual property"),
+			("hue", "a shade of a color"),
+		], &[("color", "colour"), ("color", "hue"), ("tint", "colour"), ("tint", "color")]);
+		let dict = no_cache(&ifo).unwrap();
+		assert_eq!(dict.lookup("color").unwrap().unwrap().len(), 3);
+		let definitions = dict.lookup_dedup("color").unwrap().unwrap();
+		let words: Vec<&str> = definitions.iter().map(|definition| definition.word.as_str()).collect();
+		assert_eq!(words, ["color", "hue"]);
+		// only synonyms, the first kept
+		let definitions = dict.lookup_dedup("tint").unwrap().unwrap();
+		assert_eq!(definitions.len(), 1);
+		assert!(dict.lookup_dedup("cherry").unwrap().is_none());
+	}
+
+	pub(crate) const CASED_WORDS: [(&str, &str); 2] = [("Polish", "of Poland"), ("polish", "make shiny")];
+
+	fn assert_canonical_word(dict: &dyn StarDict) {
+		assert_eq!(wait_cache(|| dict.canonical_word("english")), Some("English".to_owned()));
+		assert_eq!(dict.canonical_word("ENGLISH").unwrap(), Some("English".to_owned()));
+		assert_eq!(dict.canonical_word("iphone").unwrap(), Some("iPhone".to_owned()));
+		// first of the cased variants
+		assert_eq!(dict.canonical_word("POLISH").unwrap(), Some("Polish".to_owned()));
+		// synonyms not followed
+		assert_eq!(dict.canonical_word("anglais").unwrap(), None);
+		assert_eq!(dict.canonical_word("cherry").unwrap(), None);
+	}
+
+	const MIXED_CASE_WORDS: [(&str, &str); 4] = [("English", "a language"), ("iPhone", "a phone"),
+		("Polish", "of Poland"), ("polish", "make shiny")];
+
+	#[test]
+	fn canonical_word() {
+		let ifo = create_dict("canonical_word", &MIXED_CASE_WORDS, &[("anglais", "English")]);
+		check_backends(&ifo, |dict| assert_canonical_word(dict));
+	}
+
+	#[test]
+	fn lookup_exact() {
+		let mut words = SAMPLE_WORDS.to_vec();
+		words.extend_from_slice(&CASED_WORDS);
+		let ifo = create_dict("lookup_exact", &words, &[("shine", "polish")]);
+		check_backends(&ifo, |dict| {
+			for (word, text) in CASED_WORDS {
+				let definitions = wait_cache(|| dict.lookup_exact(word)).unwrap();
+				assert_eq!(definitions.len(), 1);
+				assert_eq!(definitions[0].word, word);
+				assert_eq!(definitions[0].segments, vec![WordDefinitionSegment::new("m".to_owned(), text.as_bytes())]);
+			}
+			assert!(dict.lookup_exact("POLISH").unwrap().is_none());
+			assert!(dict.lookup_exact("shine").unwrap().is_none());
+			assert!(dict.lookup_exact("Apple").unwrap().is_some());
+			assert!(dict.lookup_exact("apple").unwrap().is_none());
+			// kept apart by lookup
+			let definitions = dict.lookup("polish").unwrap().unwrap();
+			let words: Vec<&str> = definitions.iter().map(|definition| definition.word.as_str()).collect();
+			assert_eq!(words, vec!["Polish", "polish"]);
+		});
+	}
+
+	const SAME_SPELLED: [(&str, &str); 3] = [
+		("bank", "land beside a river"),
+		("bank", "a place for money"),
+		("Bank", "a surname"),
+	];
+
+	fn assert_same_spelled(dict: &dyn StarDict) {
+		let definitions = wait_cache(|| dict.lookup("BANK")).unwrap();
+		let definitions: Vec<(&str, &str)> = definitions.iter()
+			.map(|definition| (definition.word.as_str(), definition.segments[0].text.as_str()))
+			.collect();
+		assert_eq!(definitions, SAME_SPELLED);
+		let definitions = dict.lookup_exact("bank").unwrap().unwrap();
+		assert_eq!(definitions.len(), 2);
+		assert_eq!(definitions[1].segments[0].text, "a place for money");
+		// every cased headword once
+		let words: Vec<String> = dict.words().unwrap().into_iter()
+			.filter(|word| word.eq_ignore_ascii_case("bank"))
+			.collect();
+		assert_eq!(words, ["bank", "Bank"]);
+	}
+
+	#[test]
+	fn same_spelled() {
+		let mut words = SAMPLE_WORDS.to_vec();
+		words.extend_from_slice(&SAME_SPELLED);
+		let ifo = create_dict("same_spelled", &words, &[]);
+		check_backends(&ifo, |dict| assert_same_spelled(dict));
+	}
+
 	#[test]
 	fn lookup() {
-		let mut dict = no_cache(DICT).unwrap();
+		let dict = no_cache(DICT).unwrap();
 		let definitions = dict.lookup(WORD).unwrap().unwrap();
 		assert_eq!(definitions.len(), 1);
 		assert_eq!(definitions[0].word, WORD_DEFINITION);
@@ -201,14 +1944,14 @@ mod tests {
 	#[cfg(feature = "sled")]
 	fn lookup_sled() {
 		use crate::with_sled;
-		let mut dict = with_sled(DICT, CACHE_NAME).unwrap();
+		let dict = with_sled(DICT, CACHE_NAME).unwrap();
 		let definitions = dict.lookup(WORD).unwrap().unwrap();
 		assert_eq!(definitions.len(), 1);
 		assert_eq!(definitions[0].word, WORD_DEFINITION);
 		assert_eq!(definitions[0].segments.len(), 1);
 		assert_eq!(definitions[0].segments[0].types, "g");
 
-		let mut dict = no_cache(DICT).unwrap();
+		let dict = no_cache(DICT).unwrap();
 		let std_definitions = dict.lookup(WORD).unwrap().unwrap();
 		for i in 0..definitions.len() {
 			let cached = &definitions[i];
@@ -227,12 +1970,12 @@ mod tests {
 	#[cfg(feature = "sqlite")]
 	fn lookup_sqlite() {
 		use crate::with_sqlite;
-		let mut dict = with_sqlite(DICT, CACHE_NAME).unwrap();
+		let dict = with_sqlite(DICT, CACHE_NAME).unwrap();
 		let definitions = loop {
 			match dict.lookup(WORD) {
 				Ok(definitions) => break definitions,
 				Err(Error::CacheInitiating) => thread::sleep(Duration::from_secs(1)),
-				Err(_) => assert!(false),
+				Err(e) => panic!("{}", e),
 			}
 		}.unwrap();
 		assert_eq!(definitions.len(), 1);
@@ -240,7 +1983,7 @@ mod tests {
 		assert_eq!(definitions[0].segments.len(), 1);
 		assert_eq!(definitions[0].segments[0].types, "g");
 
-		let mut dict = no_cache(DICT).unwrap();
+		let dict = no_cache(DICT).unwrap();
 		let std_definitions = dict.lookup(WORD).unwrap().unwrap();
 		for i in 0..definitions.len() {
 			let cached = &definitions[i];
@@ -0,0 +1,88 @@
+/// mime type by extension of the href, magic bytes of data if unknown
+pub(crate) fn resource_mime(href: &str, data: &[u8]) -> &'static str
+{
+	let name = href.rsplit('/').next().unwrap_or(href);
+	if let Some((_, ext)) = name.rsplit_once('.') {
+		if let Some(mime) = mime_of_extension(&ext.to_ascii_lowercase()) {
+			return mime;
+		}
+	}
+	mime_of_magic(data).unwrap_or("application/octet-stream")
+}
+
+fn mime_of_extension(ext: &str) -> Option<&'static str>
+{
+	let mime = match ext {
+		"png" => "image/png",
+		"jpg" | "jpeg" => "image/jpeg",
+		"gif" => "image/gif",
+		"bmp" => "image/bmp",
+		"webp" => "image/webp",
+		"svg" => "image/svg+xml",
+		"ico" => "image/x-icon",
+		"tif" | "tiff" => "image/tiff",
+		"wav" => "audio/wav",
+		"mp3" => "audio/mpeg",
+		"ogg" | "oga" => "audio/ogg",
+		"spx" => "audio/speex",
+		"flac" => "audio/flac",
+		"m4a" => "audio/mp4",
+		"mp4" => "video/mp4",
+		"webm" => "video/webm",
+		"html" | "htm" => "text/html",
+		"css" => "text/css",
+		"js" => "text/javascript",
+		"txt" => "text/plain",
+		"xml" => "application/xml",
+		"json" => "application/json",
+		"ttf" => "font/ttf",
+		"otf" => "font/otf",
+		"woff" => "font/woff",
+		"woff2" => "font/woff2",
+		_ => return None,
+	};
+	Some(mime)
+}
+
+fn mime_of_magic(data: &[u8]) -> Option<&'static str>
+{
+	let mime = if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+		"image/png"
+	} else if data.starts_with(&[0xff, 0xd8, 0xff]) {
+		"image/jpeg"
+	} else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+		"image/gif"
+	} else if data.starts_with(b"BM") {
+		"image/bmp"
+	} else if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WAVE") {
+		"audio/wav"
+	} else if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP") {
+		"image/webp"
+	} else if data.starts_with(b"ID3") || data.starts_with(&[0xff, 0xfb]) {
+		"audio/mpeg"
+	} else if data.starts_with(b"OggS") {
+		"audio/ogg"
+	} else if data.starts_with(b"fLaC") {
+		"audio/flac"
+	} else {
+		return None;
+	};
+	Some(mime)
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::mime::resource_mime;
+
+	#[test]
+	fn mime() {
+		assert_eq!(resource_mime("apple.png", b""), "image/png");
+		assert_eq!(resource_mime("/sound/en/Apple.WAV", b""), "audio/wav");
+		assert_eq!(resource_mime("style.css", b""), "text/css");
+		// by magic
+		assert_eq!(resource_mime("image", b"\x89PNG\r\n\x1a\n...."), "image/png");
+		assert_eq!(resource_mime("sound.dat", b"RIFF\0\0\0\0WAVEfmt "), "audio/wav");
+		assert_eq!(resource_mime("v1.0/sound", b"ID3\x03"), "audio/mpeg");
+		assert_eq!(resource_mime("unknown", b"data"), "application/octet-stream");
+	}
+}
@@ -0,0 +1,65 @@
+use std::fs::File;
+use std::ops::Deref;
+use std::os::unix::io::AsRawFd;
+use std::ptr;
+use crate::error::{Error, Result};
+
+/// read only map of a whole file
+///
+/// the file must not be truncated or written by anyone while mapped,
+/// reading a page cut off by truncation raises SIGBUS, and bytes written
+/// may show through the private mapping
+pub(crate) struct Mmap {
+	ptr: *mut libc::c_void,
+	len: usize,
+}
+
+// SAFETY: the mapping is owned by the Mmap, not aliased by any other value,
+// and unmapped only in drop, so it can be moved to another thread
+unsafe impl Send for Mmap {}
+
+// SAFETY: the mapping is PROT_READ and never written through the pointer,
+// shared only as &[u8], so reading it from many threads at once is no race
+unsafe impl Sync for Mmap {}
+
+impl Mmap {
+	pub(crate) fn new(file: &File) -> Result<Mmap>
+	{
+		let len = file.metadata()?.len() as usize;
+		// empty file can not be mapped
+		if len == 0 {
+			return Ok(Mmap { ptr: ptr::null_mut(), len });
+		}
+		let ptr = unsafe {
+			libc::mmap(ptr::null_mut(), len, libc::PROT_READ, libc::MAP_PRIVATE,
+				file.as_raw_fd(), 0)
+		};
+		if ptr == libc::MAP_FAILED {
+			return Err(Error::FailedOpenFile("dict", std::io::Error::last_os_error()));
+		}
+		Ok(Mmap { ptr, len })
+	}
+}
+
+impl Deref for Mmap {
+	type Target = [u8];
+
+	#[inline]
+	fn deref(&self) -> &[u8]
+	{
+		if self.len == 0 {
+			&[]
+		} else {
+			unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+		}
+	}
+}
+
+impl Drop for Mmap {
+	fn drop(&mut self)
+	{
+		if self.len > 0 {
+			unsafe { libc::munmap(self.ptr, self.len); }
+		}
+	}
+}
@@ -0,0 +1,466 @@
+use std::path::PathBuf;
+#[cfg(any(feature = "sled", feature = "sqlite"))]
+use std::fmt::{Debug, Formatter};
+#[cfg(any(feature = "sled", feature = "sqlite"))]
+use std::sync::{Arc, Mutex, PoisonError};
+use crate::collation::Collation;
+use crate::dictzip::DEFAULT_CACHE_CAPACITY;
+use crate::encoding::{Encoding, LossyPolicy};
+use crate::error::{Error, Result};
+use crate::ifo::Ifo;
+use crate::stardict::StarDictStd;
+use crate::stardict_mem::StarDictCachedMem;
+#[cfg(feature = "sled")]
+use crate::stardict_sled::StarDictCachedSled;
+#[cfg(feature = "sqlite")]
+use crate::stardict_sqlite::StarDictCachedSqlite;
+#[cfg(any(feature = "sled", feature = "sqlite"))]
+use crate::ImportProgress;
+use crate::{create, create_from, StarDict};
+
+/// cache of the opened dict
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum Backend {
+	/// read idx and dict files directly
+	#[default]
+	None,
+	/// all definitions decoded into memory while opening
+	Memory,
+	/// sled cache with the cache name
+	#[cfg(feature = "sled")]
+	Sled(String),
+	/// sqlite cache with the cache name, built in background
+	#[cfg(feature = "sqlite")]
+	Sqlite(String),
+}
+
+/// options of the opener passed down to the backends
+#[derive(Clone, Debug)]
+pub(crate) struct OpenOptions {
+	// cache root of sled and sqlite, the user cache folder if None
+	pub(crate) cache_dir: Option<PathBuf>,
+	pub(crate) collation: Collation,
+	pub(crate) encoding: Encoding,
+	pub(crate) lossy: LossyPolicy,
+	pub(crate) follow_synonyms: bool,
+	// raw idx kept and binary searched instead of the map
+	pub(crate) low_memory: bool,
+	// idx size and syn records checked with the ifo
+	pub(crate) verify_size: bool,
+	// type chars in data with sametypesequence are an error
+	pub(crate) strict: bool,
+	// existing sqlite cache opened without import or write
+	pub(crate) readonly: bool,
+	// whole dictzip checked with the crc32 and isize of the gzip trailer
+	pub(crate) verify_dict: bool,
+	// decompressed dictzip chunks kept in memory
+	pub(crate) chunk_cache: usize,
+	// idx read a page at a time by the .idx.oft offset table
+	pub(crate) paged: bool,
+}
+
+impl Default for OpenOptions {
+	#[inline]
+	fn default() -> Self
+	{
+		OpenOptions {
+			cache_dir: None,
+			collation: Collation::default(),
+			encoding: Encoding::Utf8,
+			lossy: LossyPolicy::Strip,
+			follow_synonyms: true,
+			low_memory: false,
+			verify_size: true,
+			strict: false,
+			readonly: false,
+			verify_dict: false,
+			chunk_cache: DEFAULT_CACHE_CAPACITY,
+			paged: false,
+		}
+	}
+}
+
+/// import progress shared by clones of the opener
+#[cfg(any(feature = "sled", feature = "sqlite"))]
+#[derive(Clone)]
+struct Progress(Arc<Mutex<ImportProgress>>);
+
+#[cfg(any(feature = "sled", feature = "sqlite"))]
+impl Progress {
+	#[inline]
+	fn callback(self) -> ImportProgress
+	{
+		Box::new(move |current, total|
+			(self.0.lock().unwrap_or_else(PoisonError::into_inner))(current, total))
+	}
+}
+
+#[cfg(any(feature = "sled", feature = "sqlite"))]
+impl Debug for Progress {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+	{
+		f.write_str("Progress")
+	}
+}
+
+/// options for opening a dict in one place, open for the backend chosen by
+/// cache, or open_std, open_memory, open_sled and open_sqlite for the concrete types
+#[derive(Clone, Debug)]
+pub struct StarDictOpener {
+	backend: Backend,
+	options: OpenOptions,
+	// charset name for Encoding::from_name, resolved while opening
+	encoding: Option<String>,
+	// idx, dict and syn instead of the files named after the ifo
+	files: Option<(PathBuf, PathBuf, Option<PathBuf>)>,
+	#[cfg(any(feature = "sled", feature = "sqlite"))]
+	progress: Option<Progress>,
+}
+
+impl Default for StarDictOpener {
+	#[inline]
+	fn default() -> Self
+	{
+		Self::new()
+	}
+}
+
+impl StarDictOpener {
+	#[inline]
+	pub fn new() -> Self
+	{
+		StarDictOpener {
+			backend: Backend::None,
+			options: OpenOptions::default(),
+			encoding: None,
+			files: None,
+			#[cfg(any(feature = "sled", feature = "sqlite"))]
+			progress: None,
+		}
+	}
+
+	#[inline]
+	pub fn cache(mut self, backend: Backend) -> Self
+	{
+		self.backend = backend;
+		self
+	}
+
+	/// cache root instead of the user cache folder, for sled and sqlite
+	#[inline]
+	pub fn cache_dir(mut self, cache_dir: impl Into<PathBuf>) -> Self
+	{
+		self.options.cache_dir = Some(cache_dir.into());
+		self
+	}
+
+	/// cache built with another collation will be removed and built again
+	#[inline]
+	pub fn collation(mut self, collation: Collation) -> Self
+	{
+		self.options.collation = collation;
+		self
+	}
+
+	/// charset name for Encoding::from_name, checked while opening,
+	/// caches of sled and sqlite built again for another encoding
+	#[inline]
+	pub fn encoding(mut self, encoding: &str) -> Self
+	{
+		self.encoding = Some(encoding.to_owned());
+		self
+	}
+
+	/// headwords and text segments decoded with the encoding, e.g.
+	/// Encoding::Custom for charsets without a name for encoding,
+	/// not for sled and sqlite, which can not tell custom decoders apart
+	#[inline]
+	pub fn decoder(mut self, encoding: Encoding) -> Self
+	{
+		self.encoding = None;
+		self.options.encoding = encoding;
+		self
+	}
+
+	/// invalid utf-8 in text segments handled with the policy instead of stripped,
+	/// with LossyPolicy::Error and Backend::Memory any invalid definition fails the opening
+	#[inline]
+	pub fn lossy(mut self, lossy: LossyPolicy) -> Self
+	{
+		self.options.lossy = lossy;
+		self
+	}
+
+	/// without following, lookup only the headword, not definitions by syn
+	#[inline]
+	pub fn follow_synonyms(mut self, follow: bool) -> Self
+	{
+		self.options.follow_synonyms = follow;
+		self
+	}
+
+	/// keep the raw idx in memory and binary search its records sorted by key,
+	/// instead of building a map of all words, words decoded on every search
+	#[inline]
+	pub fn low_memory(mut self, low_memory: bool) -> Self
+	{
+		self.options.low_memory = low_memory;
+		self
+	}
+
+	/// read the idx a page at a time by the .idx.oft offset table next to it
+	/// as stardict does, instead of parsing it while opening, only for the
+	/// uncompressed idx with Collation::AsciiLower, the idx parsed whole otherwise,
+	/// pages failed reading and the reason parsed whole in StarDictStd::idx_warnings
+	#[inline]
+	pub fn paged(mut self, paged: bool) -> Self
+	{
+		self.options.paged = paged;
+		self
+	}
+
+	/// not check the idx size with idxfilesize, and syn records with synwordcount of ifo
+	#[inline]
+	pub fn lenient(mut self, lenient: bool) -> Self
+	{
+		self.options.verify_size = !lenient;
+		self
+	}
+
+	/// definition with type chars while sametypesequence set is an error,
+	/// instead of logged and parsed with the type chars
+	#[inline]
+	pub fn strict(mut self, strict: bool) -> Self
+	{
+		self.options.strict = strict;
+		self
+	}
+
+	/// open an existing complete sqlite cache without import, removal or any write,
+	/// e.g. caches built in advance on a read only file system,
+	/// CacheMissing if not found or not complete, sled caches can not be read only
+	#[inline]
+	pub fn readonly(mut self, readonly: bool) -> Self
+	{
+		self.options.readonly = readonly;
+		self
+	}
+
+	/// decompress the whole .dict.dz while opening and check it with the crc32
+	/// and isize of the gzip trailer, InvalidDict if not matching,
+	/// instead of garbage or errors of the broken blocks later
+	#[inline]
+	pub fn verify_dict(mut self, verify: bool) -> Self
+	{
+		self.options.verify_dict = verify;
+		self
+	}
+
+	/// keep at most capacity decompressed chunks of .dict.dz in memory,
+	/// 64 by default, more for random lookups in large dicts, at least one
+	#[inline]
+	pub fn chunk_cache(mut self, capacity: usize) -> Self
+	{
+		self.options.chunk_cache = capacity;
+		self
+	}
+
+	/// files in any location or name, syn is optional, instead of found
+	/// by the ifo name, idx, dict and syn compressed when .gz or .dz or
+	/// started with the gzip magic, caches named after the folder of the ifo
+	#[inline]
+	pub fn files(mut self, idx: impl Into<PathBuf>, dict: impl Into<PathBuf>, syn: Option<PathBuf>) -> Self
+	{
+		self.files = Some((idx.into(), dict.into(), syn));
+		self
+	}
+
+	/// called with (current, total) while building sled and sqlite caches,
+	/// from the background import thread for sqlite
+	#[cfg(any(feature = "sled", feature = "sqlite"))]
+	#[inline]
+	pub fn progress<P>(mut self, progress: P) -> Self
+		where P: FnMut(usize, usize) + Send + 'static
+	{
+		self.progress = Some(Progress(Arc::new(Mutex::new(Box::new(progress)))));
+		self
+	}
+
+	/// the dict is Send and Sync, e.g. for DictGroup
+	pub fn open(self, path: impl Into<PathBuf>) -> Result<Box<dyn StarDict + Send + Sync>>
+	{
+		let dict: Box<dyn StarDict + Send + Sync> = match self.backend.clone() {
+			Backend::None => Box::new(self.open_std(path)?),
+			Backend::Memory => Box::new(self.open_memory(path)?),
+			#[cfg(feature = "sled")]
+			Backend::Sled(cache_name) => Box::new(self.open_sled(path, &cache_name)?),
+			#[cfg(feature = "sqlite")]
+			Backend::Sqlite(cache_name) => Box::new(self.open_sqlite(path, &cache_name)?),
+		};
+		Ok(dict)
+	}
+
+	/// read idx and dict files directly, the backend of cache ignored
+	pub fn open_std(self, path: impl Into<PathBuf>) -> Result<StarDictStd>
+	{
+		let (options, files) = self.resolve()?;
+		with_files(path.into(), files, |path, ifo, idx, idx_gz, syn, syn_gz, dict, dict_bz|
+			StarDictStd::new(path, ifo, idx, idx_gz, syn, syn_gz, dict, dict_bz, &options))
+	}
+
+	/// all definitions decoded into memory while opening, no cache files,
+	/// lookup without file access, the backend of cache ignored
+	pub fn open_memory(self, path: impl Into<PathBuf>) -> Result<StarDictCachedMem>
+	{
+		let (options, files) = self.resolve()?;
+		with_files(path.into(), files, |path, ifo, idx, idx_gz, syn, syn_gz, dict, dict_bz|
+			StarDictCachedMem::new(path, ifo, idx, idx_gz, syn, syn_gz, dict, dict_bz, &options))
+	}
+
+	/// sled cache in cache_name of the cache root, the backend of cache ignored
+	#[cfg(feature = "sled")]
+	pub fn open_sled(self, path: impl Into<PathBuf>, cache_name: &str) -> Result<StarDictCachedSled>
+	{
+		let progress = self.progress.clone().map(Progress::callback);
+		let (options, files) = self.resolve()?;
+		cacheable(&options.encoding)?;
+		if options.readonly {
+			return Err(Error::FailedOpenCache("sled cache can not be opened read only".to_owned()));
+		}
+		with_files(path.into(), files, |path, ifo, idx, idx_gz, syn, syn_gz, dict, dict_bz|
+			StarDictCachedSled::new(path, ifo, idx, idx_gz, syn, syn_gz, dict, dict_bz, cache_name, &options, progress))
+	}
+
+	/// sqlite cache in cache_name of the cache root, built in background,
+	/// the backend of cache ignored
+	#[cfg(feature = "sqlite")]
+	pub fn open_sqlite(self, path: impl Into<PathBuf>, cache_name: &str) -> Result<StarDictCachedSqlite>
+	{
+		let progress = self.progress.clone().map(Progress::callback);
+		let (options, files) = self.resolve()?;
+		cacheable(&options.encoding)?;
+		with_files(path.into(), files, |path, ifo, idx, idx_gz, syn, syn_gz, dict, dict_bz| if options.readonly {
+			StarDictCachedSqlite::open_readonly(path, ifo, idx, idx_gz, syn, syn_gz, dict, dict_bz, cache_name, &options)
+		} else {
+			StarDictCachedSqlite::new(path, ifo, idx, idx_gz, syn, syn_gz, dict, dict_bz, cache_name, &options, progress)
+		})
+	}
+
+	/// options with the encoding name resolved, and the explicit files
+	#[allow(clippy::type_complexity)]
+	fn resolve(self) -> Result<(OpenOptions, Option<(PathBuf, PathBuf, Option<PathBuf>)>)>
+	{
+		let mut options = self.options;
+		if let Some(name) = self.encoding {
+			options.encoding = Encoding::from_name(&name)
+				.ok_or(Error::UnsupportedEncoding(name))?;
+		}
+		Ok((options, self.files))
+	}
+}
+
+/// files found by the ifo name, or the explicit files
+#[inline]
+fn with_files<C, T>(ifo: PathBuf, files: Option<(PathBuf, PathBuf, Option<PathBuf>)>, creator: C) -> Result<T>
+	where C: FnOnce(PathBuf, Ifo, PathBuf, bool, Option<PathBuf>, bool, PathBuf, bool) -> Result<T>
+{
+	if let Some((idx, dict, syn)) = files {
+		create_from(ifo, idx, dict, syn, creator)
+	} else {
+		create(ifo, creator)
+	}
+}
+
+/// built-in encodings only, custom decoders not kept by caches
+#[cfg(any(feature = "sled", feature = "sqlite"))]
+#[inline]
+fn cacheable(encoding: &Encoding) -> Result<()>
+{
+	match encoding.id() {
+		Some(_) => Ok(()),
+		None => Err(Error::UnsupportedEncoding("custom".to_owned())),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+	use crate::dictzip::tests::temp_file;
+	use crate::error::Error;
+	use crate::{no_cache, Collation, StarDict};
+	use crate::tests::{assert_follow_synonyms, create_dict, SAMPLE_WORDS, SYNONYMS};
+
+	#[test]
+	fn open_from_paths() {
+		use std::io::Write;
+		use flate2::Compression;
+		use flate2::write::GzEncoder;
+		use crate::no_cache_from;
+
+		let ifo = create_dict("open_from_paths", &SAMPLE_WORDS, &SYNONYMS);
+		let dir = temp_file("open_from_paths_split");
+		if dir.exists() {
+			fs::remove_dir_all(&dir).unwrap();
+		}
+		fs::create_dir_all(dir.join("data")).unwrap();
+		let ifo_path = dir.join("main.ifo");
+		fs::copy(&ifo, &ifo_path).unwrap();
+		// gzipped idx without .gz extension
+		let mut encoder = GzEncoder::new(vec![], Compression::default());
+		encoder.write_all(&fs::read(ifo.with_extension("idx")).unwrap()).unwrap();
+		let idx_path = dir.join("data/words.index");
+		fs::write(&idx_path, encoder.finish().unwrap()).unwrap();
+		let dict_path = dir.join("data/content.dz");
+		let mut dz = vec![];
+		crate::DictZipWriter::new(8).write(&fs::read(ifo.with_extension("dict")).unwrap(), &mut dz).unwrap();
+		fs::write(&dict_path, &dz).unwrap();
+		let syn_path = dir.join("alias");
+		fs::copy(ifo.with_extension("syn"), &syn_path).unwrap();
+
+		let expected = no_cache(&ifo).unwrap();
+		let dict = no_cache_from(&ifo_path, &idx_path, &dict_path, Some(syn_path)).unwrap();
+		for word in ["apple", "pomme", "band", "cherry"] {
+			assert_eq!(dict.lookup(word).unwrap(), expected.lookup(word).unwrap());
+		}
+		let dict = no_cache_from(&ifo_path, &idx_path, &dict_path, None).unwrap();
+		assert!(dict.lookup("pomme").unwrap().is_none());
+		assert!(matches!(no_cache_from(&ifo_path, dir.join("missing"), &dict_path, None),
+			Err(Error::NoFileFound("idx"))));
+		assert!(matches!(no_cache_from(dir.join("missing.ifo"), &idx_path, &dict_path, None),
+			Err(Error::InvalidDictPath)));
+	}
+
+	#[test]
+	fn opener() {
+		use crate::{Backend, StarDictOpener};
+		let ifo = create_dict("opener", &SAMPLE_WORDS, &SYNONYMS);
+		for backend in [Backend::None, Backend::Memory] {
+			let dict = StarDictOpener::new()
+				.cache(backend)
+				.collation(Collation::AsciiLower)
+				.encoding("UTF-8")
+				.follow_synonyms(false)
+				.open(&ifo)
+				.unwrap();
+			assert_eq!(dict.lookup("APPLE").unwrap().unwrap()[0].word, "Apple");
+			assert_follow_synonyms(&*dict, false);
+		}
+		let dict = StarDictOpener::new().encoding("latin1").open(&ifo).unwrap();
+		assert_follow_synonyms(&*dict, true);
+		assert!(matches!(StarDictOpener::new().encoding("gbk").open(&ifo),
+			Err(Error::UnsupportedEncoding(name)) if name == "gbk"));
+
+		let dict = StarDictOpener::new()
+			.low_memory(true)
+			.paged(true)
+			.lenient(true)
+			.strict(true)
+			.decoder(crate::Encoding::Latin1)
+			.lossy(crate::LossyPolicy::Error)
+			.files(ifo.with_extension("idx"), ifo.with_extension("dict"), None)
+			.open_std(&ifo)
+			.unwrap();
+		assert!(dict.lookup("apple").unwrap().is_some());
+		assert!(dict.synonyms_of("apple").unwrap().is_empty());
+	}
+}
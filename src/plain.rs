@@ -0,0 +1,190 @@
+use crate::xdxf::unescape;
+
+/// strip pango, html or xdxf markup, entities decoded,
+/// <br> and block tags end a line, runs of whitespace collapsed,
+/// newlines in text kept unless html
+pub(crate) struct PlainText {
+	text: String,
+	// whitespace skipped since last char
+	space: bool,
+	newline: bool,
+}
+
+impl PlainText {
+	#[inline]
+	pub(crate) fn new() -> Self
+	{
+		PlainText { text: String::new(), space: false, newline: false }
+	}
+
+	pub(crate) fn push_markup(&mut self, markup: &str, html: bool)
+	{
+		let mut remain = markup;
+		while !remain.is_empty() {
+			if let Some(tag) = remain.strip_prefix('<') {
+				if let Some(comment) = tag.strip_prefix("!--") {
+					remain = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+					continue;
+				}
+				let is_tag = tag.starts_with(|ch: char| ch.is_ascii_alphabetic() || ch == '/' || ch == '!');
+				let end = if let (true, Some(end)) = (is_tag, tag.find('>')) {
+					end
+				} else {
+					// not a tag, keep it as text
+					self.push_text("<", html);
+					remain = tag;
+					continue;
+				};
+				let name = tag_name(&tag[..end]);
+				remain = &tag[end + 1..];
+				if name == "script" || name == "style" {
+					let close = format!("</{}", name);
+					remain = find_ignore_case(remain, &close)
+						.and_then(|start| remain[start..].find('>').map(|end| &remain[start + end + 1..]))
+						.unwrap_or("");
+				} else if name == "br" || html && matches!(name.as_str(), "p" | "div" | "li" | "tr") {
+					self.newline = true;
+				}
+			} else {
+				// text may start with a < which is not a tag
+				let end = remain.char_indices().skip(1)
+					.find(|(_, ch)| *ch == '<')
+					.map_or(remain.len(), |(end, _)| end);
+				self.push_text(&unescape(&remain[..end]), html);
+				remain = &remain[end..];
+			}
+		}
+	}
+
+	pub(crate) fn push_text(&mut self, text: &str, html: bool)
+	{
+		for ch in text.chars() {
+			if ch == '\n' && !html {
+				self.newline = true;
+			} else if ch.is_whitespace() {
+				self.space = true;
+			} else {
+				if !self.text.is_empty() {
+					if self.newline {
+						self.text.push('\n');
+					} else if self.space {
+						self.text.push(' ');
+					}
+				}
+				self.space = false;
+				self.newline = false;
+				self.text.push(ch);
+			}
+		}
+	}
+
+	#[inline]
+	pub(crate) fn newline(&mut self)
+	{
+		self.newline = true;
+	}
+
+	#[inline]
+	pub(crate) fn finish(self) -> String
+	{
+		self.text
+	}
+}
+
+/// text of pango markup, tags removed and entities decoded, whitespace kept.
+/// unclosed spans end with the text, a tag cut off at the end is dropped,
+/// < not starting a tag kept as text
+pub fn strip_pango(markup: &str) -> String
+{
+	let mut text = String::with_capacity(markup.len());
+	let mut remain = markup;
+	while let Some(start) = remain.find('<') {
+		text.push_str(&unescape(&remain[..start]));
+		let tag = &remain[start + 1..];
+		if !tag.starts_with(|ch: char| ch.is_ascii_alphabetic() || ch == '/') {
+			text.push('<');
+			remain = tag;
+			continue;
+		}
+		remain = tag.find('>').map_or("", |end| &tag[end + 1..]);
+	}
+	text.push_str(&unescape(remain));
+	text
+}
+
+/// lowercase name of the tag, without / and attributes
+fn tag_name(content: &str) -> String
+{
+	let content = content.trim_start_matches('/').trim_start();
+	let end = content.find(|ch: char| ch.is_whitespace() || ch == '/')
+		.unwrap_or(content.len());
+	content[..end].to_ascii_lowercase()
+}
+
+fn find_ignore_case(str: &str, pattern: &str) -> Option<usize>
+{
+	str.to_ascii_lowercase().find(pattern)
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::plain::strip_pango;
+	use crate::{WordDefinition, WordDefinitionSegment};
+
+	fn plain_text(types: &str, text: &str) -> String
+	{
+		let definition = WordDefinition {
+			word: "word".to_owned(),
+			segments: vec![WordDefinitionSegment::new(types.to_owned(), text.as_bytes())],
+		};
+		definition.to_plain_text()
+	}
+
+	#[test]
+	fn pango() {
+		assert_eq!(plain_text("g", "<b>apple</b>  <span foreground=\"red\">n.</span>\na &lt;fruit&gt;"),
+			"apple n.\na <fruit>");
+	}
+
+	#[test]
+	fn pango_strip() {
+		// headword, pinyin and meanings, as in the sample dictionary
+		let markup = "<span foreground=\"blue\"><big>漢</big></span> <span foreground=\"gray\">[hàn]</span>\n\
+			<span weight=\"bold\">1.</span> <span>Han &amp; <i>Chinese</i></span> &lt;n.&gt; &quot;x&quot; &apos;y&apos;";
+		assert_eq!(strip_pango(markup), "漢 [hàn]\n1. Han & Chinese <n.> \"x\" 'y'");
+		assert_eq!(plain_text("g", markup), "漢 [hàn]\n1. Han & Chinese <n.> \"x\" 'y'");
+		// nested and unclosed spans
+		assert_eq!(strip_pango("<span><span size=\"large\">a</span>b<span>c"), "abc");
+		// cut off tag, or not a tag
+		assert_eq!(strip_pango("apple <span foreground=\"re"), "apple ");
+		assert_eq!(strip_pango("1 < 2 <> 3"), "1 < 2 <> 3");
+	}
+
+	#[test]
+	fn html() {
+		assert_eq!(plain_text("h", "<p>apple</p>\n<p>a <i>round</i>&nbsp;fruit<br/>red &amp; green</p>"),
+			"apple\na round fruit\nred & green");
+		assert_eq!(plain_text("h", "<style>p { color: red }</style><script>x < 1</script>apple"),
+			"apple");
+		assert_eq!(plain_text("h", "1 < 2 <!-- comment -->done"), "1 < 2 done");
+	}
+
+	#[test]
+	fn xdxf() {
+		assert_eq!(plain_text("x", "<k>apple</k>\n<tr>ˈæp.əl</tr> <def>a <kref>fruit</kref></def>"),
+			"apple\nˈæp.əl a fruit");
+	}
+
+	#[test]
+	fn segments() {
+		let definition = WordDefinition {
+			word: "word".to_owned(),
+			segments: vec![
+				WordDefinitionSegment::new("t".to_owned(), b"  ap  ple "),
+				WordDefinitionSegment::new("W".to_owned(), &[0, 1, 2]),
+				WordDefinitionSegment::new("m".to_owned(), b"<b>fruit</b>"),
+			],
+		};
+		assert_eq!(definition.to_plain_text(), "ap ple\n<b>fruit</b>");
+	}
+}
@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use flate2::read::GzDecoder;
+use crate::dict::Dict;
+use crate::error::{Error, Result};
+
+const MAGIC: &str = "StarDict's storage ifo file";
+
+/// resource storage of res.rifo, res.ridx and res.rdict in the dict folder,
+/// ridx entries are file name null-terminated, offset in
+/// ridxoffsetbits(32 or 64) and 32 bits size, all big-endian
+pub struct ResourceDb {
+	entries: HashMap<String, (usize, usize)>,
+	dict: Dict,
+}
+
+impl ResourceDb {
+	/// None if no res.rifo in the folder
+	pub(crate) fn open(dict_path: &Path) -> Result<Option<ResourceDb>>
+	{
+		let rifo = dict_path.join("res.rifo");
+		if !rifo.is_file() {
+			return Ok(None);
+		}
+		let offset_bits = read_rifo(&rifo)?;
+		let (ridx, ridx_gz) = sub_file(dict_path, "res.ridx", "gz", "ridx")?;
+		let (rdict, rdict_dz) = sub_file(dict_path, "res.rdict", "dz", "rdict")?;
+
+		let file = File::open(ridx).map_err(|e| Error::FailedOpenFile("ridx", e))?;
+		let mut buf = vec![];
+		if ridx_gz {
+			GzDecoder::new(file).read_to_end(&mut buf)
+		} else {
+			BufReader::new(file).read_to_end(&mut buf)
+		}.map_err(|e| Error::FailedOpenFile("ridx", e))?;
+		let entries = read_ridx(&buf, offset_bits)?;
+		let dict = Dict::new(rdict, rdict_dz)?;
+		Ok(Some(ResourceDb { entries, dict }))
+	}
+
+	#[inline]
+	pub(crate) fn contains(&self, name: &str) -> bool
+	{
+		self.entries.contains_key(name)
+	}
+
+	pub(crate) fn get(&self, name: &str) -> Result<Option<Vec<u8>>>
+	{
+		if let Some((offset, size)) = self.entries.get(name) {
+			Ok(Some(self.dict.read(*offset, *size, name)?))
+		} else {
+			Ok(None)
+		}
+	}
+
+	/// file names sorted
+	pub(crate) fn names(&self) -> Vec<String>
+	{
+		let mut names: Vec<String> = self.entries.keys().cloned().collect();
+		names.sort();
+		names
+	}
+}
+
+/// resource storage opened on first use, kept by every backend,
+/// the failure kept as its message
+pub(crate) struct Resources(OnceLock<std::result::Result<Option<ResourceDb>, String>>);
+
+impl Resources {
+	#[inline]
+	pub(crate) fn new() -> Self
+	{
+		Resources(OnceLock::new())
+	}
+
+	/// failure returned as FailedLoadResource of res.rifo on every use
+	pub(crate) fn get(&self, dict_path: &Path) -> Result<Option<&ResourceDb>>
+	{
+		self.0.get_or_init(|| ResourceDb::open(dict_path).map_err(|e| e.to_string()))
+			.as_ref()
+			.map(Option::as_ref)
+			.map_err(|e| Error::FailedLoadResource("res.rifo".to_owned(), e.clone()))
+	}
+}
+
+fn sub_file(dict_path: &Path, name: &str, compress_suffix: &str, kind: &'static str)
+	-> Result<(PathBuf, bool)>
+{
+	let path = dict_path.join(name);
+	if path.is_file() {
+		return Ok((path, false));
+	}
+	let path = dict_path.join(format!("{}.{}", name, compress_suffix));
+	if path.is_file() {
+		Ok((path, true))
+	} else {
+		Err(Error::NoFileFound(kind))
+	}
+}
+
+/// ridxoffsetbits of the rifo
+fn read_rifo(path: &Path) -> Result<usize>
+{
+	let text = fs::read(path).map_err(|e| Error::FailedOpenFile("rifo", e))?;
+	let mut lines = text.lines().map_while(|line| line.ok())
+		.map(|line| line.trim_start_matches('\u{feff}').trim_end().to_owned())
+		.skip_while(|line| line.is_empty());
+	if lines.next().as_deref() != Some(MAGIC) {
+		return Err(Error::InvalidIfoMagic);
+	}
+	let mut offset_bits = 32;
+	for line in lines {
+		if let Some(bits) = line.strip_prefix("ridxoffsetbits=") {
+			offset_bits = match bits {
+				"32" => 32,
+				"64" => 64,
+				_ => return Err(Error::InvalidIfoValue("ridxoffsetbits")),
+			};
+		}
+	}
+	Ok(offset_bits)
+}
+
+fn read_ridx(buf: &[u8], offset_bits: usize) -> Result<HashMap<String, (usize, usize)>>
+{
+	let offset_width = offset_bits / 8;
+	let mut entries = HashMap::new();
+	let mut remain = buf;
+	while !remain.is_empty() {
+		let end = remain.iter().position(|b| *b == 0)
+			.ok_or(Error::InvalidIdxElement("resource name"))?;
+		let name = String::from_utf8_lossy(&remain[..end]).into_owned();
+		let numbers = remain.get(end + 1..end + 1 + offset_width + 4)
+			.ok_or(Error::InvalidIdxElement("resource offset"))?;
+		let offset = numbers[..offset_width].iter()
+			.fold(0u64, |value, b| value << 8 | *b as u64) as usize;
+		let size = u32::from_be_bytes([numbers[offset_width], numbers[offset_width + 1],
+			numbers[offset_width + 2], numbers[offset_width + 3]]) as usize;
+		entries.insert(name, (offset, size));
+		remain = &remain[end + 1 + offset_width + 4..];
+	}
+	Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+	use crate::{no_cache, DictZipWriter, Error, StarDict};
+	use crate::tests::{create_dict, SAMPLE_WORDS};
+
+	#[test]
+	fn rdict() {
+		let ifo = create_dict("resource_db", &SAMPLE_WORDS, &[]);
+		let dir = ifo.parent().unwrap();
+		let files: [(&str, &[u8]); 2] = [("apple.png", b"\x89PNG\r\n\x1a\npng"), ("sound/apple.wav", b"wav")];
+		let mut ridx = vec![];
+		let mut rdict = vec![];
+		for (name, data) in files {
+			ridx.extend_from_slice(name.as_bytes());
+			ridx.push(0);
+			ridx.extend_from_slice(&(rdict.len() as u32).to_be_bytes());
+			ridx.extend_from_slice(&(data.len() as u32).to_be_bytes());
+			rdict.extend_from_slice(data);
+		}
+		fs::write(dir.join("res.rifo"), format!("StarDict's storage ifo file\nversion=3.0.0\n\
+			filecount=2\nridxfilesize={}\n", ridx.len())).unwrap();
+		fs::write(dir.join("res.ridx"), &ridx).unwrap();
+		let mut dz = vec![];
+		DictZipWriter::new(4).write(&rdict, &mut dz).unwrap();
+		fs::write(dir.join("res.rdict.dz"), &dz).unwrap();
+
+		let dict = no_cache(&ifo).unwrap();
+		assert_eq!(dict.list_resources().unwrap(), vec!["apple.png", "sound/apple.wav"]);
+		assert!(dict.has_resource("/sound/apple.wav"));
+		assert!(!dict.has_resource("banana.png"));
+		assert_eq!(dict.get_resource("/sound/apple.wav").unwrap().unwrap(), b"wav");
+		let (mime, data) = dict.get_resource_typed("apple.png").unwrap().unwrap();
+		assert_eq!(mime, "image/png");
+		assert_eq!(data, files[0].1);
+		assert!(dict.get_resource("banana.png").is_err());
+
+		// res folder takes precedence
+		fs::create_dir_all(dir.join("res")).unwrap();
+		assert!(dict.list_resources().unwrap().is_empty());
+		assert!(!dict.has_resource("apple.png"));
+	}
+
+	#[test]
+	fn broken_rdict() {
+		let ifo = create_dict("broken_resource_db", &SAMPLE_WORDS, &[]);
+		let dir = ifo.parent().unwrap();
+		fs::write(dir.join("res.rifo"), "not a storage ifo
+").unwrap();
+
+		let dict = no_cache(&ifo).unwrap();
+		assert!(matches!(dict.get_resource("apple.png"), Err(Error::FailedLoadResource(name, _))
+			if name == "res.rifo"));
+		assert!(dict.list_resources().is_err());
+		assert!(!dict.has_resource("apple.png"));
+		// lookup not affected
+		assert!(dict.lookup("apple").unwrap().is_some());
+	}
+}
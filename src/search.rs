@@ -0,0 +1,202 @@
+/// collect headwords close to the word by edit distance,
+/// keys and word should be normalized by the same collation
+pub(crate) struct Suggestions {
+	word: Vec<char>,
+	max_distance: usize,
+	matched: Vec<(usize, String, String)>,
+}
+
+impl Suggestions {
+	pub(crate) fn new(word: &str, max_distance: usize) -> Self
+	{
+		Suggestions { word: word.chars().collect(), max_distance, matched: vec![] }
+	}
+
+	/// headword only built when the key matched
+	pub(crate) fn push<F>(&mut self, key: &str, headword: F)
+		where F: FnOnce() -> String
+	{
+		if let Some(distance) = edit_distance(&self.word, key, self.max_distance) {
+			self.matched.push((distance, key.to_owned(), headword()));
+		}
+	}
+
+	/// sorted by distance then key, limit == 0 means unbounded,
+	/// the first pushed headword of a key kept
+	pub(crate) fn finish(mut self, limit: usize) -> Vec<String>
+	{
+		self.matched.sort_by(|(a, a_key, _), (b, b_key, _)| (a, a_key).cmp(&(b, b_key)));
+		self.matched.dedup_by(|(_, b_key, _), (_, a_key, _)| a_key == b_key);
+		if limit > 0 {
+			self.matched.truncate(limit);
+		}
+		self.matched.into_iter().map(|(_, _, word)| word).collect()
+	}
+}
+
+#[derive(Debug, PartialEq)]
+enum GlobToken {
+	Char(char),
+	// ?
+	Any,
+	// *
+	Many,
+}
+
+/// glob pattern, * matches any run of characters and ? matches a single one
+pub(crate) struct Glob {
+	tokens: Vec<GlobToken>,
+}
+
+impl Glob {
+	pub(crate) fn new(pattern: &str) -> Self
+	{
+		let mut tokens = vec![];
+		for ch in pattern.chars() {
+			let token = match ch {
+				'*' => GlobToken::Many,
+				'?' => GlobToken::Any,
+				ch => GlobToken::Char(ch),
+			};
+			// successive * same as one
+			if token == GlobToken::Many && tokens.last() == Some(&GlobToken::Many) {
+				continue;
+			}
+			tokens.push(token);
+		}
+		Glob { tokens }
+	}
+
+	/// literal chars before the first wildcard
+	#[cfg(feature = "sled")]
+	pub(crate) fn prefix(&self) -> String
+	{
+		self.tokens.iter()
+			.map_while(|token| if let GlobToken::Char(ch) = token {
+				Some(*ch)
+			} else {
+				None
+			})
+			.collect()
+	}
+
+	pub(crate) fn matches(&self, str: &str) -> bool
+	{
+		let chars: Vec<char> = str.chars().collect();
+		let (mut t, mut c) = (0, 0);
+		// position of the last * and the char it matched up to
+		let mut backtrack = None;
+		while c < chars.len() {
+			match self.tokens.get(t) {
+				Some(GlobToken::Char(ch)) if *ch == chars[c] => {
+					t += 1;
+					c += 1;
+				}
+				Some(GlobToken::Any) => {
+					t += 1;
+					c += 1;
+				}
+				Some(GlobToken::Many) => {
+					backtrack = Some((t, c));
+					t += 1;
+				}
+				_ => if let Some((bt, bc)) = backtrack {
+					t = bt + 1;
+					c = bc + 1;
+					backtrack = Some((bt, bc + 1));
+				} else {
+					return false;
+				}
+			}
+		}
+		self.tokens[t..].iter().all(|token| *token == GlobToken::Many)
+	}
+
+	/// pattern for sql like, literal %, _ and \\ escaped by \\
+	#[cfg(feature = "sqlite")]
+	pub(crate) fn to_like(&self) -> String
+	{
+		let mut like = String::new();
+		for token in &self.tokens {
+			match token {
+				GlobToken::Many => like.push('%'),
+				GlobToken::Any => like.push('_'),
+				GlobToken::Char(ch) => {
+					if matches!(ch, '%' | '_' | '\\') {
+						like.push('\\');
+					}
+					like.push(*ch);
+				}
+			}
+		}
+		like
+	}
+}
+
+/// levenshtein distance, None if more than max
+fn edit_distance(a: &[char], b: &str, max: usize) -> Option<usize>
+{
+	let b: Vec<char> = b.chars().collect();
+	if a.len().abs_diff(b.len()) > max {
+		return None;
+	}
+	let mut prev: Vec<usize> = (0..=b.len()).collect();
+	let mut row = vec![0; b.len() + 1];
+	for (i, ca) in a.iter().enumerate() {
+		row[0] = i + 1;
+		for (j, cb) in b.iter().enumerate() {
+			let cost = if ca == cb { 0 } else { 1 };
+			row[j + 1] = (prev[j] + cost)
+				.min(prev[j + 1] + 1)
+				.min(row[j] + 1);
+		}
+		if row.iter().min().is_some_and(|min| *min > max) {
+			return None;
+		}
+		std::mem::swap(&mut prev, &mut row);
+	}
+	let distance = prev[b.len()];
+	if distance > max {
+		None
+	} else {
+		Some(distance)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::search::{edit_distance, Glob};
+
+	#[test]
+	fn distance() {
+		let kitten: Vec<char> = "kitten".chars().collect();
+		assert_eq!(edit_distance(&kitten, "sitting", 3), Some(3));
+		assert_eq!(edit_distance(&kitten, "sitting", 2), None);
+		assert_eq!(edit_distance(&kitten, "kitten", 0), Some(0));
+		assert_eq!(edit_distance(&kitten, "kit", 2), None);
+		assert_eq!(edit_distance(&[], "ab", 2), Some(2));
+	}
+
+	#[test]
+	fn glob() {
+		let glob = Glob::new("col*r");
+		assert!(glob.matches("color"));
+		assert!(glob.matches("colour"));
+		assert!(glob.matches("colr"));
+		assert!(!glob.matches("colors"));
+		#[cfg(feature = "sled")]
+		assert_eq!(glob.prefix(), "col");
+
+		let glob = Glob::new("gr?y");
+		assert!(glob.matches("grey"));
+		assert!(!glob.matches("gry"));
+		assert!(!glob.matches("greey"));
+
+		let glob = Glob::new("*a*a");
+		assert!(glob.matches("banana"));
+		assert!(!glob.matches("band"));
+		assert!(Glob::new("**").matches(""));
+		#[cfg(feature = "sqlite")]
+		assert_eq!(Glob::new("5%_*?\\").to_like(), "5\\%\\_%_\\\\");
+	}
+}
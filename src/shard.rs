@@ -0,0 +1,198 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use crate::error::{Error, Result};
+
+/// the file and its numbered shards file.2, file.3 and so on,
+/// stop at the first missing number
+pub(crate) fn shard_paths(path: &Path) -> Vec<PathBuf>
+{
+	let mut paths = vec![path.to_path_buf()];
+	let mut name = path.as_os_str().to_owned();
+	name.push(".2");
+	let mut shard = PathBuf::from(name);
+	while shard.is_file() {
+		paths.push(shard);
+		let mut name = path.as_os_str().to_owned();
+		name.push(format!(".{}", paths.len() + 1));
+		shard = PathBuf::from(name);
+	}
+	paths
+}
+
+/// shards read as one file, offsets span the concatenation
+pub(crate) struct Shards {
+	// file and offset of its first byte in the concatenation
+	files: Vec<(File, u64)>,
+	len: u64,
+	pos: u64,
+}
+
+impl Shards {
+	/// name of the file kind for errors
+	pub(crate) fn open(path: &Path, name: &'static str) -> Result<Shards>
+	{
+		let mut files = vec![];
+		let mut len = 0;
+		for path in shard_paths(path) {
+			let file = File::open(path).map_err(|e| Error::FailedOpenFile(name, e))?;
+			let size = file.metadata().map_err(|e| Error::FailedOpenFile(name, e))?.len();
+			files.push((file, len));
+			len += size;
+		}
+		Ok(Shards { files, len, pos: 0 })
+	}
+
+	/// total bytes of all shards
+	#[inline]
+	pub(crate) fn len(&self) -> u64
+	{
+		self.len
+	}
+
+	/// the file when not split
+	#[allow(unused)]
+	#[inline]
+	pub(crate) fn single(&self) -> Option<&File>
+	{
+		match self.files.as_slice() {
+			[(file, _)] => Some(file),
+			_ => None,
+		}
+	}
+}
+
+impl Read for Shards {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize>
+	{
+		if buf.is_empty() || self.pos >= self.len {
+			return Ok(0);
+		}
+		let index = self.files.partition_point(|(_, start)| *start <= self.pos) - 1;
+		let end = self.files.get(index + 1).map_or(self.len, |(_, start)| *start);
+		let (file, start) = &mut self.files[index];
+		file.seek(SeekFrom::Start(self.pos - *start))?;
+		let max = buf.len().min((end - self.pos) as usize);
+		let read = file.read(&mut buf[..max])?;
+		self.pos += read as u64;
+		Ok(read)
+	}
+}
+
+impl Seek for Shards {
+	fn seek(&mut self, pos: SeekFrom) -> io::Result<u64>
+	{
+		let pos = match pos {
+			SeekFrom::Start(pos) => Some(pos),
+			SeekFrom::End(delta) => self.len.checked_add_signed(delta),
+			SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+		};
+		self.pos = pos.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput,
+			"invalid seek to a negative or overflowing position"))?;
+		Ok(self.pos)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+	use std::io::{Read, Seek, SeekFrom};
+	use std::path::PathBuf;
+	use crate::shard::{shard_paths, Shards};
+	use crate::dictzip::tests::temp_file;
+	use crate::{no_cache, StarDict, WordDefinition};
+	use crate::tests::{create_dict, SAMPLE_WORDS, SYNONYMS};
+
+	#[test]
+	fn shards() {
+		let dir = temp_file("shards");
+		if dir.exists() {
+			fs::remove_dir_all(&dir).unwrap();
+		}
+		fs::create_dir_all(&dir).unwrap();
+		let path = dir.join("data");
+		fs::write(&path, b"hello ").unwrap();
+		fs::write(dir.join("data.2"), b"sharded ").unwrap();
+		fs::write(dir.join("data.3"), b"world").unwrap();
+		// not consecutive
+		fs::write(dir.join("data.5"), b"!").unwrap();
+		assert_eq!(shard_paths(&path).len(), 3);
+
+		let mut shards = Shards::open(&path, "dict").unwrap();
+		assert_eq!(shards.len(), 19);
+		assert!(shards.single().is_none());
+		let mut buf = vec![];
+		shards.read_to_end(&mut buf).unwrap();
+		assert_eq!(buf, b"hello sharded world");
+		// read across the shard boundary
+		shards.seek(SeekFrom::Start(4)).unwrap();
+		let mut buf = [0; 6];
+		shards.read_exact(&mut buf).unwrap();
+		assert_eq!(&buf, b"o shar");
+		shards.seek(SeekFrom::End(-3)).unwrap();
+		let mut buf = vec![];
+		shards.read_to_end(&mut buf).unwrap();
+		assert_eq!(buf, b"rld");
+		assert!(shards.seek(SeekFrom::Current(-100)).is_err());
+
+		fs::remove_file(dir.join("data.2")).unwrap();
+		assert!(Shards::open(&path, "dict").unwrap().single().is_some());
+	}
+
+	/// move the bytes after at into file.2
+	fn split_shards(path: &std::path::Path, at: usize) {
+		let data = fs::read(path).unwrap();
+		fs::write(path, &data[..at]).unwrap();
+		let mut shard = path.as_os_str().to_owned();
+		shard.push(".2");
+		fs::write(shard, &data[at..]).unwrap();
+	}
+
+	fn assert_shards(ifo: &PathBuf, expected: &[Option<Vec<WordDefinition>>], size: u64) {
+		let dicts: [Box<dyn StarDict>; 3] = [Box::new(no_cache(ifo).unwrap()),
+			Box::new(crate::no_cache_low_memory(ifo).unwrap()), Box::new(crate::with_memory(ifo).unwrap())];
+		for dict in dicts {
+			for ((word, _), definitions) in SAMPLE_WORDS.iter().zip(expected) {
+				assert_eq!(&dict.lookup(word).unwrap(), definitions, "{}", word);
+			}
+			assert_eq!(dict.lookup("pomme").unwrap(), expected[0]);
+			assert_eq!(dict.dict_size().unwrap(), size);
+		}
+	}
+
+	#[test]
+	fn sharded_dict() {
+		use std::io::Write;
+		use flate2::Compression;
+		use flate2::write::GzEncoder;
+
+		let ifo = create_dict("shards", &SAMPLE_WORDS, &SYNONYMS);
+		let dict = no_cache(&ifo).unwrap();
+		let expected: Vec<_> = SAMPLE_WORDS.iter().map(|(word, _)| dict.lookup(word).unwrap()).collect();
+		let size = dict.dict_size().unwrap();
+		drop(dict);
+		// cut in the middle of entries
+		split_shards(&ifo.with_extension("idx"), 7);
+		split_shards(&ifo.with_extension("dict"), 20);
+		assert_shards(&ifo, &expected, size);
+
+		// byte pieces of the gzipped idx and the dictzip
+		let dir = ifo.parent().unwrap();
+		let idx = [fs::read(ifo.with_extension("idx")).unwrap(), fs::read(dir.join("shards.idx.2")).unwrap()].concat();
+		let dict = [fs::read(ifo.with_extension("dict")).unwrap(), fs::read(dir.join("shards.dict.2")).unwrap()].concat();
+		for name in ["shards.idx", "shards.idx.2", "shards.dict", "shards.dict.2"] {
+			fs::remove_file(dir.join(name)).unwrap();
+		}
+		let mut encoder = GzEncoder::new(vec![], Compression::default());
+		encoder.write_all(&idx).unwrap();
+		fs::write(dir.join("shards.idx.gz"), encoder.finish().unwrap()).unwrap();
+		let mut dz = vec![];
+		crate::DictZipWriter::new(8).write(&dict, &mut dz).unwrap();
+		fs::write(dir.join("shards.dict.dz"), &dz).unwrap();
+		let size = fs::metadata(dir.join("shards.idx.gz")).unwrap().len() + dz.len() as u64
+			+ fs::metadata(ifo.with_extension("syn")).unwrap().len();
+		split_shards(&dir.join("shards.idx.gz"), 10);
+		split_shards(&dir.join("shards.dict.dz"), dz.len() / 2);
+		assert_shards(&ifo, &expected, size);
+	}
+}
@@ -2,9 +2,22 @@ use crate::dict::Dict;
 use crate::error::Result;
 use crate::idx::Idx;
 use crate::ifo::Ifo;
+use crate::opener::OpenOptions;
+use crate::resource::{ResourceDb, Resources};
 
 use std::path::PathBuf;
-use crate::{StarDict, WordDefinition};
+use crate::{into_definitions, limit_reached, source_size, MatchedDefinition, StarDict,
+	WordDefinition, WordDefinitionSegmentRef};
+
+/// result of StarDictStd::verify
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VerifyReport {
+	/// idx blocks checked
+	pub total: usize,
+	pub ok: usize,
+	/// headword and the reason of every failed block, in offset order
+	pub failed: Vec<(String, String)>,
+}
 
 pub struct StarDictStd {
 	path: PathBuf,
@@ -12,16 +25,156 @@ pub struct StarDictStd {
 	pub ifo: Ifo,
 	idx: Idx,
 	dict: Dict,
+	// idx, dict and syn files
+	files: Vec<PathBuf>,
+	resources: Resources,
+	follow_synonyms: bool,
 }
 
 impl StarDictStd {
 	#[inline]
+	#[allow(clippy::too_many_arguments)]
 	pub(crate) fn new(path: PathBuf, ifo: Ifo, idx: PathBuf, idx_gz: bool,
-		syn: Option<PathBuf>, dict: PathBuf, dict_bz: bool) -> Result<Self>
+		syn: Option<PathBuf>, syn_gz: bool, dict: PathBuf, dict_bz: bool, options: &OpenOptions) -> Result<Self>
+	{
+		let files = [Some(idx.clone()), Some(dict.clone()), syn.clone()].into_iter().flatten().collect();
+		let mut idx = Idx::new(idx, &ifo, idx_gz, syn, syn_gz, options)?;
+		let dict = Dict::open(dict, dict_bz, options)?;
+		idx.check_offsets(dict.data_size(), !options.verify_size)?;
+		Ok(StarDictStd { path, ifo, idx, dict, files, resources: Resources::new(),
+			follow_synonyms: options.follow_synonyms })
+	}
+
+	/// without following, lookup only the headword, not definitions by syn
+	#[inline]
+	pub fn set_follow_synonyms(&mut self, follow: bool)
+	{
+		self.follow_synonyms = follow;
+	}
+
+	/// definition of every idx block by offset, for the dict read sequentially,
+	/// definitions read while iterated, empty blocks skipped
+	pub fn iter_definitions(&self) -> impl Iterator<Item = Result<WordDefinition>> + '_
+	{
+		self.idx.blocks().into_iter().filter_map(|(word, offset, size)|
+			match self.dict.get_segments(&word, offset, size, &self.ifo) {
+				Ok(Some(segments)) => Some(Ok(WordDefinition { word, segments })),
+				Ok(None) => None,
+				Err(e) => Some(Err(e)),
+			})
+	}
+
+	/// suspicious idx entries skipped while opening, e.g. empty headwords,
+	/// for the paged idx those of the pages read so far,
+	/// then blocks looked up so far parsed leniently
+	pub fn idx_warnings(&self) -> Vec<String>
+	{
+		let mut warnings = self.idx.warnings();
+		warnings.extend(self.dict.warnings());
+		warnings
+	}
+
+	/// check every idx block is inside the dict and decodes,
+	/// the whole dict read, failures collected instead of returned
+	pub fn verify(&self) -> Result<VerifyReport>
+	{
+		let dict_size = self.dict.data_size();
+		let mut report = VerifyReport::default();
+		for (word, offset, size) in self.idx.blocks() {
+			report.total += 1;
+			if offset.checked_add(size).is_none_or(|end| end > dict_size) {
+				let reason = format!("block at {} of {} bytes out of the dict of {} bytes", offset, size, dict_size);
+				report.failed.push((word, reason));
+				continue;
+			}
+			match self.dict.get_segments(&word, offset, size, &self.ifo) {
+				Ok(_) => report.ok += 1,
+				Err(e) => report.failed.push((word, e.to_string())),
+			}
+		}
+		Ok(report)
+	}
+
+	/// lookup without copy of segment text, f called with the headword and
+	/// segments of every definition, borrowed from the dict data only while f runs,
+	/// false if not found, no lookup of this dict inside f
+	pub fn lookup_borrowed<F>(&self, word: &str, mut f: F) -> Result<bool>
+		where F: FnMut(&str, &[WordDefinitionSegmentRef])
 	{
-		let idx = Idx::new(idx, &ifo, idx_gz, syn)?;
-		let dict = Dict::new(dict, dict_bz)?;
-		Ok(StarDictStd { path, ifo, idx, dict })
+		let blocks = if let Some(blocks) = self.idx.lookup_blocks(word, self.follow_synonyms) {
+			blocks
+		} else {
+			return Ok(false);
+		};
+		let mut found = false;
+		for (_, _, block) in blocks {
+			self.dict.visit_definitions(&block, &self.ifo, |word, segments| {
+				found = true;
+				f(word, segments);
+			})?;
+		}
+		Ok(found)
+	}
+
+	/// dict blocks after max definitions not read
+	fn lookup_matched(&self, word: &str, max: usize) -> Result<Option<Vec<MatchedDefinition>>>
+	{
+		let blocks = if let Some(blocks) = self.idx.lookup_blocks(word, self.follow_synonyms) {
+			blocks
+		} else {
+			return Ok(None);
+		};
+
+		let mut definitions = vec![];
+		for (matched_key, via_synonym, block) in blocks {
+			if limit_reached(&definitions, max) {
+				break;
+			}
+			for definition in self.dict.get_definitions(&block, &self.ifo)? {
+				definitions.push(MatchedDefinition { definition, via_synonym, matched_key: matched_key.clone() });
+			}
+		}
+		if max > 0 {
+			definitions.truncate(max);
+		}
+		// in idx but nothing to show, same as not found
+		if definitions.is_empty() {
+			Ok(None)
+		} else {
+			Ok(Some(definitions))
+		}
+	}
+
+	/// chunk length and count of the dict.dz, None for plain dict,
+	/// small chunks make random reads cheap
+	#[inline]
+	pub fn compression_info(&self) -> Option<(usize, usize)>
+	{
+		self.dict.compression_info()
+	}
+
+	/// definition of a raw offset and size from the idx without the word map,
+	/// for custom indexes, word of the definition left empty
+	#[inline]
+	pub fn get_at(&self, offset: usize, size: usize) -> Result<Option<WordDefinition>>
+	{
+		self.dict.get_at(offset, size, &self.ifo)
+	}
+
+	/// offset and size in the dict of every block for the word as in idx,
+	/// synonym targets included when following, for diagnosing broken dicts
+	#[inline]
+	pub fn idx_blocks(&self, word: &str) -> Result<Option<Vec<(usize, usize)>>>
+	{
+		Ok(self.idx.offsets_of(word, self.follow_synonyms))
+	}
+
+	/// whole decompressed dict data, sliced by offsets of idx entries,
+	/// the entire dict is held in memory
+	#[inline]
+	pub fn read_dict(&self) -> Result<Vec<u8>>
+	{
+		self.dict.read_all()
 	}
 }
 
@@ -37,19 +190,280 @@ impl StarDict for StarDictStd {
 	}
 
 	#[inline]
-	fn lookup(&mut self, word: &str) -> Result<Option<Vec<WordDefinition>>> {
-		let blocks = if let Some(blocks) = self.idx.lookup_blocks(word) {
-			blocks
+	fn dict_source_filename(&self) -> Option<&str> {
+		self.dict.filename()
+	}
+
+	#[inline]
+	fn dict_source_comment(&self) -> Option<&str> {
+		self.dict.comment()
+	}
+
+	#[inline]
+	fn resource_db(&self) -> Result<Option<&ResourceDb>> {
+		self.resources.get(&self.path)
+	}
+
+	#[inline]
+	fn dict_size(&self) -> Result<u64> {
+		source_size(&self.path, self.files.iter().map(PathBuf::as_path))
+	}
+
+	#[inline]
+	fn lookup(&self, word: &str) -> Result<Option<Vec<WordDefinition>>> {
+		Ok(self.lookup_detailed(word)?.map(into_definitions))
+	}
+
+	/// definitions pushed into out directly, without the matched list
+	fn lookup_into(&self, word: &str, out: &mut Vec<WordDefinition>) -> Result<bool> {
+		out.clear();
+		if let Some(blocks) = self.idx.lookup_blocks(word, self.follow_synonyms) {
+			for (_, _, block) in blocks {
+				out.extend(self.dict.get_definitions(&block, &self.ifo)?);
+			}
+		}
+		Ok(!out.is_empty())
+	}
+
+	/// from idx without reading definitions
+	#[inline]
+	fn canonical_word(&self, word: &str) -> Result<Option<String>> {
+		Ok(self.idx.canonical_word(word))
+	}
+
+	#[inline]
+	fn contains(&self, word: &str) -> Result<bool> {
+		Ok(self.idx.contains(word, self.follow_synonyms))
+	}
+
+	fn lookup_exact(&self, word: &str) -> Result<Option<Vec<WordDefinition>>> {
+		if let Some(entry) = self.idx.lookup_exact(word) {
+			let definitions = self.dict.get_definitions(&entry, &self.ifo)?;
+			Ok(if definitions.is_empty() { None } else { Some(definitions) })
 		} else {
-			return Ok(None);
-		};
+			Ok(None)
+		}
+	}
 
-		let mut definitions = vec![];
-		for block in blocks {
-			if let Some(result) = self.dict.get_definition(block, &self.ifo)? {
-				definitions.push(result);
+	#[inline]
+	fn lookup_detailed(&self, word: &str) -> Result<Option<Vec<MatchedDefinition>>> {
+		self.lookup_matched(word, 0)
+	}
+
+	#[inline]
+	fn lookup_limited(&self, word: &str, max: usize) -> Result<Option<Vec<WordDefinition>>> {
+		Ok(self.lookup_matched(word, max)?.map(into_definitions))
+	}
+
+	fn search_prefix(&self, prefix: &str, limit: usize) -> Result<Vec<String>> {
+		Ok(self.idx.search_prefix(prefix, limit))
+	}
+
+	fn neighbors(&self, word: &str, before: usize, after: usize) -> Result<Vec<String>> {
+		Ok(self.idx.neighbors(word, before, after))
+	}
+
+	fn search_glob(&self, pattern: &str, limit: usize) -> Result<Vec<String>> {
+		Ok(self.idx.search_glob(pattern, limit))
+	}
+
+	fn synonyms_of(&self, word: &str) -> Result<Vec<String>> {
+		Ok(self.idx.synonyms_of(word))
+	}
+
+	fn suggest(&self, word: &str, max_distance: usize, limit: usize) -> Result<Vec<String>> {
+		Ok(self.idx.suggest(word, max_distance, limit))
+	}
+
+	fn words(&self) -> Result<Vec<String>> {
+		Ok(self.idx.words())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+	use crate::error::{Error, Result};
+	use crate::{no_cache, StarDict, WordDefinition, WordDefinitionSegmentRef};
+	use crate::tests::{create_dict, create_dict_with, SAMPLE_WORDS, SYNONYMS};
+
+	#[test]
+	fn get_at() {
+		let ifo = create_dict("get_at", &SAMPLE_WORDS, &[]);
+		let dict_path = ifo.with_extension("dict");
+		let mut dz = vec![];
+		crate::DictZipWriter::new(8).write(&fs::read(&dict_path).unwrap(), &mut dz).unwrap();
+		fs::remove_file(&dict_path).unwrap();
+		fs::write(ifo.with_extension("dict.dz"), &dz).unwrap();
+		let dict = no_cache(&ifo).unwrap();
+		// banana after Apple, application and apply
+		let offset = SAMPLE_WORDS[..3].iter().map(|(_, definition)| definition.len()).sum();
+		let definition = dict.get_at(offset, SAMPLE_WORDS[3].1.len()).unwrap().unwrap();
+		assert_eq!(definition.segments, dict.lookup("banana").unwrap().unwrap()[0].segments);
+		assert!(matches!(dict.get_at(dz.len() * 10, 4), Err(Error::InvalidDictBlock { .. })));
+	}
+
+	#[test]
+	fn idx_blocks() {
+		let ifo = create_dict("idx_blocks", &SAMPLE_WORDS, &SYNONYMS);
+		let offset_of = |i: usize| SAMPLE_WORDS[..i].iter().map(|(_, definition)| definition.len()).sum::<usize>();
+		let apple = (offset_of(0), SAMPLE_WORDS[0].1.len());
+		let banana = (offset_of(3), SAMPLE_WORDS[3].1.len());
+		let std = no_cache(&ifo).unwrap();
+		let mem = crate::with_memory(&ifo).unwrap();
+		for blocks in [std.idx_blocks("banana").unwrap(), mem.idx_blocks("BANANA").unwrap()] {
+			assert_eq!(blocks, Some(vec![banana]));
+		}
+		// both targets of the synonym
+		let mut blocks = std.idx_blocks("fruit").unwrap().unwrap();
+		blocks.sort();
+		assert_eq!(blocks, [apple, banana]);
+		assert_eq!(std.idx_blocks("cherry").unwrap(), None);
+
+		let definition = std.get_at(banana.0, banana.1).unwrap().unwrap();
+		assert_eq!(definition.segments[0].text, SAMPLE_WORDS[3].1);
+	}
+
+	#[test]
+	fn verify() {
+		let ifo = create_dict("verify", &SAMPLE_WORDS, &[]);
+		let report = no_cache(&ifo).unwrap().verify().unwrap();
+		assert_eq!((report.total, report.ok), (SAMPLE_WORDS.len(), SAMPLE_WORDS.len()));
+		assert!(report.failed.is_empty());
+
+		// size of banana past the dict end
+		let mut idx = fs::read(ifo.with_extension("idx")).unwrap();
+		let start = idx.windows(7).position(|w| w == b"banana\0").unwrap() + 7 + 4;
+		idx[start..start + 4].copy_from_slice(&1000u32.to_be_bytes());
+		fs::write(ifo.with_extension("idx"), idx).unwrap();
+		let report = no_cache(&ifo).unwrap().verify().unwrap();
+		assert_eq!((report.total, report.ok), (SAMPLE_WORDS.len(), SAMPLE_WORDS.len() - 1));
+		assert_eq!(report.failed.len(), 1);
+		assert_eq!(report.failed[0].0, "banana");
+		assert!(report.failed[0].1.contains("out of the dict"));
+	}
+
+	#[test]
+	fn iter_definitions() {
+		let ifo = create_dict("iter_definitions", &SAMPLE_WORDS, &SYNONYMS);
+		for dict in [no_cache(&ifo).unwrap(), crate::no_cache_low_memory(&ifo).unwrap()] {
+			let definitions: Vec<WordDefinition> = dict.iter_definitions().collect::<Result<_>>().unwrap();
+			assert_eq!(definitions.len(), dict.word_count());
+			// in idx order, the order of the dict data
+			for (definition, (word, text)) in definitions.iter().zip(SAMPLE_WORDS) {
+				assert_eq!(definition.word, word);
+				assert_eq!(definition.segments[0].text, text);
 			}
 		}
-		Ok(Some(definitions))
+
+		// dict data written in reverse of the idx order
+		let idx_path = ifo.with_extension("idx");
+		let dict_path = ifo.with_extension("dict");
+		let mut idx = fs::read(&idx_path).unwrap();
+		let data = fs::read(&dict_path).unwrap();
+		let mut fields = vec![];
+		let mut start = 0;
+		while start < idx.len() {
+			let field = start + idx[start..].iter().position(|&b| b == 0).unwrap() + 1;
+			fields.push(field);
+			start = field + 8;
+		}
+		let mut reversed = vec![];
+		for &field in fields.iter().rev() {
+			let number = |at: usize| u32::from_be_bytes(idx[at..at + 4].try_into().unwrap()) as usize;
+			let (offset, size) = (number(field), number(field + 4));
+			idx[field..field + 4].copy_from_slice(&(reversed.len() as u32).to_be_bytes());
+			reversed.extend_from_slice(&data[offset..offset + size]);
+		}
+		fs::write(&idx_path, &idx).unwrap();
+		fs::write(&dict_path, &reversed).unwrap();
+		let reversed_words: Vec<&str> = SAMPLE_WORDS.iter().rev().map(|(word, _)| *word).collect();
+		for dict in [no_cache(&ifo).unwrap(), crate::no_cache_low_memory(&ifo).unwrap()] {
+			let words: Vec<String> = dict.iter_definitions().map(|definition| definition.unwrap().word).collect();
+			assert_eq!(words, reversed_words);
+		}
+
+		let mut dz = vec![];
+		crate::DictZipWriter::new(8).write(&reversed, &mut dz).unwrap();
+		fs::remove_file(&dict_path).unwrap();
+		fs::write(ifo.with_extension("dict.dz"), &dz).unwrap();
+		let dict = no_cache(&ifo).unwrap();
+		let words: Vec<String> = dict.iter_definitions().map(|definition| definition.unwrap().word).collect();
+		assert_eq!(words, reversed_words);
+	}
+
+	#[test]
+	fn lookup_borrowed() {
+		use std::borrow::Cow;
+		let ifo = create_dict("lookup_borrowed", &SAMPLE_WORDS, &SYNONYMS);
+		let dict = no_cache(&ifo).unwrap();
+		for word in ["apple", "pomme", "fruit", "band"] {
+			let mut definitions = vec![];
+			assert!(dict.lookup_borrowed(word, |word, segments| {
+				assert!(segments.iter().all(|segment| matches!(segment.text, Cow::Borrowed(_))));
+				definitions.push(WordDefinition {
+					word: word.to_owned(),
+					segments: segments.iter().cloned().map(WordDefinitionSegmentRef::into_owned).collect(),
+				});
+			}).unwrap());
+			assert_eq!(Some(definitions), dict.lookup(word).unwrap());
+		}
+		assert!(!dict.lookup_borrowed("cherry", |_, _| panic!()).unwrap());
+
+		// decoded from other charsets
+		let ifo = create_dict_with("lookup_borrowed_latin1", "m", &[("cafe", &b"caf\xe9"[..])], &[]);
+		let dict = crate::no_cache_with_encoding(&ifo, crate::Encoding::Latin1).unwrap();
+		assert!(dict.lookup_borrowed("cafe", |_, segments|
+			assert!(matches!(&segments[0].text, Cow::Owned(text) if text == "café"))).unwrap());
+	}
+
+	#[test]
+	fn verify_dict() {
+		use crate::StarDictOpener;
+		let ifo = create_dict("verify_dict", &SAMPLE_WORDS, &[]);
+		let dict_path = ifo.with_extension("dict");
+		let mut dz = vec![];
+		crate::DictZipWriter::new(8).write(&fs::read(&dict_path).unwrap(), &mut dz).unwrap();
+		fs::remove_file(&dict_path).unwrap();
+		fs::write(ifo.with_extension("dict.dz"), &dz).unwrap();
+		assert!(StarDictOpener::new().verify_dict(true).open_std(&ifo).is_ok());
+
+		// crc32 of the trailer not matching
+		let len = dz.len();
+		dz[len - 8] ^= 0xff;
+		fs::write(ifo.with_extension("dict.dz"), &dz).unwrap();
+		assert!(no_cache(&ifo).unwrap().lookup("apple").unwrap().is_some());
+		assert!(matches!(StarDictOpener::new().verify_dict(true).open_std(&ifo), Err(Error::InvalidDict)));
+		assert!(matches!(StarDictOpener::new().verify_dict(true).open_memory(&ifo), Err(Error::InvalidDict)));
+	}
+
+	#[test]
+	fn low_memory() {
+		use crate::no_cache_low_memory;
+		let mut words = SAMPLE_WORDS.to_vec();
+		words.push(("apple", "a tech company"));
+		let ifo = create_dict("low_memory", &words, &[("fruit", "banana"), ("Apfel", "Apple")]);
+		let dict = no_cache(&ifo).unwrap();
+		let low_memory = no_cache_low_memory(&ifo).unwrap();
+		for word in ["apple", "APPLY", "band", "fruit", "banana", "apfel", "cherry"] {
+			assert_eq!(dict.lookup(word).unwrap(), low_memory.lookup(word).unwrap());
+		}
+		assert_eq!(low_memory.lookup("apple").unwrap().unwrap().len(), 2);
+		assert_eq!(dict.words().unwrap(), low_memory.words().unwrap());
+		// streamed over the raw idx, the first headword of apple kept
+		for prefix in ["a", "APP", "b", "z", ""] {
+			assert_eq!(dict.search_prefix(prefix, 0).unwrap(), low_memory.search_prefix(prefix, 0).unwrap());
+			assert_eq!(dict.search_prefix(prefix, 2).unwrap(), low_memory.search_prefix(prefix, 2).unwrap());
+		}
+		for pattern in ["ap*", "*an*", "b?nd", "*"] {
+			assert_eq!(dict.search_glob(pattern, 0).unwrap(), low_memory.search_glob(pattern, 0).unwrap());
+			assert_eq!(dict.search_glob(pattern, 1).unwrap(), low_memory.search_glob(pattern, 1).unwrap());
+		}
+		for word in ["aple", "bannana", "zzz"] {
+			assert_eq!(dict.suggest(word, 2, 0).unwrap(), low_memory.suggest(word, 2, 0).unwrap());
+		}
+		for (word, before, after) in [("apple", 0, 2), ("band", 3, 0), ("b", 1, 1), ("zoo", 10, 10), ("a", 2, 2)] {
+			assert_eq!(dict.neighbors(word, before, after).unwrap(), low_memory.neighbors(word, before, after).unwrap());
+		}
 	}
 }
@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use crate::dict::Dict;
+use crate::error::Result;
+use crate::idx::Idx;
+use crate::ifo::Ifo;
+use crate::opener::OpenOptions;
+use crate::resource::{ResourceDb, Resources};
+use crate::{into_definitions, limit_reached, source_size, MatchedDefinition, StarDict, WordDefinition};
+
+/// all definitions decoded into memory while opening,
+/// lookup without file access, memory used as large as the uncompressed dict
+pub struct StarDictCachedMem {
+	path: PathBuf,
+	ifo: Ifo,
+	// for search and syn, offsets not used after opened
+	idx: Idx,
+	// by normalized key, one for every block of the entry
+	definitions: HashMap<String, Vec<WordDefinition>>,
+	// from the dictzip header
+	dict_filename: Option<String>,
+	dict_comment: Option<String>,
+	// of the idx and dict read while opening
+	warnings: Vec<String>,
+	// idx, dict and syn files
+	files: Vec<PathBuf>,
+	resources: Resources,
+	follow_synonyms: bool,
+}
+
+impl StarDictCachedMem {
+	#[allow(clippy::too_many_arguments)]
+	pub(crate) fn new(path: PathBuf, ifo: Ifo, idx: PathBuf, idx_gz: bool,
+		syn: Option<PathBuf>, syn_gz: bool, dict: PathBuf, dict_bz: bool, options: &OpenOptions) -> Result<Self>
+	{
+		let files = [Some(idx.clone()), Some(dict.clone()), syn.clone()].into_iter().flatten().collect();
+		let mut idx = Idx::new(idx, &ifo, idx_gz, syn, syn_gz, options)?;
+		let dict = Dict::open(dict, dict_bz, options)?;
+		idx.check_offsets(dict.data_size(), !options.verify_size)?;
+		let mut definitions = HashMap::new();
+		for (key, entry) in idx.items().iter() {
+			let entry_definitions = dict.get_definitions(entry, &ifo)?;
+			if !entry_definitions.is_empty() {
+				definitions.insert(key.to_string(), entry_definitions);
+			}
+		}
+		let mut warnings = idx.warnings();
+		warnings.extend(dict.warnings());
+		let dict_filename = dict.filename().map(str::to_owned);
+		let dict_comment = dict.comment().map(str::to_owned);
+		Ok(StarDictCachedMem {
+			path,
+			ifo,
+			idx,
+			definitions,
+			dict_filename,
+			dict_comment,
+			warnings,
+			files,
+			resources: Resources::new(),
+			follow_synonyms: options.follow_synonyms,
+		})
+	}
+
+	/// suspicious idx entries skipped while opening, e.g. empty headwords,
+	/// and blocks parsed leniently
+	#[inline]
+	pub fn idx_warnings(&self) -> Vec<String>
+	{
+		self.warnings.clone()
+	}
+
+	fn lookup_matched(&self, word: &str, max: usize) -> Result<Option<Vec<MatchedDefinition>>>
+	{
+		let blocks = if let Some(blocks) = self.idx.lookup_blocks(word, self.follow_synonyms) {
+			blocks
+		} else {
+			return Ok(None);
+		};
+		let mut definitions = vec![];
+		'blocks: for (matched_key, via_synonym, _) in blocks {
+			for definition in self.definitions.get(&matched_key).into_iter().flatten() {
+				if limit_reached(&definitions, max) {
+					break 'blocks;
+				}
+				definitions.push(MatchedDefinition { definition: definition.clone(), via_synonym,
+					matched_key: matched_key.clone() });
+			}
+		}
+		if definitions.is_empty() {
+			Ok(None)
+		} else {
+			Ok(Some(definitions))
+		}
+	}
+
+	/// offset and size in the dict of every block for the word as in idx,
+	/// synonym targets included when following
+	#[inline]
+	pub fn idx_blocks(&self, word: &str) -> Result<Option<Vec<(usize, usize)>>>
+	{
+		Ok(self.idx.offsets_of(word, self.follow_synonyms))
+	}
+
+	/// without following, lookup only the headword, not definitions by syn
+	#[inline]
+	pub fn set_follow_synonyms(&mut self, follow: bool)
+	{
+		self.follow_synonyms = follow;
+	}
+}
+
+impl StarDict for StarDictCachedMem {
+	#[inline]
+	fn path(&self) -> &PathBuf {
+		&self.path
+	}
+
+	#[inline]
+	fn ifo(&self) -> &Ifo {
+		&self.ifo
+	}
+
+	#[inline]
+	fn dict_source_filename(&self) -> Option<&str> {
+		self.dict_filename.as_deref()
+	}
+
+	#[inline]
+	fn dict_source_comment(&self) -> Option<&str> {
+		self.dict_comment.as_deref()
+	}
+
+	#[inline]
+	fn resource_db(&self) -> Result<Option<&ResourceDb>> {
+		self.resources.get(&self.path)
+	}
+
+	#[inline]
+	fn dict_size(&self) -> Result<u64> {
+		source_size(&self.path, self.files.iter().map(PathBuf::as_path))
+	}
+
+	#[inline]
+	fn lookup(&self, word: &str) -> Result<Option<Vec<WordDefinition>>> {
+		Ok(self.lookup_detailed(word)?.map(into_definitions))
+	}
+
+	/// from idx without reading definitions
+	#[inline]
+	fn canonical_word(&self, word: &str) -> Result<Option<String>> {
+		Ok(self.idx.canonical_word(word))
+	}
+
+	#[inline]
+	fn contains(&self, word: &str) -> Result<bool> {
+		Ok(self.idx.contains(word, self.follow_synonyms))
+	}
+
+	fn lookup_exact(&self, word: &str) -> Result<Option<Vec<WordDefinition>>> {
+		let definitions: Vec<WordDefinition> = self.definitions.get(&self.idx.collation.normalize(word))
+			.into_iter()
+			.flatten()
+			.filter(|definition| definition.word == word)
+			.cloned()
+			.collect();
+		Ok(if definitions.is_empty() { None } else { Some(definitions) })
+	}
+
+	#[inline]
+	fn lookup_detailed(&self, word: &str) -> Result<Option<Vec<MatchedDefinition>>> {
+		self.lookup_matched(word, 0)
+	}
+
+	#[inline]
+	fn lookup_limited(&self, word: &str, max: usize) -> Result<Option<Vec<WordDefinition>>> {
+		Ok(self.lookup_matched(word, max)?.map(into_definitions))
+	}
+
+	fn search_prefix(&self, prefix: &str, limit: usize) -> Result<Vec<String>> {
+		Ok(self.idx.search_prefix(prefix, limit))
+	}
+
+	fn words(&self) -> Result<Vec<String>> {
+		Ok(self.idx.words())
+	}
+
+	fn neighbors(&self, word: &str, before: usize, after: usize) -> Result<Vec<String>> {
+		Ok(self.idx.neighbors(word, before, after))
+	}
+
+	fn suggest(&self, word: &str, max_distance: usize, limit: usize) -> Result<Vec<String>> {
+		Ok(self.idx.suggest(word, max_distance, limit))
+	}
+
+	fn search_glob(&self, pattern: &str, limit: usize) -> Result<Vec<String>> {
+		Ok(self.idx.search_glob(pattern, limit))
+	}
+
+	fn synonyms_of(&self, word: &str) -> Result<Vec<String>> {
+		Ok(self.idx.synonyms_of(word))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+	use crate::{no_cache, MatchedDefinition, StarDict};
+	use crate::tests::{create_dict, SAMPLE_WORDS, SYNONYMS, CASED_WORDS};
+
+	#[test]
+	fn memory() {
+		use crate::with_memory;
+		let mut words = SAMPLE_WORDS.to_vec();
+		words.extend_from_slice(&CASED_WORDS);
+		let ifo = create_dict("memory", &words, &SYNONYMS);
+		let expected = no_cache(&ifo).unwrap();
+		let dict = with_memory(&ifo).unwrap();
+		// no file access after opened
+		fs::remove_dir_all(ifo.parent().unwrap()).unwrap();
+		// order of definitions through syn not defined
+		let sorted = |mut definitions: Vec<MatchedDefinition>| {
+			definitions.sort_by(|a, b| a.matched_key.cmp(&b.matched_key));
+			definitions
+		};
+		for word in ["apple", "POMME", "fruit", "band", "polish", "cherry"] {
+			assert_eq!(dict.lookup_detailed(word).unwrap().map(sorted),
+				expected.lookup_detailed(word).unwrap().map(sorted));
+			assert_eq!(dict.lookup_exact(word).unwrap(), expected.lookup_exact(word).unwrap());
+		}
+		for (word, _) in CASED_WORDS {
+			assert_eq!(dict.lookup_exact(word).unwrap(), expected.lookup_exact(word).unwrap());
+		}
+		assert_eq!(dict.words().unwrap(), expected.words().unwrap());
+		assert_eq!(dict.search_prefix("ba", 0).unwrap(), expected.search_prefix("ba", 0).unwrap());
+		assert_eq!(dict.synonyms_of("apple").unwrap(), expected.synonyms_of("apple").unwrap());
+	}
+}
@@ -1,38 +1,150 @@
 use std::collections::HashSet;
-use std::path::PathBuf;
+use std::fs;
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
 use sled::{Config, Db};
 use crate::error::{Error, Result};
-use crate::{get_cache_dir, Ifo, StarDict, WordDefinition, WordDefinitionSegment};
+use crate::{disk_usage, limit_reached, source_size, CacheSource, Collation, into_definitions, MatchedDefinition, get_cache_dir, ImportProgress, PROGRESS_STEP, skipped_warning, source_stamp, Ifo, is_binary_type, StarDict, WordDefinition, WordDefinitionSegment, wait_until};
 use crate::dict::Dict;
+use crate::resource::{ResourceDb, Resources};
+use crate::opener::OpenOptions;
 use crate::idx::Idx;
+use crate::search::{Glob, Suggestions};
 
 pub const IDX_SLED_SUFFIX: &str = "idx.sled";
 pub const SYN_SLED_SUFFIX: &str = "syn.sled";
+const META_TREE: &str = "meta";
+// in syn db, synonyms of every headword
+const SYNONYMS_TREE: &str = "synonyms";
+const META_COLLATION: &str = "collation";
+const META_VERSION: &str = "version";
+const META_SOURCE: &str = "source";
+const META_INIT_STATUS: &str = "init_status";
+// entries skipped while importing, null-terminated
+const META_WARNINGS: &str = "warnings";
+const INIT_SUCCESS: &str = "success";
+// version 2 stores binary segments with size prefixed
+// version 3 stores synonyms of headwords
+// version 4 stores every block of merged entries as a definition
+const CACHE_VERSION: &str = "4";
 
 pub struct StarDictCachedSled {
 	path: PathBuf,
 	ifo: Ifo,
 	idx: Db,
 	syn: Option<Db>,
+	collation: Collation,
+	importing: Option<Importing>,
+	source: CacheSource,
+	// from the dictzip header
+	dict_filename: Option<String>,
+	dict_comment: Option<String>,
+	resources: Resources,
+	follow_synonyms: bool,
+	// stale cache removed while opening
+	rebuilt: bool,
+}
+
+/// the cache is importing in background, lookup with idx and dict before finished
+struct Importing {
+	idx: Arc<Idx>,
+	dict: Dict,
+	handle: Option<JoinHandle<()>>,
+}
+
+impl Drop for Importing {
+	/// wait the import, not leave the cache half built
+	fn drop(&mut self)
+	{
+		if let Some(handle) = self.handle.take() {
+			let _ = handle.join();
+		}
+	}
 }
 
 impl StarDictCachedSled {
+	#[allow(clippy::too_many_arguments)]
 	pub(crate) fn new(path: PathBuf, ifo: Ifo, idx: PathBuf, idx_gz: bool,
-		syn: Option<PathBuf>, dict: PathBuf, dict_dz: bool, cache_name: &str) -> Result<Self>
+		syn: Option<PathBuf>, syn_gz: bool, dict: PathBuf, dict_dz: bool, cache_name: &str,
+		options: &OpenOptions, progress: Option<ImportProgress>) -> Result<Self>
 	{
+		let collation = options.collation.clone();
 		let (idx_cache, syn_cache) = get_cache_dir(
-			&path, cache_name, IDX_SLED_SUFFIX, Some(SYN_SLED_SUFFIX))?;
+			&path, cache_name, options.cache_dir.as_deref(), IDX_SLED_SUFFIX, Some(SYN_SLED_SUFFIX))?;
+		let source = CacheSource {
+			idx: idx.clone(),
+			idx_gz,
+			syn: syn.clone(),
+			syn_gz,
+			dict: dict.clone(),
+			dict_dz,
+			cache_name: cache_name.to_owned(),
+			options: options.clone(),
+		};
 
-		let (idx, syn) = if !idx_cache.exists() {
-			import_cache(&ifo, idx_cache, syn_cache, idx, idx_gz, syn, dict, dict_dz)?
+		let stamp = source_stamp(&idx, &dict, &options.encoding)?;
+		let (dict_filename, dict_comment) = if dict_dz {
+			let dict = Dict::new(dict.clone(), true)?;
+			(dict.filename().map(str::to_owned), dict.comment().map(str::to_owned))
+		} else {
+			(None, None)
+		};
+		let mut rebuilt = false;
+		let cached = if idx_cache.exists() {
+			let idx_db = open_db(&idx_cache)?;
+			if check_cache(&idx_db, &collation, &stamp)? {
+				Some(idx_db)
+			} else {
+				// built with another collation or version, or source files changed,
+				// remove and build again
+				rebuilt = true;
+				drop(idx_db);
+				remove_cache(&idx_cache, syn_cache.as_ref())?;
+				None
+			}
+		} else {
+			None
+		};
+		let (idx, syn, importing) = if let Some(idx_db) = cached {
+			let syn = if let Some(syn_cache) = syn_cache.filter(|p| p.exists()) {
+				Some(open_db(&syn_cache)?)
+			} else {
+				None
+			};
+			(idx_db, syn, None)
 		} else {
-			let idx = open_db(idx_cache)?;
-			let syn = if let Some(syn_cache) = syn_cache {
-				Some(open_db(syn_cache)?)
+			let mut idx = Idx::new(idx, &ifo, idx_gz, syn, syn_gz, options)?;
+			let import_dict = Dict::open(dict.clone(), dict_dz, options)?;
+			idx.check_offsets(import_dict.data_size(), !options.verify_size)?;
+			let idx = Arc::new(idx);
+			let idx_db = sled::open(&idx_cache).map_err(sled_error_map)?;
+			let syn_db = if let (Some(syn_cache), Some(_)) = (&syn_cache, &idx.syn) {
+				Some(sled::open(syn_cache).map_err(sled_error_map)?)
 			} else {
 				None
 			};
-			(idx, syn)
+
+			let import_idx = idx.clone();
+			let import_idx_db = idx_db.clone();
+			let import_syn_db = syn_db.clone();
+			let import_ifo = ifo.clone();
+			let import_collation = collation.clone();
+			let handle = thread::spawn(move || {
+				if import_cache(&import_ifo, &import_idx_db, import_syn_db.as_ref(), &import_idx,
+					import_dict, &import_collation, &stamp, progress).is_err() {
+					eprint!("Failed import dictionary cache:{:#?}", idx_cache);
+				}
+			});
+			let importing = Importing {
+				idx,
+				dict: Dict::open(dict, dict_dz, options)?,
+				handle: Some(handle),
+			};
+			(idx_db, syn_db, Some(importing))
 		};
 
 		Ok(StarDictCachedSled {
@@ -40,100 +152,453 @@ impl StarDictCachedSled {
 			ifo,
 			idx,
 			syn,
+			collation,
+			importing,
+			source,
+			dict_filename,
+			dict_comment,
+			resources: Resources::new(),
+			follow_synonyms: options.follow_synonyms,
+			rebuilt,
 		})
 	}
-}
 
-impl StarDict for StarDictCachedSled {
+	/// without following, lookup only the headword, not definitions by syn
 	#[inline]
-	fn path(&self) -> &PathBuf {
-		&self.path
+	pub fn set_follow_synonyms(&mut self, follow: bool)
+	{
+		self.follow_synonyms = follow;
 	}
 
+	/// the cache imported, lookup with the source files before that
 	#[inline]
-	fn ifo(&self) -> &Ifo {
-		&self.ifo
+	pub fn cache_ready(&self) -> bool
+	{
+		import_completed(&self.idx)
 	}
 
-	fn lookup(&mut self, word: &str) -> Result<Option<Vec<WordDefinition>>> {
-		let lowercase_word = word.to_lowercase();
+	/// wait the background import up to timeout, false if not finished in time
+	#[inline]
+	pub fn wait_ready(&self, timeout: Duration) -> Result<bool>
+	{
+		wait_until(timeout, || Ok(self.cache_ready()))
+	}
+
+	/// a stale cache, built with another version, collation or source files,
+	/// found while opening and removed to import again
+	#[inline]
+	pub fn cache_rebuilt(&self) -> bool
+	{
+		self.rebuilt
+	}
+
+	/// suspicious entries skipped while importing the cache, e.g. empty headwords
+	/// and blocks without definition, Error::CacheInitiating before imported
+	pub fn idx_warnings(&self) -> Result<Vec<String>>
+	{
+		if !self.cache_ready() {
+			return Err(Error::CacheInitiating);
+		}
+		let meta = self.idx.open_tree(META_TREE).map_err(sled_error_map)?;
+		let warnings = meta.get(META_WARNINGS).map_err(sled_error_map)?;
+		Ok(warnings.map(|bytes| parse_strings(&bytes)).unwrap_or_default())
+	}
+
+	/// remove the cache and import again from the source files,
+	/// for corrupted caches, lookup with the source files until imported
+	pub fn rebuild(&mut self) -> Result<()>
+	{
+		// wait the import and close the cache before removal
+		self.importing = None;
+		self.idx = Config::new().temporary(true).open().map_err(sled_error_map)?;
+		self.syn = None;
+		let source = self.source.clone();
+		let follow_synonyms = self.follow_synonyms;
+		let (idx_cache, syn_cache) = get_cache_dir(&self.path, &source.cache_name,
+			source.options.cache_dir.as_deref(), IDX_SLED_SUFFIX, Some(SYN_SLED_SUFFIX))?;
+		remove_cache(&idx_cache, syn_cache.as_ref())?;
+		*self = Self::new(self.path.clone(), self.ifo.clone(), source.idx, source.idx_gz, source.syn, source.syn_gz,
+			source.dict, source.dict_dz, &source.cache_name, &source.options, None)?;
+		self.follow_synonyms = follow_synonyms;
+		Ok(())
+	}
+
+	/// wait the import, flush and release the caches, so the cache files
+	/// are not locked any more, e.g. for removal on windows
+	pub fn close(self) -> Result<()>
+	{
+		let StarDictCachedSled { idx, syn, importing, .. } = self;
+		drop(importing);
+		idx.flush().map_err(sled_error_map)?;
+		if let Some(syn) = &syn {
+			syn.flush().map_err(sled_error_map)?;
+		}
+		Ok(())
+	}
+
+	/// aliases after max definitions not read
+	fn lookup_matched(&self, word: &str, max: usize) -> Result<Option<Vec<MatchedDefinition>>>
+	{
+		if let (Some(idx), Some(importing)) = (self.importing_idx(), &self.importing) {
+			let blocks = if let Some(blocks) = idx.lookup_blocks(word, self.follow_synonyms) {
+				blocks
+			} else {
+				return Ok(None);
+			};
+			let mut definitions = vec![];
+			for (matched_key, via_synonym, block) in blocks {
+				if limit_reached(&definitions, max) {
+					break;
+				}
+				for definition in importing.dict.get_definitions(&block, &self.ifo)? {
+					definitions.push(MatchedDefinition { definition, via_synonym, matched_key: matched_key.clone() });
+				}
+			}
+			if max > 0 {
+				definitions.truncate(max);
+			}
+			return Ok(if definitions.is_empty() { None } else { Some(definitions) });
+		}
+		let lowercase_word = self.collation.normalize(word);
 		let mut vec = vec![];
 		let mut found = HashSet::new();
-		if let Some(definition) = get_definition(&self.idx, &lowercase_word)? {
-			found.insert(definition.word.clone());
-			vec.push(definition);
+		if let Some(definitions) = get_definitions(&self.idx, &lowercase_word)? {
+			found.insert(definitions[0].word.clone());
+			for definition in definitions {
+				vec.push(MatchedDefinition { definition, via_synonym: false, matched_key: lowercase_word.clone() });
+			}
 		}
-		if let Some(syn) = &self.syn {
-			if let Some(alias) = get_strings(&syn, &lowercase_word)? {
+		if let Some(syn) = self.syn.as_ref().filter(|_| self.follow_synonyms && !limit_reached(&vec, max)) {
+			if let Some(alias) = get_strings(syn, &lowercase_word)? {
 				for key in alias {
-					if let Some(definition) = get_definition(&self.idx, &key)? {
-						if !found.contains(&definition.word) {
-							found.insert(definition.word.clone());
-							vec.push(definition);
+					if limit_reached(&vec, max) {
+						break;
+					}
+					if let Some(definitions) = get_definitions(&self.idx, &key)? {
+						if !found.contains(&definitions[0].word) {
+							found.insert(definitions[0].word.clone());
+							for definition in definitions {
+								vec.push(MatchedDefinition { definition, via_synonym: true, matched_key: key.clone() });
+							}
 						}
 					}
 				}
 			}
 		}
-		let definitions = if vec.len() == 0 {
+		if max > 0 {
+			vec.truncate(max);
+		}
+		let definitions = if vec.is_empty() {
 			None
 		} else {
 			Some(vec)
 		};
 		Ok(definitions)
 	}
+
+	/// idx for lookup before the background import finished
+	fn importing_idx(&self) -> Option<&Idx>
+	{
+		let importing = self.importing.as_ref()?;
+		if import_completed(&self.idx) {
+			None
+		} else {
+			Some(&importing.idx)
+		}
+	}
 }
 
-fn import_cache(ifo: &Ifo, idx_cache: PathBuf, syn_cache: Option<PathBuf>,
-	idx: PathBuf, idx_gz: bool, syn: Option<PathBuf>, dict: PathBuf,
-	dict_dz: bool) -> Result<(Db, Option<Db>)>
-{
-	let idx = Idx::new(idx, ifo, idx_gz, syn.clone())?;
-	let mut dict = Dict::new(dict, dict_dz)?;
+impl StarDict for StarDictCachedSled {
+	#[inline]
+	fn path(&self) -> &PathBuf {
+		&self.path
+	}
 
-	let idx_db = sled::open(&idx_cache).map_err(sled_error_map)?;
-	let syn_db = if let Some(syn_cache) = &syn_cache {
-		Some(sled::open(syn_cache).map_err(sled_error_map)?)
-	} else {
-		None
-	};
+	#[inline]
+	fn ifo(&self) -> &Ifo {
+		&self.ifo
+	}
+
+	#[inline]
+	fn resource_db(&self) -> Result<Option<&ResourceDb>> {
+		self.resources.get(&self.path)
+	}
+
+	#[inline]
+	fn dict_size(&self) -> Result<u64> {
+		source_size(&self.path, self.source.files())
+	}
+
+	fn cache_size(&self) -> Result<u64> {
+		let (idx_cache, syn_cache) = get_cache_dir(&self.path, &self.source.cache_name,
+			self.source.options.cache_dir.as_deref(), IDX_SLED_SUFFIX, Some(SYN_SLED_SUFFIX))?;
+		let mut size = disk_usage(&idx_cache)?;
+		if let Some(syn_cache) = syn_cache {
+			size += disk_usage(&syn_cache)?;
+		}
+		Ok(size)
+	}
+
+	#[inline]
+	fn dict_source_filename(&self) -> Option<&str> {
+		self.dict_filename.as_deref()
+	}
+
+	#[inline]
+	fn dict_source_comment(&self) -> Option<&str> {
+		self.dict_comment.as_deref()
+	}
+
+	#[inline]
+	fn lookup(&self, word: &str) -> Result<Option<Vec<WordDefinition>>> {
+		Ok(self.lookup_detailed(word)?.map(into_definitions))
+	}
+
+	fn contains(&self, word: &str) -> Result<bool> {
+		if let Some(idx) = self.importing_idx() {
+			return Ok(idx.contains(word, self.follow_synonyms));
+		}
+		let key = self.collation.normalize(word);
+		if self.idx.contains_key(key.as_bytes()).map_err(sled_error_map)? {
+			return Ok(true);
+		}
+		if let Some(syn) = self.syn.as_ref().filter(|_| self.follow_synonyms) {
+			for key in get_strings(syn, &key)?.into_iter().flatten() {
+				if self.idx.contains_key(key.as_bytes()).map_err(sled_error_map)? {
+					return Ok(true);
+				}
+			}
+		}
+		Ok(false)
+	}
+
+	fn lookup_exact(&self, word: &str) -> Result<Option<Vec<WordDefinition>>> {
+		if let (Some(idx), Some(importing)) = (self.importing_idx(), &self.importing) {
+			return if let Some(entry) = idx.lookup_exact(word) {
+				let definitions = importing.dict.get_definitions(&entry, &self.ifo)?;
+				Ok(if definitions.is_empty() { None } else { Some(definitions) })
+			} else {
+				Ok(None)
+			};
+		}
+		let definitions: Vec<WordDefinition> = get_definitions(&self.idx, &self.collation.normalize(word))?
+			.into_iter()
+			.flatten()
+			.filter(|definition| definition.word == word)
+			.collect();
+		Ok(if definitions.is_empty() { None } else { Some(definitions) })
+	}
+
+	#[inline]
+	fn lookup_detailed(&self, word: &str) -> Result<Option<Vec<MatchedDefinition>>> {
+		self.lookup_matched(word, 0)
+	}
+
+	#[inline]
+	fn lookup_limited(&self, word: &str, max: usize) -> Result<Option<Vec<WordDefinition>>> {
+		Ok(self.lookup_matched(word, max)?.map(into_definitions))
+	}
+
+	fn search_prefix(&self, prefix: &str, limit: usize) -> Result<Vec<String>> {
+		if let Some(idx) = self.importing_idx() {
+			return Ok(idx.search_prefix(prefix, limit));
+		}
+		let lowercase_prefix = self.collation.normalize(prefix);
+		let mut words = vec![];
+		for item in self.idx.scan_prefix(lowercase_prefix.as_bytes()) {
+			let (_, value) = item.map_err(sled_error_map)?;
+			words.push(decode_word(&value));
+			if limit > 0 && words.len() >= limit {
+				break;
+			}
+		}
+		Ok(words)
+	}
+
+	fn words(&self) -> Result<Vec<String>> {
+		if let Some(idx) = self.importing_idx() {
+			return Ok(idx.words());
+		}
+		let mut words = vec![];
+		for item in self.idx.iter() {
+			let (_, value) = item.map_err(sled_error_map)?;
+			words.extend(decode_headwords(&value));
+		}
+		Ok(words)
+	}
+
+	fn neighbors(&self, word: &str, before: usize, after: usize) -> Result<Vec<String>> {
+		if let Some(idx) = self.importing_idx() {
+			return Ok(idx.neighbors(word, before, after));
+		}
+		let key = self.collation.normalize(word);
+		let mut words = vec![];
+		for item in self.idx.range(..key.as_bytes()).rev().take(before) {
+			let (_, value) = item.map_err(sled_error_map)?;
+			words.push(decode_word(&value));
+		}
+		words.reverse();
+		let range = (Bound::Excluded(key.as_bytes()), Bound::Unbounded);
+		for item in self.idx.range::<&[u8], _>(range).take(after) {
+			let (_, value) = item.map_err(sled_error_map)?;
+			words.push(decode_word(&value));
+		}
+		Ok(words)
+	}
+
+	fn search_glob(&self, pattern: &str, limit: usize) -> Result<Vec<String>> {
+		if let Some(idx) = self.importing_idx() {
+			return Ok(idx.search_glob(pattern, limit));
+		}
+		let glob = Glob::new(&self.collation.normalize(pattern));
+		let mut words = vec![];
+		for item in self.idx.scan_prefix(glob.prefix().as_bytes()) {
+			let (key, value) = item.map_err(sled_error_map)?;
+			if glob.matches(&String::from_utf8_lossy(&key)) {
+				words.push(decode_word(&value));
+				if limit > 0 && words.len() >= limit {
+					break;
+				}
+			}
+		}
+		Ok(words)
+	}
 
-	for (word, entry) in &idx.items {
-		let definition = if let Some(definition) = dict.get_definition(entry, ifo)? {
-			definition
+	fn synonyms_of(&self, word: &str) -> Result<Vec<String>> {
+		if let Some(idx) = self.importing_idx() {
+			return Ok(idx.synonyms_of(word));
+		}
+		let syn = if let Some(syn) = &self.syn {
+			syn
 		} else {
-			return Err(Error::InvalidIdxBlock(word.to_owned()));
+			return Ok(vec![]);
 		};
-		let key = word.to_lowercase();
-		let mut buf = vec![];
-		buf.append(&mut definition.word.into_bytes());
-		buf.push(0);
-		for segment in definition.segments {
-			buf.append(&mut segment.types.into_bytes());
-			buf.push(0);
-			buf.append(&mut segment.text.into_bytes());
-			buf.push(0);
+		let tree = syn.open_tree(SYNONYMS_TREE).map_err(sled_error_map)?;
+		let bytes = tree.get(self.collation.normalize(word).as_bytes()).map_err(sled_error_map)?;
+		Ok(bytes.map(|bytes| parse_strings(&bytes)).unwrap_or_default())
+	}
+
+	fn suggest(&self, word: &str, max_distance: usize, limit: usize) -> Result<Vec<String>> {
+		if let Some(idx) = self.importing_idx() {
+			return Ok(idx.suggest(word, max_distance, limit));
 		}
-		idx_db.insert(key.as_bytes(), buf.as_slice())
+		let mut suggestions = Suggestions::new(&self.collation.normalize(word), max_distance);
+		for item in self.idx.iter() {
+			let (key, value) = item.map_err(sled_error_map)?;
+			suggestions.push(&String::from_utf8_lossy(&key), || decode_word(&value));
+		}
+		Ok(suggestions.finish(limit))
+	}
+}
+
+#[allow(clippy::too_many_arguments)]
+fn import_cache(ifo: &Ifo, idx_db: &Db, syn_db: Option<&Db>, idx: &Idx, dict: Dict,
+	collation: &Collation, stamp: &str, mut progress: Option<ImportProgress>) -> Result<()>
+{
+	let mut imported = 0;
+	let mut warnings = idx.warnings();
+	for (word, entry) in idx.items().iter() {
+		// idx records of the key, counted as ifo.wordcount
+		let before = imported;
+		imported += entry.blocks.len();
+		if let Some(progress) = &mut progress {
+			if imported / PROGRESS_STEP != before / PROGRESS_STEP {
+				progress(imported, ifo.wordcount);
+			}
+		}
+		// skip the entry, not fail the whole cache
+		let definitions = match dict.get_definitions(entry, ifo) {
+			Ok(definitions) if !definitions.is_empty() => definitions,
+			result => {
+				warnings.push(skipped_warning(&entry.word, result.err()));
+				continue;
+			}
+		};
+		let buf = encode_definitions(definitions);
+		idx_db.insert(word.as_bytes(), buf.as_slice())
 			.map_err(sled_error_map)?;
 	}
+	if let Some(progress) = &mut progress {
+		progress(imported, ifo.wordcount);
+	}
+	warnings.extend(dict.warnings());
 
-	if let Some(syn_db) = &syn_db {
-		for (key, aliases) in idx.syn.unwrap() {
+	if let (Some(syn_db), Some(syn)) = (syn_db, &idx.syn) {
+		for (key, aliases) in syn {
 			let mut buf = vec![];
 			for alias in aliases {
-				buf.append(&mut alias.to_lowercase().into_bytes());
+				buf.extend_from_slice(alias.as_bytes());
 				buf.push(0);
 			}
-			syn_db.insert(key.to_lowercase().as_bytes(), buf.as_slice())
+			syn_db.insert(key.as_bytes(), buf.as_slice())
 				.map_err(sled_error_map)?;
 		}
 	}
-	Ok((idx_db, syn_db))
+	if let (Some(syn_db), Some(synonyms)) = (syn_db, &idx.synonyms) {
+		let tree = syn_db.open_tree(SYNONYMS_TREE).map_err(sled_error_map)?;
+		for (key, synonyms) in synonyms {
+			let mut buf = vec![];
+			for synonym in synonyms {
+				buf.extend_from_slice(synonym.as_bytes());
+				buf.push(0);
+			}
+			tree.insert(key.as_bytes(), buf).map_err(sled_error_map)?;
+		}
+	}
+	let meta = idx_db.open_tree(META_TREE).map_err(sled_error_map)?;
+	meta.insert(META_VERSION, CACHE_VERSION).map_err(sled_error_map)?;
+	meta.insert(META_COLLATION, collation.id().as_bytes()).map_err(sled_error_map)?;
+	meta.insert(META_SOURCE, stamp.as_bytes()).map_err(sled_error_map)?;
+	let mut buf = vec![];
+	for warning in warnings {
+		buf.extend_from_slice(warning.as_bytes());
+		buf.push(0);
+	}
+	meta.insert(META_WARNINGS, buf).map_err(sled_error_map)?;
+	if let Some(syn_db) = syn_db {
+		syn_db.flush().map_err(sled_error_map)?;
+	}
+	idx_db.flush().map_err(sled_error_map)?;
+	// mark finished at last
+	meta.insert(META_INIT_STATUS, INIT_SUCCESS).map_err(sled_error_map)?;
+	meta.flush().map_err(sled_error_map)?;
+	Ok(())
+}
+
+#[inline]
+fn import_completed(db: &Db) -> bool
+{
+	db.open_tree(META_TREE)
+		.and_then(|meta| meta.get(META_INIT_STATUS))
+		.is_ok_and(|status| status.as_deref() == Some(INIT_SUCCESS.as_bytes()))
+}
+
+/// check the cache import finished, built with current version, the collation and the same source files,
+/// caches without collation use the default
+fn check_cache(db: &Db, collation: &Collation, stamp: &str) -> Result<bool>
+{
+	if !import_completed(db) {
+		return Ok(false);
+	}
+	let meta = db.open_tree(META_TREE).map_err(sled_error_map)?;
+	let version = meta.get(META_VERSION).map_err(sled_error_map)?;
+	if version.as_deref() != Some(CACHE_VERSION.as_bytes()) {
+		return Ok(false);
+	}
+	let source = meta.get(META_SOURCE).map_err(sled_error_map)?;
+	if source.as_deref() != Some(stamp.as_bytes()) {
+		return Ok(false);
+	}
+	let id = if let Some(id) = meta.get(META_COLLATION).map_err(sled_error_map)? {
+		String::from_utf8_lossy(id.as_ref()).to_string()
+	} else {
+		Collation::default().id()
+	};
+	Ok(id == collation.id())
 }
 
 #[inline]
-fn open_db(path: PathBuf) -> Result<Db>
+fn open_db(path: &Path) -> Result<Db>
 {
 	Config::new()
 		.path(path)
@@ -142,27 +607,136 @@ fn open_db(path: PathBuf) -> Result<Db>
 		.map_err(sled_error_map)
 }
 
+/// remove the idx and syn cache if exists
+pub(crate) fn remove_cache(idx_cache: &Path, syn_cache: Option<&PathBuf>) -> Result<()>
+{
+	if idx_cache.exists() {
+		fs::remove_dir_all(idx_cache)?;
+	}
+	if let Some(syn_cache) = syn_cache.filter(|p| p.exists()) {
+		fs::remove_dir_all(syn_cache)?;
+	}
+	Ok(())
+}
+
 #[inline]
 fn sled_error_map(error: sled::Error) -> Error
 {
 	Error::FailedOpenCache(error.to_string())
 }
 
-fn get_definition(db: &Db, lowercase_key: &str) -> Result<Option<WordDefinition>>
+/// headword of the first definition null-terminated for decode_word,
+/// then every definition prefixed with 4 bytes big-endian size
+fn encode_definitions(definitions: Vec<WordDefinition>) -> Vec<u8>
+{
+	let mut buf = vec![];
+	if let Some(definition) = definitions.first() {
+		buf.extend_from_slice(definition.word.as_bytes());
+	}
+	buf.push(0);
+	for definition in definitions {
+		let mut data = encode_definition(definition);
+		buf.extend_from_slice(&(data.len() as u32).to_be_bytes());
+		buf.append(&mut data);
+	}
+	buf
+}
+
+fn decode_definitions(buf: &[u8]) -> Vec<WordDefinition>
 {
-	let strings = get_strings(db, lowercase_key)?;
-	if let Some(strings) = strings {
-		let mut iter = strings.into_iter();
-		let word = iter.next().unwrap();
-		let mut entry = WordDefinition { word, segments: vec![] };
-		while let Some(types) = iter.next() {
-			let text = iter.next().unwrap();
-			entry.segments.push(WordDefinitionSegment { types, text });
+	let (_, mut buf) = next_string(buf);
+	let mut definitions = vec![];
+	while buf.len() >= 4 {
+		let size = u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) as usize;
+		let end = (4 + size).min(buf.len());
+		definitions.push(decode_definition(&buf[4..end]));
+		buf = &buf[end..];
+	}
+	definitions
+}
+
+/// distinct headwords of the encoded definitions, segments not decoded
+fn decode_headwords(buf: &[u8]) -> Vec<String>
+{
+	let (_, mut buf) = next_string(buf);
+	let mut words: Vec<String> = vec![];
+	while buf.len() >= 4 {
+		let size = u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) as usize;
+		let end = (4 + size).min(buf.len());
+		let word = decode_word(&buf[4..end]);
+		if !words.contains(&word) {
+			words.push(word);
 		}
-		Ok(Some(entry))
-	} else {
-		Ok(None)
+		buf = &buf[end..];
 	}
+	words
+}
+
+/// word, then types and text of every segment, all null-terminated,
+/// data of binary segment is prefixed with 4 bytes big-endian size instead
+fn encode_definition(definition: WordDefinition) -> Vec<u8>
+{
+	let mut buf = vec![];
+	buf.append(&mut definition.word.into_bytes());
+	buf.push(0);
+	for segment in definition.segments {
+		buf.append(&mut segment.types.into_bytes());
+		buf.push(0);
+		if let Some(mut data) = segment.data {
+			buf.extend_from_slice(&(data.len() as u32).to_be_bytes());
+			buf.append(&mut data);
+		} else {
+			buf.append(&mut segment.text.into_bytes());
+			buf.push(0);
+		}
+	}
+	buf
+}
+
+fn decode_definition(buf: &[u8]) -> WordDefinition
+{
+	let (word, mut buf) = next_string(buf);
+	let mut definition = WordDefinition { word, segments: vec![] };
+	while !buf.is_empty() {
+		let (types, remain) = next_string(buf);
+		let segment = if is_binary_type(&types) {
+			if remain.len() < 4 {
+				break;
+			}
+			let size = u32::from_be_bytes([remain[0], remain[1], remain[2], remain[3]]) as usize;
+			let end = (4 + size).min(remain.len());
+			let data = remain[4..end].to_vec();
+			buf = &remain[end..];
+			WordDefinitionSegment { types, text: String::new(), data: Some(data) }
+		} else {
+			let (text, remain) = next_string(remain);
+			buf = remain;
+			WordDefinitionSegment { types, text, data: None }
+		};
+		definition.segments.push(segment);
+	}
+	definition
+}
+
+#[inline]
+fn decode_word(buf: &[u8]) -> String
+{
+	next_string(buf).0
+}
+
+/// return the null-terminated string and the remain buf
+#[inline]
+fn next_string(buf: &[u8]) -> (String, &[u8])
+{
+	let end = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+	let str = String::from_utf8_lossy(&buf[..end]).to_string();
+	(str, &buf[(end + 1).min(buf.len())..])
+}
+
+fn get_definitions(db: &Db, lowercase_key: &str) -> Result<Option<Vec<WordDefinition>>>
+{
+	let bytes = db.get(lowercase_key.as_bytes()).map_err(sled_error_map)?;
+	Ok(bytes.map(|bytes| decode_definitions(bytes.as_ref())).filter(|definitions| !definitions.is_empty()))
 }
 
 #[inline]
@@ -175,17 +749,224 @@ fn get_strings(db: &Db, lowercase_key: &str) -> Result<Option<Vec<String>>>
 	} else {
 		return Ok(None);
 	};
-	let buf = bytes.as_ref();
+	Ok(Some(parse_strings(bytes.as_ref())))
+}
+
+fn parse_strings(buf: &[u8]) -> Vec<String>
+{
 	let mut strings = vec![];
 	let mut start = 0;
 	while start < buf.len() {
 		let mut end = start;
-		while bytes[end] != 0 {
+		while buf[end] != 0 {
 			end += 1;
 		}
 		let str = String::from_utf8_lossy(&buf[start..end]).to_string();
 		strings.push(str);
 		start = end + 1;
 	}
-	Ok(Some(strings))
-}
\ No newline at end of file
+	strings
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+	use std::time::Duration;
+	use crate::dictzip::tests::temp_file;
+	use crate::error::Error;
+	use crate::{Collation, StarDict};
+	use crate::tests::{assert_broken_block, assert_follow_synonyms, broken_block_dict, cache_file, cache_file_exists,
+		create_dict, create_sized_dict, latin1_dict, update_source, CACHE_NAME, COLLATION_WORDS, SAMPLE_WORDS, SYNONYMS};
+
+	#[test]
+	fn wait_ready() {
+		use crate::{clear_cache, with_sled};
+		let ifo = create_dict("wait_ready_sled", &SAMPLE_WORDS, &SYNONYMS);
+		clear_cache(&ifo, CACHE_NAME).unwrap();
+		let dict = with_sled(&ifo, CACHE_NAME).unwrap();
+		assert!(dict.wait_ready(Duration::from_secs(30)).unwrap());
+		assert!(dict.cache_ready());
+		assert!(dict.lookup("pomme").unwrap().is_some());
+	}
+
+	#[test]
+	fn collation() {
+		use crate::{with_sled, with_sled_collation};
+		let ifo = create_dict("collation_sled", &COLLATION_WORDS, &[]);
+		let dict = with_sled(&ifo, CACHE_NAME).unwrap();
+		assert!(dict.lookup("äpfel").unwrap().is_some());
+		drop(dict);
+
+		// cache built again with the new collation
+		let dict = with_sled_collation(&ifo, CACHE_NAME, Collation::AsciiLower).unwrap();
+		assert!(dict.lookup("äpfel").unwrap().is_none());
+		assert!(dict.lookup("Äpfel").unwrap().is_some());
+	}
+
+	#[test]
+	fn source_changed() {
+		use crate::with_sled;
+		let ifo = create_dict("source_changed_sled", &[("apple", "old fruit")], &[]);
+		let dict = with_sled(&ifo, CACHE_NAME).unwrap();
+		assert_eq!(dict.lookup("apple").unwrap().unwrap()[0].segments[0].text, "old fruit");
+		assert!(!dict.cache_rebuilt());
+		drop(dict);
+
+		let ifo = update_source("source_changed_sled");
+		let dict = with_sled(&ifo, CACHE_NAME).unwrap();
+		assert!(dict.cache_rebuilt());
+		assert_eq!(dict.lookup("apple").unwrap().unwrap()[0].segments[0].text, "new fruit");
+	}
+
+	#[test]
+	fn progress() {
+		use std::sync::{Arc, Mutex};
+		use crate::with_sled_progress;
+		let mut words = SAMPLE_WORDS.to_vec();
+		// two idx records of one key
+		words.push(("apple", "a tech company"));
+		let ifo = create_dict("progress_sled", &words, &[]);
+		let last = Arc::new(Mutex::new(None));
+		let last2 = last.clone();
+		let dict = with_sled_progress(&ifo, CACHE_NAME, move |current, total|
+			*last2.lock().unwrap() = Some((current, total))).unwrap();
+		// wait the background import
+		drop(dict);
+		assert_eq!(*last.lock().unwrap(), Some((words.len(), words.len())));
+	}
+
+	#[test]
+	fn importing() {
+		use crate::with_sled;
+		let words: Vec<(String, String)> = (0..20000)
+			.map(|i| (format!("word{}", i), format!("definition of {}", i)))
+			.collect();
+		let words: Vec<(&str, &str)> = words.iter()
+			.map(|(word, definition)| (word.as_str(), definition.as_str()))
+			.collect();
+		let ifo = create_dict("importing_sled", &words, &[("alias", "word42")]);
+		// lookup while importing in background
+		let dict = with_sled(&ifo, CACHE_NAME).unwrap();
+		let definitions = dict.lookup("WORD42").unwrap().unwrap();
+		assert_eq!(definitions[0].segments[0].text, "definition of 42");
+		assert_eq!(dict.lookup("alias").unwrap().unwrap()[0].word, "word42");
+		assert_eq!(dict.search_prefix("word1999", 0).unwrap().len(), 11);
+		drop(dict);
+
+		// import finished when dropped
+		let dict = with_sled(&ifo, CACHE_NAME).unwrap();
+		assert_eq!(dict.lookup("alias").unwrap().unwrap()[0].word, "word42");
+		assert_eq!(dict.words().unwrap().len(), words.len());
+	}
+
+	#[test]
+	fn cache_root() {
+		use crate::with_sled_in;
+		let ifo = create_dict("cache_root_sled", &SAMPLE_WORDS, &[]);
+		let cache_root = temp_file("cache_root_sled_cache");
+		let dict = with_sled_in(&ifo, CACHE_NAME, &cache_root).unwrap();
+		assert!(dict.lookup("apple").unwrap().is_some());
+		assert!(cache_file_exists(&cache_root, "idx.sled"));
+	}
+
+	#[test]
+	fn dict_size() {
+		use crate::{disk_usage, with_sled_in};
+		let (ifo, size) = create_sized_dict("dict_size_sled");
+		let cache_root = temp_file("dict_size_sled_cache");
+		drop(with_sled_in(&ifo, CACHE_NAME, &cache_root).unwrap());
+		let dict = with_sled_in(&ifo, CACHE_NAME, &cache_root).unwrap();
+		assert_eq!(dict.dict_size().unwrap(), size);
+		let cache_size = dict.cache_size().unwrap();
+		assert!(cache_size > 0);
+		assert_eq!(cache_size, disk_usage(&cache_root).unwrap());
+	}
+
+	#[test]
+	fn close() {
+		use crate::with_sled_in;
+		let ifo = create_dict("close_sled", &SAMPLE_WORDS, &SYNONYMS);
+		let cache_root = temp_file("close_sled_cache");
+		// while importing
+		with_sled_in(&ifo, CACHE_NAME, &cache_root).unwrap().close().unwrap();
+		let dict = with_sled_in(&ifo, CACHE_NAME, &cache_root).unwrap();
+		assert!(dict.lookup("apple").unwrap().is_some());
+		dict.close().unwrap();
+		// not locked, also on windows
+		fs::remove_dir_all(&cache_root).unwrap();
+		assert!(!cache_root.exists());
+	}
+
+	#[test]
+	fn rebuild() {
+		use crate::{clear_cache_in, with_sled_in};
+		let ifo = create_dict("rebuild_sled", &SAMPLE_WORDS, &[]);
+		let cache_root = temp_file("rebuild_sled_cache");
+		drop(with_sled_in(&ifo, CACHE_NAME, &cache_root).unwrap());
+
+		// corrupt the cache
+		let db = sled::open(cache_file(&cache_root, "idx.sled")).unwrap();
+		db.remove("apple").unwrap();
+		db.flush().unwrap();
+		drop(db);
+		let mut dict = with_sled_in(&ifo, CACHE_NAME, &cache_root).unwrap();
+		assert!(dict.lookup("apple").unwrap().is_none());
+
+		dict.rebuild().unwrap();
+		assert!(dict.lookup("apple").unwrap().is_some());
+		drop(dict);
+		let dict = with_sled_in(&ifo, CACHE_NAME, &cache_root).unwrap();
+		assert!(dict.lookup("apple").unwrap().is_some());
+		drop(dict);
+
+		clear_cache_in(&ifo, CACHE_NAME, &cache_root).unwrap();
+		assert!(!cache_file_exists(&cache_root, "idx.sled"));
+	}
+
+	#[test]
+	fn opener() {
+		use crate::{Backend, StarDictOpener};
+		let ifo = create_dict("opener_sled", &SAMPLE_WORDS, &SYNONYMS);
+		let cache_root = temp_file("opener_sled_cache");
+		let opener = StarDictOpener::new()
+			.cache(Backend::Sled(CACHE_NAME.to_owned()))
+			.cache_dir(&cache_root);
+		let dict = opener.clone().follow_synonyms(false).open(&ifo).unwrap();
+		assert_follow_synonyms(&*dict, false);
+		assert!(cache_file_exists(&cache_root, "idx.sled"));
+		drop(dict);
+		assert!(matches!(opener.decoder(crate::Encoding::Custom(|_| String::new())).open(&ifo),
+			Err(Error::UnsupportedEncoding(_))));
+	}
+
+	#[test]
+	fn latin1() {
+		use crate::{Encoding, StarDictOpener};
+		let ifo = latin1_dict("latin1_sled");
+		let dict = StarDictOpener::new().open_sled(&ifo, CACHE_NAME).unwrap();
+		assert!(dict.wait_ready(Duration::from_secs(30)).unwrap());
+		assert!(dict.lookup("café").unwrap().is_none());
+		drop(dict);
+
+		// built again for another encoding
+		let dict = StarDictOpener::new().decoder(Encoding::Latin1).open_sled(&ifo, CACHE_NAME).unwrap();
+		assert!(dict.cache_rebuilt());
+		assert!(dict.wait_ready(Duration::from_secs(30)).unwrap());
+		assert_eq!(dict.lookup("café").unwrap().unwrap()[0].segments[0].text, "a café");
+		drop(dict);
+		let dict = StarDictOpener::new().encoding("latin1").open_sled(&ifo, CACHE_NAME).unwrap();
+		assert!(!dict.cache_rebuilt());
+		assert!(dict.lookup("café").unwrap().is_some());
+	}
+
+	#[test]
+	fn broken_block() {
+		use crate::with_sled;
+		let ifo = broken_block_dict("broken_block_sled");
+		let dict = with_sled(&ifo, CACHE_NAME).unwrap();
+		assert!(matches!(dict.idx_warnings(), Err(Error::CacheInitiating)) || dict.cache_ready());
+		drop(dict);
+		let dict = with_sled(&ifo, CACHE_NAME).unwrap();
+		assert_broken_block(&dict, dict.idx_warnings().unwrap());
+	}
+}
@@ -1,16 +1,26 @@
 use std::collections::HashSet;
-use std::path::PathBuf;
-use std::sync::{Arc, Mutex};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
 use std::{fs, process, thread};
+use std::thread::JoinHandle;
+use std::time::Duration;
 use std::str::FromStr;
 use process_alive::{Pid, State};
-use rusqlite::{Connection, OpenFlags, params};
+use rusqlite::{Connection, OpenFlags, OptionalExtension, params};
 use crate::error::{Error, Result};
-use crate::{get_cache_dir, Ifo, StarDict, WordDefinition, WordDefinitionSegment};
+use crate::{disk_usage, limit_reached, source_size, CacheSource, Collation, into_definitions, MatchedDefinition, cache_location, get_cache_dir, ImportProgress, PROGRESS_STEP, skipped_warning, source_stamp, Ifo, StarDict, WordDefinition, WordDefinitionSegment, wait_until};
 use crate::dict::Dict;
+use crate::resource::{ResourceDb, Resources};
+use crate::opener::OpenOptions;
 use crate::idx::Idx;
+use crate::search::{Glob, Suggestions};
 
 pub const IDX_SQLITE_SUFFIX: &str = "sqlite";
+// version 2 add data column for binary segment
+// version 3 add synonym table for synonyms of headwords
+// version 4 add entry and headword columns, blocks of merged entries kept apart
+const CACHE_VERSION: &str = "4";
 
 enum InnerDb {
 	Loaded(Connection),
@@ -21,16 +31,57 @@ enum InnerDb {
 pub struct StarDictCachedSqlite {
 	path: PathBuf,
 	ifo: Ifo,
-	db: InnerDb,
+	// connection is not Sync, queries go one by one
+	db: Mutex<InnerDb>,
 	has_syn: bool,
+	collation: Collation,
+	source: CacheSource,
+	// from the dictzip header
+	dict_filename: Option<String>,
+	dict_comment: Option<String>,
+	resources: Resources,
+	follow_synonyms: bool,
+	importing: Option<Importing>,
+	// stale cache removed while opening
+	rebuilt: bool,
+}
+
+/// the background import thread, canceled and joined on drop
+struct Importing {
+	handle: Option<JoinHandle<()>>,
+	cancel: Arc<AtomicBool>,
+}
+
+impl Importing {
+	/// wait the import finished
+	fn join(mut self)
+	{
+		if let Some(handle) = self.handle.take() {
+			let _ = handle.join();
+		}
+	}
+}
+
+impl Drop for Importing {
+	/// stop the import, not leave the cache locked by a detached thread,
+	/// the partial cache rebuilt on next open
+	fn drop(&mut self)
+	{
+		if let Some(handle) = self.handle.take() {
+			self.cancel.store(true, Ordering::Relaxed);
+			let _ = handle.join();
+		}
+	}
 }
 
 impl StarDictCachedSqlite {
+	#[allow(clippy::too_many_arguments)]
 	pub(crate) fn new(path: PathBuf, ifo: Ifo, idx: PathBuf, idx_gz: bool,
-		syn: Option<PathBuf>, dict: PathBuf, dict_dz: bool, cache_name: &str)
-		-> Result<Self>
+		syn: Option<PathBuf>, syn_gz: bool, dict: PathBuf, dict_dz: bool, cache_name: &str,
+		options: &OpenOptions, progress: Option<ImportProgress>) -> Result<Self>
 	{
-		fn load_db(idx_cache: &PathBuf) -> Result<Option<InnerDb>>
+		fn load_db(idx_cache: &PathBuf, collation: &Collation, stamp: &str, rebuilt: &mut bool)
+			-> Result<Option<InnerDb>>
 		{
 			if !idx_cache.exists() {
 				return Ok(None);
@@ -38,15 +89,17 @@ impl StarDictCachedSqlite {
 			let db = Connection::open_with_flags(idx_cache, OpenFlags::SQLITE_OPEN_READ_ONLY)
 				.map_err(sqlite_error_map)?;
 			if check_init_complete(&db).map_err(sqlite_error_map)? {
-				return Ok(Some(InnerDb::Loaded(db)));
-			}
-
-			// another process is doing init now
-			if other_pid_alive(&db, &idx_cache)? {
+				if check_cache(&db, collation, stamp).map_err(sqlite_error_map)? {
+					return Ok(Some(InnerDb::Loaded(db)));
+				}
+				*rebuilt = true;
+			} else if !check_init_canceled(&db).map_err(sqlite_error_map)? && other_pid_alive(&db, idx_cache)? {
+				// another process is doing init now
 				return Ok(Some(InnerDb::InitByOther(idx_cache.clone(), db)));
 			}
 
-			// preview process end without init finished
+			// preview process end without init finished, import canceled by drop,
+			// or cache built with another version, collation or source files,
 			// remove it and do init again
 			if let Err((_, err)) = db.close() {
 				return Err(sqlite_error_map(err));
@@ -55,97 +108,270 @@ impl StarDictCachedSqlite {
 			Ok(None)
 		}
 
+		let collation = options.collation.clone();
 		let (idx_cache, _) = get_cache_dir(
-			&path, cache_name, IDX_SQLITE_SUFFIX, None)?;
+			&path, cache_name, options.cache_dir.as_deref(), IDX_SQLITE_SUFFIX, None)?;
+		let source = CacheSource {
+			idx: idx.clone(),
+			idx_gz,
+			syn: syn.clone(),
+			syn_gz,
+			dict: dict.clone(),
+			dict_dz,
+			cache_name: cache_name.to_owned(),
+			options: options.clone(),
+		};
 
 		let has_syn = syn.is_some();
-		let inner = load_db(&idx_cache)?;
+		let stamp = source_stamp(&idx, &dict, &options.encoding)?;
+		let (dict_filename, dict_comment) = if dict_dz {
+			let dict = Dict::new(dict.clone(), true)?;
+			(dict.filename().map(str::to_owned), dict.comment().map(str::to_owned))
+		} else {
+			(None, None)
+		};
+		let mut rebuilt = false;
+		let inner = load_db(&idx_cache, &collation, &stamp, &mut rebuilt)?;
 
-		let inner = if let Some(inner) = inner {
-			inner
+		let (inner, importing) = if let Some(inner) = inner {
+			(inner, None)
 		} else {
+			// source checked before the cache file created
+			let mut idx = Idx::new(idx, &ifo, idx_gz, syn.clone(), syn_gz, options)?;
+			let dict = Dict::open(dict, dict_dz, options)?;
+			idx.check_offsets(dict.data_size(), !options.verify_size)?;
 			let db = Connection::open(&idx_cache).map_err(sqlite_error_map)?;
-			init_db(&db)?;
-			let idx = Idx::new(idx, &ifo, idx_gz, syn.clone())?;
-			let dict = Dict::new(dict, dict_dz)?;
+			init_db(&db, &collation, &stamp)?;
 
 			let db = Arc::new(Mutex::new(db));
 			let arc_db = db.clone();
 			let idx_cache2 = idx_cache.clone();
 			let ifo2 = ifo.clone();
-			thread::spawn(move || {
+			let cancel = Arc::new(AtomicBool::new(false));
+			let cancel2 = cancel.clone();
+			let handle = thread::spawn(move || {
 				if let Ok(db) = arc_db.lock() {
-					if let Err(_) = import_cache(&db, &ifo2, idx, dict) {
+					if import_cache(&db, &ifo2, idx, dict, progress, &cancel2).is_err() {
 						eprint!("Failed import dictionary cache:{:#?}", idx_cache2);
 					}
 				};
 			});
 
-			InnerDb::Init(idx_cache, db.clone())
+			(InnerDb::Init(idx_cache, db.clone()), Some(Importing { handle: Some(handle), cancel }))
 		};
 
 		Ok(StarDictCachedSqlite {
 			path,
 			ifo,
-			db: inner,
+			db: Mutex::new(inner),
 			has_syn,
+			collation,
+			source,
+			dict_filename,
+			dict_comment,
+			resources: Resources::new(),
+			follow_synonyms: options.follow_synonyms,
+			importing,
+			rebuilt,
+		})
+	}
+
+	/// only an existing cache complete and built from the same source,
+	/// nothing imported, removed or written
+	#[allow(clippy::too_many_arguments)]
+	pub(crate) fn open_readonly(path: PathBuf, ifo: Ifo, idx: PathBuf, idx_gz: bool,
+		syn: Option<PathBuf>, syn_gz: bool, dict: PathBuf, dict_dz: bool, cache_name: &str,
+		options: &OpenOptions) -> Result<Self>
+	{
+		let collation = options.collation.clone();
+		let (cache_dir, dict_name) = cache_location(&path, cache_name, options.cache_dir.as_deref())?;
+		let idx_cache = cache_dir.join(format!("{}.{}", dict_name, IDX_SQLITE_SUFFIX));
+		if !idx_cache.is_file() {
+			return Err(Error::CacheMissing(idx_cache));
+		}
+		let db = Connection::open_with_flags(&idx_cache, OpenFlags::SQLITE_OPEN_READ_ONLY)
+			.map_err(sqlite_error_map)?;
+		// no meta table or import not finished
+		if !check_init_complete(&db).unwrap_or(false) {
+			return Err(Error::CacheMissing(idx_cache));
+		}
+		let stamp = source_stamp(&idx, &dict, &options.encoding)?;
+		if !check_cache(&db, &collation, &stamp).map_err(sqlite_error_map)? {
+			return Err(Error::InvalidDictCache(format!("{:#?}", idx_cache)));
+		}
+		let (dict_filename, dict_comment) = if dict_dz {
+			let dict = Dict::new(dict.clone(), true)?;
+			(dict.filename().map(str::to_owned), dict.comment().map(str::to_owned))
+		} else {
+			(None, None)
+		};
+		let source = CacheSource {
+			idx,
+			idx_gz,
+			syn: syn.clone(),
+			syn_gz,
+			dict,
+			dict_dz,
+			cache_name: cache_name.to_owned(),
+			options: options.clone(),
+		};
+		Ok(StarDictCachedSqlite {
+			path,
+			ifo,
+			db: Mutex::new(InnerDb::Loaded(db)),
+			has_syn: syn.is_some(),
+			collation,
+			source,
+			dict_filename,
+			dict_comment,
+			resources: Resources::new(),
+			follow_synonyms: options.follow_synonyms,
+			importing: None,
+			rebuilt: false,
+		})
+	}
+
+	/// without following, lookup only the headword, not definitions by syn
+	#[inline]
+	pub fn set_follow_synonyms(&mut self, follow: bool)
+	{
+		self.follow_synonyms = follow;
+	}
+
+	/// the cache imported here or by another process,
+	/// queries fail with CacheInitiating before that
+	#[inline]
+	pub fn cache_ready(&self) -> bool
+	{
+		self.query(|_| Ok(())).is_ok()
+	}
+
+	/// a stale cache, built with another version, collation or source files,
+	/// found while opening and removed to import again
+	#[inline]
+	pub fn cache_rebuilt(&self) -> bool
+	{
+		self.rebuilt
+	}
+
+	/// suspicious entries skipped while importing the cache, e.g. empty headwords
+	/// and blocks without definition, Error::CacheInitiating before imported
+	pub fn idx_warnings(&self) -> Result<Vec<String>>
+	{
+		self.query(|db| {
+			let mut stmt = db.prepare("select value from meta where key = 'warning' order by rowid")?;
+			let rows = stmt.query_map([], |row| row.get(0))?;
+			rows.collect()
 		})
 	}
 
-	fn lookup_db(&self, db: &Connection, lowercase_word: &str) -> core::result::Result<Option<Vec<WordDefinition>>, rusqlite::Error>
+	/// wait the import up to timeout instead of retrying queries,
+	/// false if not finished in time
+	pub fn wait_ready(&self, timeout: Duration) -> Result<bool>
+	{
+		wait_until(timeout, || match self.query(|_| Ok(())) {
+			Ok(()) => Ok(true),
+			Err(Error::CacheInitiating) => Ok(false),
+			Err(e) => Err(e),
+		})
+	}
+
+	/// remove the cache and import again from the source files,
+	/// for corrupted caches, queries fail with CacheInitiating until imported
+	pub fn rebuild(&mut self) -> Result<()>
+	{
+		if self.source.options.readonly {
+			return Err(Error::FailedOpenCache("read only cache not rebuilt".to_owned()));
+		}
+		// stop the import and close the cache before removal
+		self.importing = None;
+		let inner = self.db.get_mut().unwrap_or_else(PoisonError::into_inner);
+		*inner = InnerDb::Loaded(Connection::open_in_memory().map_err(sqlite_error_map)?);
+		let source = self.source.clone();
+		let follow_synonyms = self.follow_synonyms;
+		let (idx_cache, _) = get_cache_dir(&self.path, &source.cache_name,
+			source.options.cache_dir.as_deref(), IDX_SQLITE_SUFFIX, None)?;
+		remove_cache(&idx_cache)?;
+		*self = Self::new(self.path.clone(), self.ifo.clone(), source.idx, source.idx_gz, source.syn, source.syn_gz,
+			source.dict, source.dict_dz, &source.cache_name, &source.options, None)?;
+		self.follow_synonyms = follow_synonyms;
+		Ok(())
+	}
+
+	/// wait the import, finalize cached statements and close the connection,
+	/// so the cache file is not locked any more, e.g. for removal on windows
+	pub fn close(self) -> Result<()>
+	{
+		let StarDictCachedSqlite { db, importing, .. } = self;
+		if let Some(importing) = importing {
+			importing.join();
+		}
+		let db = match db.into_inner().unwrap_or_else(PoisonError::into_inner) {
+			InnerDb::Loaded(db) | InnerDb::InitByOther(_, db) => db,
+			InnerDb::Init(_, db) => Arc::try_unwrap(db)
+				.map_err(|_| Error::FailedOpenCache("cache still used by the import".to_owned()))?
+				.into_inner()
+				.unwrap_or_else(PoisonError::into_inner),
+		};
+		db.flush_prepared_statement_cache();
+		db.close().map_err(|(_, e)| sqlite_error_map(e))
+	}
+
+	/// aliases after max definitions not queried, max == 0 means unbounded
+	fn lookup_db(&self, db: &Connection, lowercase_word: &str, max: usize)
+		-> core::result::Result<Option<Vec<MatchedDefinition>>, rusqlite::Error>
 	{
 		let mut vec = vec![];
 		let mut found = HashSet::new();
-		if let Some(definition) = query_definition(db, &lowercase_word)? {
-			found.insert(definition.word.clone());
-			vec.push(definition);
+		if let Some(definitions) = query_definitions(db, lowercase_word)? {
+			found.insert(definitions[0].word.clone());
+			for definition in definitions {
+				vec.push(MatchedDefinition { definition, via_synonym: false, matched_key: lowercase_word.to_owned() });
+			}
 		}
 
 		// now query aliases
-		if self.has_syn {
-			let mut stmt = db.prepare("select aliases from alias where word = ?")?;
+		if self.has_syn && self.follow_synonyms && !limit_reached(&vec, max) {
+			let mut stmt = db.prepare_cached("select aliases from alias where word = ?")?;
 			let mut rows = stmt.query([&lowercase_word])?;
 			if let Some(row) = rows.next()? {
 				let aliases: String = row.get(0)?;
 				let aliases: Vec<String> = serde_json::from_str(&aliases).unwrap();
 
 				for key in aliases {
-					if let Some(definition) = query_definition(db, &key)? {
-						if !found.contains(&definition.word) {
-							found.insert(definition.word.clone());
-							vec.push(definition);
+					if limit_reached(&vec, max) {
+						break;
+					}
+					if let Some(definitions) = query_definitions(db, &key)? {
+						if !found.contains(&definitions[0].word) {
+							found.insert(definitions[0].word.clone());
+							for definition in definitions {
+								vec.push(MatchedDefinition { definition, via_synonym: true, matched_key: key.clone() });
+							}
 						}
 					}
 				}
 			}
 		}
+		if max > 0 {
+			vec.truncate(max);
+		}
 
-		let definitions = if vec.len() == 0 {
+		let definitions = if vec.is_empty() {
 			None
 		} else {
 			Some(vec)
 		};
 		Ok(definitions)
 	}
-}
-
-impl StarDict for StarDictCachedSqlite {
-	#[inline]
-	fn path(&self) -> &PathBuf
-	{
-		&self.path
-	}
 
-	#[inline]
-	fn ifo(&self) -> &Ifo
+	/// run query with the cache db if init completed,
+	/// switch to a read only connection once init completed
+	fn query<T, F>(&self, f: F) -> Result<T>
+		where F: FnOnce(&Connection) -> core::result::Result<T, rusqlite::Error>
 	{
-		&self.ifo
-	}
-
-	#[inline]
-	fn lookup(&mut self, word: &str) -> Result<Option<Vec<WordDefinition>>>
-	{
-		let reset_init = match &self.db {
+		let mut inner = self.db.lock().unwrap_or_else(PoisonError::into_inner);
+		let reset_init = match &*inner {
 			InnerDb::Loaded(_) => None,
 			InnerDb::InitByOther(idx_cache, db) =>
 				if Ok(true) == check_init_complete(db) {
@@ -170,94 +396,447 @@ impl StarDict for StarDictCachedSqlite {
 				&idx_cache,
 				OpenFlags::SQLITE_OPEN_READ_ONLY)
 				.map_err(sqlite_error_map)?;
-			self.db = InnerDb::Loaded(db);
+			*inner = InnerDb::Loaded(db);
 		}
-		if let InnerDb::Loaded(db) = &self.db {
-			Ok(self.lookup_db(db, &word.to_lowercase()).map_err(sqlite_error_map)?)
+		if let InnerDb::Loaded(db) = &*inner {
+			f(db).map_err(sqlite_error_map)
 		} else {
 			panic!("noway")
 		}
 	}
 }
 
-fn init_db(db: &Connection) -> Result<()>
+#[cfg(feature = "sqlite-fts")]
+impl StarDictCachedSqlite {
+	/// headwords which definition matches the fts5 query, most relevant first,
+	/// limit == 0 means unbounded
+	pub fn search_full_text(&self, query: &str, limit: usize) -> Result<Vec<String>>
+	{
+		let limit = if limit == 0 { -1 } else { limit as i64 };
+		let words = self.query(|db| {
+			if !full_text_enabled(db)? {
+				return Ok(None);
+			}
+			let mut stmt = db.prepare(
+				"select word.definition from full_text join word on word.id = full_text.rowid
+					where full_text match ? order by rank limit ?")?;
+			let rows = stmt.query_map(params![query, limit], |row| row.get(0))?;
+			rows.collect::<core::result::Result<Vec<String>, _>>().map(Some)
+		})?;
+		words.ok_or(Error::FullTextUnsupported)
+	}
+}
+
+impl StarDict for StarDictCachedSqlite {
+	#[inline]
+	fn path(&self) -> &PathBuf
+	{
+		&self.path
+	}
+
+	#[inline]
+	fn ifo(&self) -> &Ifo
+	{
+		&self.ifo
+	}
+
+	#[inline]
+	fn dict_source_filename(&self) -> Option<&str>
+	{
+		self.dict_filename.as_deref()
+	}
+
+	#[inline]
+	fn dict_source_comment(&self) -> Option<&str>
+	{
+		self.dict_comment.as_deref()
+	}
+
+	#[inline]
+	fn resource_db(&self) -> Result<Option<&ResourceDb>>
+	{
+		self.resources.get(&self.path)
+	}
+
+	#[inline]
+	fn dict_size(&self) -> Result<u64>
+	{
+		source_size(&self.path, self.source.files())
+	}
+
+	/// with the journal while importing
+	fn cache_size(&self) -> Result<u64>
+	{
+		let (idx_cache, _) = get_cache_dir(&self.path, &self.source.cache_name,
+			self.source.options.cache_dir.as_deref(), IDX_SQLITE_SUFFIX, None)?;
+		let mut size = disk_usage(&idx_cache)?;
+		for suffix in ["-journal", "-wal"] {
+			let mut path = idx_cache.clone().into_os_string();
+			path.push(suffix);
+			size += disk_usage(Path::new(&path))?;
+		}
+		Ok(size)
+	}
+
+	#[inline]
+	fn lookup(&self, word: &str) -> Result<Option<Vec<WordDefinition>>>
+	{
+		Ok(self.lookup_detailed(word)?.map(into_definitions))
+	}
+
+	fn contains(&self, word: &str) -> Result<bool>
+	{
+		let lowercase_word = self.collation.normalize(word);
+		self.query(|db| {
+			let mut stmt = db.prepare_cached("select 1 from word where word = ? limit 1")?;
+			if stmt.exists([&lowercase_word])? {
+				return Ok(true);
+			}
+			if !self.has_syn || !self.follow_synonyms {
+				return Ok(false);
+			}
+			let aliases: Option<String> = db.query_row(
+				"select aliases from alias where word = ?", [&lowercase_word], |row| row.get(0))
+				.optional()?;
+			if let Some(aliases) = aliases {
+				let aliases: Vec<String> = serde_json::from_str(&aliases).unwrap();
+				for key in aliases {
+					if stmt.exists([&key])? {
+						return Ok(true);
+					}
+				}
+			}
+			Ok(false)
+		})
+	}
+
+	fn lookup_exact(&self, word: &str) -> Result<Option<Vec<WordDefinition>>>
+	{
+		let lowercase_word = self.collation.normalize(word);
+		let definitions: Vec<WordDefinition> = self.query(|db| query_definitions(db, &lowercase_word))?
+			.into_iter()
+			.flatten()
+			.filter(|definition| definition.word == word)
+			.collect();
+		Ok(if definitions.is_empty() { None } else { Some(definitions) })
+	}
+
+	fn lookup_detailed(&self, word: &str) -> Result<Option<Vec<MatchedDefinition>>>
+	{
+		let lowercase_word = self.collation.normalize(word);
+		self.query(|db| self.lookup_db(db, &lowercase_word, 0))
+	}
+
+	fn lookup_limited(&self, word: &str, max: usize) -> Result<Option<Vec<WordDefinition>>>
+	{
+		let lowercase_word = self.collation.normalize(word);
+		Ok(self.query(|db| self.lookup_db(db, &lowercase_word, max))?.map(into_definitions))
+	}
+
+	/// all words looked up with one connection and reused statements
+	fn lookup_many(&self, words: &[&str]) -> Result<Vec<(String, Option<Vec<WordDefinition>>)>>
+	{
+		self.query(|db| {
+			let mut results = Vec::with_capacity(words.len());
+			for word in words {
+				let lowercase_word = self.collation.normalize(word);
+				let definitions = self.lookup_db(db, &lowercase_word, 0)?.map(into_definitions);
+				results.push((word.to_string(), definitions));
+			}
+			Ok(results)
+		})
+	}
+
+	fn search_prefix(&self, prefix: &str, limit: usize) -> Result<Vec<String>>
+	{
+		let pattern = format!("{}%", escape_like(&self.collation.normalize(prefix)));
+		let limit = if limit == 0 { -1 } else { limit as i64 };
+		self.query(|db| {
+			let mut stmt = db.prepare(
+				"select distinct definition from word where word like ? escape '\\' order by word limit ?")?;
+			let rows = stmt.query_map(params![pattern, limit], |row| row.get(0))?;
+			rows.collect()
+		})
+	}
+
+	fn words(&self) -> Result<Vec<String>>
+	{
+		self.query(|db| {
+			// a row for every segment, headwords of a key in idx order
+			let mut stmt = db.prepare("select word.word, segment.headword from word \
+				join segment on segment.word_id = word.id order by word.word, segment.id")?;
+			let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+			let mut words: Vec<String> = vec![];
+			let mut key_words = 0;
+			let mut last_key = None;
+			for row in rows {
+				let (key, word) = row?;
+				if last_key.as_ref() != Some(&key) {
+					key_words = words.len();
+					last_key = Some(key);
+				}
+				if !words[key_words..].contains(&word) {
+					words.push(word);
+				}
+			}
+			Ok(words)
+		})
+	}
+
+	fn neighbors(&self, word: &str, before: usize, after: usize) -> Result<Vec<String>>
+	{
+		let key = self.collation.normalize(word);
+		self.query(|db| {
+			let mut stmt = db.prepare(
+				"select definition from word where word < ? order by word desc limit ?")?;
+			let rows = stmt.query_map(params![key, before as i64], |row| row.get(0))?;
+			let mut words = rows.collect::<core::result::Result<Vec<String>, _>>()?;
+			words.reverse();
+			let mut stmt = db.prepare(
+				"select definition from word where word > ? order by word limit ?")?;
+			for word in stmt.query_map(params![key, after as i64], |row| row.get(0))? {
+				words.push(word?);
+			}
+			Ok(words)
+		})
+	}
+
+	fn search_glob(&self, pattern: &str, limit: usize) -> Result<Vec<String>>
+	{
+		let pattern = Glob::new(&self.collation.normalize(pattern)).to_like();
+		let limit = if limit == 0 { -1 } else { limit as i64 };
+		self.query(|db| {
+			let mut stmt = db.prepare(
+				"select distinct definition from word where word like ? escape '\\' order by word limit ?")?;
+			let rows = stmt.query_map(params![pattern, limit], |row| row.get(0))?;
+			rows.collect()
+		})
+	}
+
+	fn synonyms_of(&self, word: &str) -> Result<Vec<String>>
+	{
+		if !self.has_syn {
+			return Ok(vec![]);
+		}
+		let key = self.collation.normalize(word);
+		self.query(|db| {
+			let mut stmt = db.prepare("select synonym from synonym where word = ? order by id")?;
+			let rows = stmt.query_map([key], |row| row.get(0))?;
+			rows.collect()
+		})
+	}
+
+	fn suggest(&self, word: &str, max_distance: usize, limit: usize) -> Result<Vec<String>>
+	{
+		let mut suggestions = Suggestions::new(&self.collation.normalize(word), max_distance);
+		self.query(|db| {
+			let mut stmt = db.prepare("select word, definition from word")?;
+			let mut rows = stmt.query([])?;
+			while let Some(row) = rows.next()? {
+				let key: String = row.get(0)?;
+				let headword: String = row.get(1)?;
+				suggestions.push(&key, || headword);
+			}
+			Ok(())
+		})?;
+		Ok(suggestions.finish(limit))
+	}
+}
+
+fn init_db(db: &Connection, collation: &Collation, stamp: &str) -> Result<()>
 {
 	let pid = process::id();
 	db.execute_batch(
 		"create table meta(key text, value text);
 			create table word(id integer primary key, word text, definition text);
 			create index word_idx on word(word);
-			create table segment(id integer primary key, word_id integer, types text, text text);
+			create table segment(id integer primary key, word_id integer, entry integer, headword text,
+				types text, text text, data blob);
 			create index segment_idx on segment(word_id);
 			create table alias(id integer primary key, word text, aliases text);
 			create index alias_idx on alias(word);
-			insert into meta(key, value) values ('version', '1');
+			create table synonym(id integer primary key, word text, synonym text);
+			create index synonym_idx on synonym(word);
 			insert into meta(key, value) values ('init_status', 'start');")
 		.map_err(sqlite_error_map)?;
+	db.execute("insert into meta(key, value) values ('version', ?)", [CACHE_VERSION])
+		.map_err(sqlite_error_map)?;
 	db.execute("insert into meta(key, value) values ('init_pid', ?)", [pid])
 		.map_err(sqlite_error_map)?;
+	db.execute("insert into meta(key, value) values ('collation', ?)", [collation.id()])
+		.map_err(sqlite_error_map)?;
+	db.execute("insert into meta(key, value) values ('source', ?)", [stamp])
+		.map_err(sqlite_error_map)?;
+	#[cfg(feature = "sqlite-fts")]
+	{
+		// sqlite may be built without fts5
+		let full_text = if db.execute_batch(
+			"create virtual table full_text using fts5(text, content='');").is_ok() {
+			"fts5"
+		} else {
+			"none"
+		};
+		db.execute("insert into meta(key, value) values ('full_text', ?)", [full_text])
+			.map_err(sqlite_error_map)?;
+	}
 	Ok(())
 }
 
-fn import_cache(db: &Connection, ifo: &Ifo, idx: Idx, mut dict: Dict)
-	-> core::result::Result<(), rusqlite::Error>
+/// with cancel set, imported entries rolled back and the cache marked canceled
+fn import_cache(db: &Connection, ifo: &Ifo, idx: Idx, dict: Dict,
+	mut progress: Option<ImportProgress>, cancel: &AtomicBool) -> core::result::Result<(), rusqlite::Error>
 {
+	let canceled = || -> core::result::Result<(), rusqlite::Error> {
+		db.execute("rollback", ())?;
+		db.execute("update meta set value = 'canceled' where key = 'init_status'", ())?;
+		Ok(())
+	};
 	db.execute("begin", ())?;
 	let mut definition_stmt = db.prepare("insert into word (word, definition) values (?, ?)")?;
-	let mut segment_stmt = db.prepare("insert into segment (word_id, types, text) values (?, ?, ?)")?;
-	for (word, entry) in &idx.items {
-		let definition = if let Ok(Some(definition)) = dict.get_definition(entry, ifo) {
-			definition
-		} else {
-			continue;
+	let mut segment_stmt = db.prepare(
+		"insert into segment (word_id, entry, headword, types, text, data) values (?, ?, ?, ?, ?, ?)")?;
+	#[cfg(feature = "sqlite-fts")]
+	let mut full_text_stmt = if full_text_enabled(db)? {
+		Some(db.prepare("insert into full_text (rowid, text) values (?, ?)")?)
+	} else {
+		None
+	};
+	let mut imported = 0;
+	let mut warnings = idx.warnings();
+	for (word, entry) in idx.items().iter() {
+		if cancel.load(Ordering::Relaxed) {
+			return canceled();
+		}
+		// idx records of the key, counted as ifo.wordcount
+		let before = imported;
+		imported += entry.blocks.len();
+		if let Some(progress) = &mut progress {
+			if imported / PROGRESS_STEP != before / PROGRESS_STEP {
+				progress(imported, ifo.wordcount);
+			}
+		}
+		// skip the entry, not fail the whole cache
+		let definitions = match dict.get_definitions(entry, ifo) {
+			Ok(definitions) if !definitions.is_empty() => definitions,
+			result => {
+				warnings.push(skipped_warning(&entry.word, result.err()));
+				continue;
+			}
 		};
-		let key = word.to_lowercase();
-		let word_id = definition_stmt.insert([&key, &definition.word])?;
-		for segment in definition.segments {
-			segment_stmt.execute(params![word_id, segment.types, segment.text])?;
+		let word_id = definition_stmt.insert([&**word, &definitions[0].word])?;
+		#[cfg(feature = "sqlite-fts")]
+		if let Some(full_text_stmt) = &mut full_text_stmt {
+			let text: Vec<String> = definitions.iter().map(WordDefinition::to_plain_text).collect();
+			full_text_stmt.execute(params![word_id, text.join("\n")])?;
 		}
+		for (i, definition) in definitions.into_iter().enumerate() {
+			for segment in definition.segments {
+				segment_stmt.execute(params![word_id, i, definition.word, segment.types, segment.text,
+					segment.data])?;
+			}
+		}
+	}
+	if let Some(progress) = &mut progress {
+		progress(imported, ifo.wordcount);
 	}
+	warnings.extend(dict.warnings());
 	definition_stmt.finalize()?;
 	segment_stmt.finalize()?;
+	let mut warning_stmt = db.prepare("insert into meta(key, value) values ('warning', ?)")?;
+	for warning in warnings {
+		warning_stmt.execute([warning])?;
+	}
+	warning_stmt.finalize()?;
+	#[cfg(feature = "sqlite-fts")]
+	if let Some(full_text_stmt) = full_text_stmt {
+		full_text_stmt.finalize()?;
+	}
 
+	if cancel.load(Ordering::Relaxed) {
+		return canceled();
+	}
 	if let Some(syn) = &idx.syn {
 		let mut alias_stmt = db.prepare("insert into alias (word, aliases) values (?, ?)")?;
 		for (key, aliases) in syn {
 			let aliases_json = serde_json::to_string(aliases).unwrap();
-			alias_stmt.execute([&key.to_lowercase(), &aliases_json])?;
+			alias_stmt.execute([key, &aliases_json])?;
 		}
 		alias_stmt.finalize()?;
 	}
+	if let Some(synonyms) = &idx.synonyms {
+		let mut synonym_stmt = db.prepare("insert into synonym (word, synonym) values (?, ?)")?;
+		for (key, synonyms) in synonyms {
+			for synonym in synonyms {
+				synonym_stmt.execute([key, synonym])?;
+			}
+		}
+		synonym_stmt.finalize()?;
+	}
 	db.execute("update meta set value = 'success' where key = 'init_status'", ())?;
 	db.execute("commit", ())?;
 	Ok(())
 }
 
-fn query_definition(db: &Connection, lowercase_word: &str) -> core::result::Result<Option<WordDefinition>, rusqlite::Error>
+/// definitions of every entry of the key, in idx order
+fn query_definitions(db: &Connection, lowercase_word: &str)
+	-> core::result::Result<Option<Vec<WordDefinition>>, rusqlite::Error>
 {
-	let mut stmt = db.prepare("select id, definition from word where word in (?) order by id")?;
+	let mut stmt = db.prepare_cached("select id from word where word in (?) order by id")?;
 	let mut rows = stmt.query([lowercase_word])?;
-	let (word_id, mut definition) = if let Some(row) = rows.next()? {
-		let word_id: i64 = row.get(0)?;
-		let word = row.get(1)?;
-		let definition = WordDefinition { word, segments: vec![] };
-		(word_id, definition)
+	let word_id: i64 = if let Some(row) = rows.next()? {
+		row.get(0)?
 	} else {
 		return Ok(None);
 	};
 	drop(rows);
-	stmt.finalize()?;
+	drop(stmt);
 
-	stmt = db.prepare("select types, text from segment where word_id = ?")?;
+	let mut stmt = db.prepare_cached(
+		"select entry, headword, types, text, data from segment where word_id = ? order by id")?;
 	let mut rows = stmt.query([word_id])?;
+	let mut definitions: Vec<WordDefinition> = vec![];
+	let mut current = None;
 	while let Some(row) = rows.next()? {
-		let types = row.get(0)?;
-		let text = row.get(1)?;
-		definition.segments.push(WordDefinitionSegment { types, text });
+		let entry: i64 = row.get(0)?;
+		if current != Some(entry) {
+			current = Some(entry);
+			definitions.push(WordDefinition { word: row.get(1)?, segments: vec![] });
+		}
+		let types = row.get(2)?;
+		let text = row.get(3)?;
+		let data = row.get(4)?;
+		if let Some(definition) = definitions.last_mut() {
+			definition.segments.push(WordDefinitionSegment { types, text, data });
+		}
 	}
-	drop(rows);
-	stmt.finalize()?;
-	Ok(Some(definition))
+	if definitions.is_empty() {
+		Ok(None)
+	} else {
+		Ok(Some(definitions))
+	}
+}
+
+#[inline]
+fn escape_like(str: &str) -> String
+{
+	let mut escaped = String::with_capacity(str.len());
+	for ch in str.chars() {
+		if matches!(ch, '%' | '_' | '\\') {
+			escaped.push('\\');
+		}
+		escaped.push(ch);
+	}
+	escaped
+}
+
+#[cfg(feature = "sqlite-fts")]
+#[inline]
+fn full_text_enabled(db: &Connection) -> core::result::Result<bool, rusqlite::Error>
+{
+	let full_text: Option<String> = db.query_row(
+		"select value from meta where key = 'full_text'", [], |row| row.get(0))
+		.optional()?;
+	Ok(full_text.as_deref() == Some("fts5"))
 }
 
 #[inline]
@@ -269,6 +848,57 @@ fn check_init_complete(db: &Connection) -> core::result::Result<bool, rusqlite::
 	})
 }
 
+/// import stopped by drop, no process building the cache
+#[inline]
+fn check_init_canceled(db: &Connection) -> core::result::Result<bool, rusqlite::Error>
+{
+	db.query_row("select value from meta where key = 'init_status'", (), |row| {
+		let init_status: String = row.get(0)?;
+		Ok(init_status == "canceled")
+	})
+}
+
+/// check the cache built with current version and the collation,
+/// caches without collation use the default
+fn check_cache(db: &Connection, collation: &Collation, stamp: &str)
+	-> core::result::Result<bool, rusqlite::Error>
+{
+	let version: Option<String> = db.query_row(
+		"select value from meta where key = 'version'", [], |row| row.get(0))
+		.optional()?;
+	if version.as_deref() != Some(CACHE_VERSION) {
+		return Ok(false);
+	}
+	let source: Option<String> = db.query_row(
+		"select value from meta where key = 'source'", [], |row| row.get(0))
+		.optional()?;
+	if source.as_deref() != Some(stamp) {
+		return Ok(false);
+	}
+	#[cfg(feature = "sqlite-fts")]
+	{
+		// cache built without full text table
+		let full_text: Option<String> = db.query_row(
+			"select value from meta where key = 'full_text'", [], |row| row.get(0))
+			.optional()?;
+		if full_text.is_none() {
+			return Ok(false);
+		}
+	}
+	let id = db.query_row("select value from meta where key = 'collation'", [], |row| row.get(0))
+		.optional()?;
+	Ok(id.unwrap_or_else(|| Collation::default().id()) == collation.id())
+}
+
+/// remove the cache file if exists
+pub(crate) fn remove_cache(idx_cache: &Path) -> Result<()>
+{
+	if idx_cache.exists() {
+		fs::remove_file(idx_cache)?;
+	}
+	Ok(())
+}
+
 #[inline]
 fn sqlite_error_map(error: rusqlite::Error) -> Error
 {
@@ -288,4 +918,290 @@ fn other_pid_alive(db: &Connection, idx_cache: &PathBuf) -> Result<bool>
 
 	// another process is doing init now
 	Ok(matches!(state, State::Alive))
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+	use std::path::PathBuf;
+	use std::thread;
+	use std::time::Duration;
+	use crate::dictzip::tests::temp_file;
+	use crate::error::Error;
+	use crate::{Collation, StarDict};
+	use crate::tests::{assert_broken_block, broken_block_dict, cache_file, cache_file_exists, create_dict,
+		create_sized_dict, latin1_dict, strip_accents, update_source, wait_cache, ACCENT_WORDS, CACHE_NAME,
+		COLLATION_WORDS, SAMPLE_WORDS, SYNONYMS};
+
+	#[test]
+	fn custom_collation() {
+		use crate::{with_sqlite, with_sqlite_collation};
+		let ifo = create_dict("custom_collation_sqlite", &ACCENT_WORDS, &[]);
+		let dict = with_sqlite(&ifo, CACHE_NAME).unwrap();
+		assert!(wait_cache(|| dict.lookup("cafe")).is_none());
+		drop(dict);
+
+		// keys stored with the normalizer
+		let dict = with_sqlite_collation(&ifo, CACHE_NAME, strip_accents()).unwrap();
+		assert!(wait_cache(|| dict.lookup("cafe")).is_some());
+		assert!(dict.lookup("FACADE").unwrap().is_some());
+	}
+
+	#[test]
+	fn wait_ready() {
+		use crate::{clear_cache, with_sqlite};
+		let ifo = create_dict("wait_ready_sqlite", &SAMPLE_WORDS, &SYNONYMS);
+		clear_cache(&ifo, CACHE_NAME).unwrap();
+		let dict = with_sqlite(&ifo, CACHE_NAME).unwrap();
+		assert!(dict.wait_ready(Duration::from_secs(30)).unwrap());
+		assert!(dict.cache_ready());
+		// no CacheInitiating any more
+		assert!(dict.lookup("pomme").unwrap().is_some());
+	}
+
+	#[test]
+	fn readonly() {
+		use crate::stardict_sqlite::IDX_SQLITE_SUFFIX;
+		use crate::{clear_cache, get_cache_dir, with_sqlite, with_sqlite_readonly};
+		let ifo = create_dict("readonly_sqlite", &SAMPLE_WORDS, &SYNONYMS);
+		clear_cache(&ifo, CACHE_NAME).unwrap();
+		let (idx_cache, _) = get_cache_dir(ifo.parent().unwrap(), CACHE_NAME, None,
+			IDX_SQLITE_SUFFIX, None).unwrap();
+		assert!(matches!(with_sqlite_readonly(&ifo, CACHE_NAME), Err(Error::CacheMissing(_))));
+		assert!(!idx_cache.exists());
+
+		let dict = with_sqlite(&ifo, CACHE_NAME).unwrap();
+		assert!(dict.wait_ready(Duration::from_secs(30)).unwrap());
+		dict.close().unwrap();
+		let content = fs::read(&idx_cache).unwrap();
+		let modified = fs::metadata(&idx_cache).unwrap().modified().unwrap();
+		let writable = fs::metadata(&idx_cache).unwrap().permissions();
+		let mut readonly = writable.clone();
+		readonly.set_readonly(true);
+		fs::set_permissions(&idx_cache, readonly).unwrap();
+
+		let mut dict = with_sqlite_readonly(&ifo, CACHE_NAME).unwrap();
+		assert!(dict.cache_ready());
+		assert!(dict.lookup("pomme").unwrap().is_some());
+		assert!(dict.rebuild().is_err());
+		dict.close().unwrap();
+		assert_eq!(fs::read(&idx_cache).unwrap(), content);
+		assert_eq!(fs::metadata(&idx_cache).unwrap().modified().unwrap(), modified);
+		let mut journal = idx_cache.as_os_str().to_owned();
+		journal.push("-journal");
+		assert!(!PathBuf::from(journal).exists());
+		fs::set_permissions(&idx_cache, writable).unwrap();
+	}
+
+	#[test]
+	fn collation() {
+		use crate::{with_sqlite, with_sqlite_collation};
+		let ifo = create_dict("collation_sqlite", &COLLATION_WORDS, &[]);
+		let dict = with_sqlite(&ifo, CACHE_NAME).unwrap();
+		assert!(wait_cache(|| dict.lookup("äpfel")).is_some());
+		drop(dict);
+
+		// cache built again with the new collation
+		let dict = with_sqlite_collation(&ifo, CACHE_NAME, Collation::AsciiLower).unwrap();
+		assert!(wait_cache(|| dict.lookup("äpfel")).is_none());
+		assert!(dict.lookup("Äpfel").unwrap().is_some());
+	}
+
+	#[test]
+	fn source_changed() {
+		use crate::with_sqlite;
+		let ifo = create_dict("source_changed_sqlite", &[("apple", "old fruit")], &[]);
+		let dict = with_sqlite(&ifo, CACHE_NAME).unwrap();
+		assert_eq!(wait_cache(|| dict.lookup("apple")).unwrap()[0].segments[0].text, "old fruit");
+		assert!(!dict.cache_rebuilt());
+		drop(dict);
+
+		let ifo = update_source("source_changed_sqlite");
+		let dict = with_sqlite(&ifo, CACHE_NAME).unwrap();
+		assert!(dict.cache_rebuilt());
+		assert_eq!(wait_cache(|| dict.lookup("apple")).unwrap()[0].segments[0].text, "new fruit");
+	}
+
+	#[test]
+	fn progress() {
+		use std::sync::{Arc, Mutex};
+		use crate::with_sqlite_progress;
+		let mut words = SAMPLE_WORDS.to_vec();
+		// two idx records of one key
+		words.push(("apple", "a tech company"));
+		let ifo = create_dict("progress_sqlite", &words, &[]);
+		let last = Arc::new(Mutex::new(None));
+		let last2 = last.clone();
+		let dict = with_sqlite_progress(&ifo, CACHE_NAME, move |current, total|
+			*last2.lock().unwrap() = Some((current, total))).unwrap();
+		wait_cache(|| dict.words());
+		assert_eq!(*last.lock().unwrap(), Some((words.len(), words.len())));
+	}
+
+	#[test]
+	fn dict_size() {
+		use crate::{disk_usage, with_sqlite_in};
+		let (ifo, size) = create_sized_dict("dict_size_sqlite");
+		let cache_root = temp_file("dict_size_sqlite_cache");
+		with_sqlite_in(&ifo, CACHE_NAME, &cache_root).unwrap().close().unwrap();
+		let dict = with_sqlite_in(&ifo, CACHE_NAME, &cache_root).unwrap();
+		assert_eq!(dict.dict_size().unwrap(), size);
+		let cache_size = dict.cache_size().unwrap();
+		assert!(cache_size > 0);
+		assert_eq!(cache_size, disk_usage(&cache_root).unwrap());
+	}
+
+	#[test]
+	fn close() {
+		use crate::with_sqlite_in;
+		let ifo = create_dict("close_sqlite", &SAMPLE_WORDS, &SYNONYMS);
+		let cache_root = temp_file("close_sqlite_cache");
+		// import waited
+		with_sqlite_in(&ifo, CACHE_NAME, &cache_root).unwrap().close().unwrap();
+		let dict = with_sqlite_in(&ifo, CACHE_NAME, &cache_root).unwrap();
+		assert!(dict.lookup("apple").unwrap().is_some());
+		dict.close().unwrap();
+		// not locked, also on windows
+		fs::remove_dir_all(&cache_root).unwrap();
+		assert!(!cache_root.exists());
+	}
+
+	#[test]
+	fn cancel_import() {
+		use std::sync::mpsc;
+		use rusqlite::{Connection, OpenFlags};
+		use crate::{with_sqlite_in, StarDictOpener};
+		let words: Vec<(String, String)> = (0..5000)
+			.map(|i| (format!("word{}", i), format!("definition of {}", i)))
+			.collect();
+		let words: Vec<(&str, &str)> = words.iter()
+			.map(|(word, definition)| (word.as_str(), definition.as_str()))
+			.collect();
+		let ifo = create_dict("cancel_import_sqlite", &words, &[]);
+		let cache_root = temp_file("cancel_import_sqlite_cache");
+		if cache_root.exists() {
+			fs::remove_dir_all(&cache_root).unwrap();
+		}
+		let (sender, receiver) = mpsc::channel();
+		let dict = StarDictOpener::new()
+			.cache_dir(&cache_root)
+			.progress(move |current, _| {
+				let _ = sender.send(current);
+				// slow import, dropped before finished
+				thread::sleep(Duration::from_millis(100));
+			})
+			.open_sqlite(&ifo, CACHE_NAME)
+			.unwrap();
+		assert_eq!(receiver.recv().unwrap(), 1000);
+		drop(dict);
+		assert!(receiver.try_iter().all(|current| current < words.len()));
+
+		let cache = fs::read_dir(cache_root.join(CACHE_NAME)).unwrap()
+			.map(|entry| entry.unwrap().path())
+			.find(|path| path.to_string_lossy().ends_with("sqlite"))
+			.unwrap();
+		let db = Connection::open_with_flags(&cache, OpenFlags::SQLITE_OPEN_READ_ONLY).unwrap();
+		let status: String = db.query_row("select value from meta where key = 'init_status'", [],
+			|row| row.get(0)).unwrap();
+		assert_eq!(status, "canceled");
+		let count: usize = db.query_row("select count(*) from word", [], |row| row.get(0)).unwrap();
+		assert_eq!(count, 0);
+		drop(db);
+
+		// rebuilt on next open
+		let dict = with_sqlite_in(&ifo, CACHE_NAME, &cache_root).unwrap();
+		let definitions = wait_cache(|| dict.lookup("word4999")).unwrap();
+		assert_eq!(definitions[0].segments[0].text, "definition of 4999");
+		dict.close().unwrap();
+		let dict = with_sqlite_in(&ifo, CACHE_NAME, &cache_root).unwrap();
+		assert_eq!(dict.words().unwrap().len(), words.len());
+	}
+
+	#[test]
+	fn cache_root() {
+		use crate::with_sqlite_in;
+		let ifo = create_dict("cache_root_sqlite", &SAMPLE_WORDS, &[]);
+		let cache_root = temp_file("cache_root_sqlite_cache");
+		let dict = with_sqlite_in(&ifo, CACHE_NAME, &cache_root).unwrap();
+		assert!(wait_cache(|| dict.lookup("apple")).is_some());
+		assert!(cache_file_exists(&cache_root, "sqlite"));
+
+		// not creatable
+		let file = temp_file("cache_root_sqlite_file");
+		fs::write(&file, "").unwrap();
+		assert!(matches!(with_sqlite_in(&ifo, CACHE_NAME, &file),
+			Err(Error::FailedCreateCacheDir(..))));
+	}
+
+	#[test]
+	fn rebuild() {
+		use crate::{clear_cache_in, with_sqlite_in};
+		let ifo = create_dict("rebuild_sqlite", &SAMPLE_WORDS, &[]);
+		let cache_root = temp_file("rebuild_sqlite_cache");
+		let dict = with_sqlite_in(&ifo, CACHE_NAME, &cache_root).unwrap();
+		assert!(wait_cache(|| dict.lookup("apple")).is_some());
+		drop(dict);
+
+		// corrupt the cache
+		let db = rusqlite::Connection::open(cache_file(&cache_root, "sqlite")).unwrap();
+		db.execute("delete from word where word = 'apple'", ()).unwrap();
+		drop(db);
+		let mut dict = with_sqlite_in(&ifo, CACHE_NAME, &cache_root).unwrap();
+		assert!(dict.lookup("apple").unwrap().is_none());
+
+		dict.rebuild().unwrap();
+		assert!(wait_cache(|| dict.lookup("apple")).is_some());
+		drop(dict);
+
+		clear_cache_in(&ifo, CACHE_NAME, &cache_root).unwrap();
+		assert!(!cache_file_exists(&cache_root, "sqlite"));
+	}
+
+	#[test]
+	#[cfg(feature = "sqlite-fts")]
+	fn search_full_text() {
+		use crate::tests::create_dict_with;
+		use crate::with_sqlite;
+		let words: [(&str, &[u8]); 3] = [("apple", b"a round fruit"),
+			("banana", b"a <b>long</b> yellow fruit"), ("cherry", b"small red stone fruit, not yellow")];
+		let ifo = create_dict_with("search_full_text_sqlite", "h", &words, &[]);
+		let dict = with_sqlite(&ifo, CACHE_NAME).unwrap();
+		let found = wait_cache(|| dict.search_full_text("yellow", 0));
+		assert_eq!(found.len(), 2);
+		assert!(found.contains(&"banana".to_owned()) && found.contains(&"cherry".to_owned()));
+		assert_eq!(dict.search_full_text("round", 0).unwrap(), vec!["apple"]);
+		assert_eq!(dict.search_full_text("fruit", 1).unwrap().len(), 1);
+		// markup not indexed
+		assert!(dict.search_full_text("b", 0).unwrap().is_empty());
+		assert!(dict.search_full_text("grape", 0).unwrap().is_empty());
+	}
+
+	#[test]
+	fn latin1() {
+		use crate::{Encoding, StarDictOpener};
+		let ifo = latin1_dict("latin1_sqlite");
+		let dict = StarDictOpener::new().open_sqlite(&ifo, CACHE_NAME).unwrap();
+		assert!(wait_cache(|| dict.lookup("café")).is_none());
+		drop(dict);
+
+		// built again for another encoding
+		let dict = StarDictOpener::new().decoder(Encoding::Latin1).open_sqlite(&ifo, CACHE_NAME).unwrap();
+		assert!(dict.cache_rebuilt());
+		assert_eq!(wait_cache(|| dict.lookup("café")).unwrap()[0].segments[0].text, "a café");
+		drop(dict);
+		let dict = StarDictOpener::new().encoding("latin1").open_sqlite(&ifo, CACHE_NAME).unwrap();
+		assert!(!dict.cache_rebuilt());
+		assert!(dict.lookup("café").unwrap().is_some());
+		assert!(matches!(StarDictOpener::new().decoder(Encoding::Custom(|_| String::new()))
+			.open_sqlite(&ifo, CACHE_NAME), Err(Error::UnsupportedEncoding(_))));
+	}
+
+	#[test]
+	fn broken_block() {
+		use crate::with_sqlite;
+		let ifo = broken_block_dict("broken_block_sqlite");
+		let dict = with_sqlite(&ifo, CACHE_NAME).unwrap();
+		assert!(dict.wait_ready(Duration::from_secs(30)).unwrap());
+		assert_broken_block(&dict, dict.idx_warnings().unwrap());
+	}
+}
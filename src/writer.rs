@@ -0,0 +1,321 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use crate::error::{Error, Result};
+use crate::{DictZipWriter, is_binary_type, WordDefinitionSegment};
+
+/// write entries as a stardict dictionary, .ifo, .idx, .dict and optional .syn
+pub struct DictWriter {
+	pub bookname: String,
+	pub author: String,
+	pub email: String,
+	pub website: String,
+	pub description: String,
+	pub date: String,
+	/// write .dict.dz instead of .dict
+	pub compress: bool,
+	entries: Vec<(String, Vec<WordDefinitionSegment>)>,
+	// synonym to headword
+	synonyms: HashMap<String, String>,
+}
+
+impl DictWriter {
+	pub fn new(bookname: impl Into<String>) -> Self
+	{
+		DictWriter {
+			bookname: bookname.into(),
+			author: String::new(),
+			email: String::new(),
+			website: String::new(),
+			description: String::new(),
+			date: String::new(),
+			compress: false,
+			entries: vec![],
+			synonyms: HashMap::new(),
+		}
+	}
+
+	#[inline]
+	pub fn add(&mut self, word: impl Into<String>, segments: Vec<WordDefinitionSegment>)
+	{
+		self.entries.push((word.into(), segments));
+	}
+
+	/// synonym pointing to the headword, .syn file written if any
+	#[inline]
+	pub fn add_synonym(&mut self, synonym: impl Into<String>, word: impl Into<String>)
+	{
+		self.synonyms.insert(synonym.into(), word.into());
+	}
+
+	/// path of the .ifo file, other files written beside it
+	pub fn write(&self, path: impl AsRef<Path>) -> Result<()>
+	{
+		let ifo_path = path.as_ref();
+		if ifo_path.extension().is_none_or(|ext| ext != "ifo") {
+			return Err(Error::InvalidDictPath);
+		}
+		let mut entries: Vec<&(String, Vec<WordDefinitionSegment>)> = self.entries.iter().collect();
+		entries.sort_by(|(a, _), (b, _)| stardict_strcmp(a, b));
+
+		let sametypesequence = sametypesequence(&entries);
+		let mut dict = vec![];
+		let mut idx = vec![];
+		for (word, segments) in &entries {
+			let buf = encode_segments(segments, &sametypesequence);
+			idx.push((word.as_str(), dict.len() as u64, buf.len() as u32));
+			dict.extend_from_slice(&buf);
+		}
+		let offset = dict.len() as u64;
+		if self.compress {
+			let mut file = BufWriter::new(create_file(ifo_path, "dict.dz")?);
+			let mut writer = DictZipWriter::default();
+			// name of the uncompressed dict, as dictzip does
+			if let Some(name) = ifo_path.with_extension("dict").file_name().and_then(|name| name.to_str()) {
+				writer = writer.filename(name);
+			}
+			writer.write(&dict, &mut file)?;
+		} else {
+			create_file(ifo_path, "dict")?
+				.write_all(&dict)
+				.map_err(|e| Error::FailedWriteFile("dict", e))?;
+		}
+
+		let offset_64 = offset > u32::MAX as u64;
+		let mut idx_buf = vec![];
+		for (word, offset, size) in &idx {
+			idx_buf.extend_from_slice(word.as_bytes());
+			idx_buf.push(0);
+			if offset_64 {
+				idx_buf.extend_from_slice(&offset.to_be_bytes());
+			} else {
+				idx_buf.extend_from_slice(&(*offset as u32).to_be_bytes());
+			}
+			idx_buf.extend_from_slice(&size.to_be_bytes());
+		}
+		create_file(ifo_path, "idx")?
+			.write_all(&idx_buf)
+			.map_err(|e| Error::FailedWriteFile("idx", e))?;
+
+		let mut synonyms = vec![];
+		for (synonym, word) in &self.synonyms {
+			let index = idx.iter().position(|(w, _, _)| w == word)
+				.ok_or_else(|| Error::InvalidSynIndex(synonym.clone()))?;
+			synonyms.push((synonym.as_str(), index as u32));
+		}
+		if !synonyms.is_empty() {
+			synonyms.sort_by(|(a, _), (b, _)| stardict_strcmp(a, b));
+			let mut syn_buf = vec![];
+			for (synonym, index) in &synonyms {
+				syn_buf.extend_from_slice(synonym.as_bytes());
+				syn_buf.push(0);
+				syn_buf.extend_from_slice(&index.to_be_bytes());
+			}
+			create_file(ifo_path, "syn")?
+				.write_all(&syn_buf)
+				.map_err(|e| Error::FailedWriteFile("syn", e))?;
+		}
+
+		let mut ifo = String::from("StarDict's dict ifo file\n");
+		if offset_64 {
+			ifo.push_str("version=3.0.0\nidxoffsetbits=64\n");
+		} else {
+			ifo.push_str("version=2.4.2\n");
+		}
+		ifo.push_str(&format!("bookname={}\nwordcount={}\nidxfilesize={}\n",
+			self.bookname, entries.len(), idx_buf.len()));
+		if !synonyms.is_empty() {
+			ifo.push_str(&format!("synwordcount={}\n", synonyms.len()));
+		}
+		for (key, value) in [
+			("author", &self.author),
+			("email", &self.email),
+			("website", &self.website),
+			("description", &self.description),
+			("date", &self.date),
+			("sametypesequence", &sametypesequence),
+		] {
+			if !value.is_empty() {
+				ifo.push_str(&format!("{}={}\n", key, value));
+			}
+		}
+		File::create(ifo_path)
+			.and_then(|mut file| file.write_all(ifo.as_bytes()))
+			.map_err(|e| Error::FailedWriteFile("ifo", e))?;
+		Ok(())
+	}
+}
+
+/// the order stardict sorts idx and syn, ASCII case-insensitive first
+pub fn stardict_strcmp(a: &str, b: &str) -> Ordering
+{
+	let a_lower = a.bytes().map(|b| b.to_ascii_lowercase());
+	let b_lower = b.bytes().map(|b| b.to_ascii_lowercase());
+	a_lower.cmp(b_lower).then_with(|| a.cmp(b))
+}
+
+#[inline]
+fn create_file(ifo_path: &Path, extension: &'static str) -> Result<File>
+{
+	let path: PathBuf = ifo_path.with_extension(extension);
+	File::create(path).map_err(|e| Error::FailedWriteFile(extension, e))
+}
+
+/// types shared by every entry, or empty if they differ
+fn sametypesequence(entries: &[&(String, Vec<WordDefinitionSegment>)]) -> String
+{
+	let mut iter = entries.iter().map(|(_, segments)| segments.iter()
+		.map(|segment| segment.types.as_str())
+		.collect::<String>());
+	let first = if let Some(first) = iter.next() {
+		first
+	} else {
+		return String::new();
+	};
+	if first.chars().count() == entries[0].1.len() && iter.all(|types| types == first) {
+		first
+	} else {
+		String::new()
+	}
+}
+
+fn encode_segments(segments: &[WordDefinitionSegment], sametypesequence: &str) -> Vec<u8>
+{
+	let mut buf = vec![];
+	for (i, segment) in segments.iter().enumerate() {
+		let last = i == segments.len() - 1;
+		let content = if let Some(data) = &segment.data {
+			data.as_slice()
+		} else {
+			segment.text.as_bytes()
+		};
+		if sametypesequence.is_empty() {
+			buf.extend_from_slice(segment.types.as_bytes());
+		} else if last {
+			buf.extend_from_slice(content);
+			break;
+		}
+		if is_binary_type(&segment.types) {
+			buf.extend_from_slice(&(content.len() as u32).to_be_bytes());
+			buf.extend_from_slice(content);
+		} else {
+			buf.extend_from_slice(content);
+			buf.push(0);
+		}
+	}
+	buf
+}
+
+#[cfg(test)]
+mod tests {
+	use std::cmp::Ordering;
+	use std::fs;
+	use crate::dictzip::tests::temp_file;
+	use crate::writer::stardict_strcmp;
+	use crate::{no_cache, DictWriter, StarDict, WordDefinitionSegment};
+
+	fn text(types: &str, text: &str) -> WordDefinitionSegment
+	{
+		WordDefinitionSegment::new(types.to_owned(), text.as_bytes())
+	}
+
+	#[test]
+	fn strcmp() {
+		assert_eq!(stardict_strcmp("apple", "Banana"), Ordering::Less);
+		assert_eq!(stardict_strcmp("Apple", "apple"), Ordering::Less);
+		assert_eq!(stardict_strcmp("apple", "apple"), Ordering::Equal);
+	}
+
+	#[test]
+	fn round_trip() {
+		let dir = temp_file("writer_round_trip");
+		fs::create_dir_all(&dir).unwrap();
+		let ifo = dir.join("writer.ifo");
+
+		let mut writer = DictWriter::new("writer");
+		writer.author = "someone".to_owned();
+		writer.add("zebra", vec![text("m", "striped animal")]);
+		writer.add("Apple", vec![text("m", "a round fruit")]);
+		writer.add("bark", vec![text("m", "tree skin")]);
+		writer.add_synonym("woof", "bark");
+		writer.write(&ifo).unwrap();
+
+		let dict = no_cache(&ifo).unwrap();
+		assert_eq!(dict.ifo().wordcount, 3);
+		assert_eq!(dict.ifo().synwordcount, 1);
+		assert_eq!(dict.ifo().sametypesequence, "m");
+		assert_eq!(dict.ifo().author, "someone");
+		assert_eq!(dict.ifo().idxfilesize as u64, fs::metadata(dir.join("writer.idx")).unwrap().len());
+		assert_eq!(dict.words().unwrap(), vec!["Apple", "bark", "zebra"]);
+		assert_eq!(dict.read_dict().unwrap(), b"a round fruittree skinstriped animal");
+		assert_eq!(dict.dict_source_filename(), None);
+		for (word, definition) in [("apple", "a round fruit"), ("zebra", "striped animal"),
+			("woof", "tree skin")] {
+			let definitions = dict.lookup(word).unwrap().unwrap();
+			assert_eq!(definitions[0].segments, vec![text("m", definition)]);
+		}
+	}
+
+	#[test]
+	fn round_trip_mixed_types() {
+		let dir = temp_file("writer_mixed_types");
+		fs::create_dir_all(&dir).unwrap();
+		let ifo = dir.join("mixed.ifo");
+
+		let audio = WordDefinitionSegment::new("W".to_owned(), &[0, 1, 2, 0, 255]);
+		let entries = vec![
+			("hello", vec![audio.clone(), text("t", "həˈləʊ"), text("m", "greeting")]),
+			("world", vec![text("h", "<b>world</b>")]),
+		];
+		let mut writer = DictWriter::new("mixed");
+		for (word, segments) in &entries {
+			writer.add(*word, segments.clone());
+		}
+		writer.write(&ifo).unwrap();
+
+		let dict = no_cache(&ifo).unwrap();
+		assert_eq!(dict.ifo().sametypesequence, "");
+		for (word, segments) in &entries {
+			let definitions = dict.lookup(word).unwrap().unwrap();
+			assert_eq!(&definitions[0].segments, segments);
+		}
+
+		// same types for all entries
+		let mut writer = DictWriter::new("mixed");
+		writer.add("hello", vec![audio.clone(), text("m", "greeting")]);
+		writer.add("bye", vec![audio.clone(), text("m", "farewell")]);
+		writer.write(&ifo).unwrap();
+		let dict = no_cache(&ifo).unwrap();
+		assert_eq!(dict.ifo().sametypesequence, "Wm");
+		let definitions = dict.lookup("bye").unwrap().unwrap();
+		assert_eq!(definitions[0].segments, vec![audio, text("m", "farewell")]);
+	}
+
+	#[test]
+	fn round_trip_compressed() {
+		let dir = temp_file("writer_compressed");
+		fs::create_dir_all(&dir).unwrap();
+		let ifo = dir.join("compressed.ifo");
+
+		let mut writer = DictWriter::new("compressed");
+		writer.compress = true;
+		for i in 0..5000 {
+			writer.add(format!("word{}", i), vec![text("m", &format!("definition of {}", i))]);
+		}
+		writer.write(&ifo).unwrap();
+		assert!(dir.join("compressed.dict.dz").exists());
+		assert!(!dir.join("compressed.dict").exists());
+
+		let dict = no_cache(&ifo).unwrap();
+		for i in [0, 1234, 4999] {
+			let definitions = dict.lookup(&format!("word{}", i)).unwrap().unwrap();
+			assert_eq!(definitions[0].segments[0].text, format!("definition of {}", i));
+		}
+		assert_eq!(dict.dict_source_filename(), Some("compressed.dict"));
+		let data = dict.read_dict().unwrap();
+		assert!(data.starts_with(b"definition of 0"));
+		assert!(data.ends_with(b"definition of 999"));
+	}
+}
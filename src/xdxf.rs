@@ -0,0 +1,266 @@
+use crate::error::{Error, Result};
+
+/// node of xdxf markup in segments of type 'x',
+/// tags not listed kept as Other
+#[derive(Debug, Clone, PartialEq)]
+pub enum XdxfNode {
+	/// article
+	Ar(Vec<XdxfNode>),
+	/// keyword
+	K(Vec<XdxfNode>),
+	/// definition
+	Def(Vec<XdxfNode>),
+	/// transcription
+	Tr(Vec<XdxfNode>),
+	/// example
+	Ex(Vec<XdxfNode>),
+	/// cross-reference, target from the k attribute if exists
+	Kref { target: Option<String>, children: Vec<XdxfNode> },
+	Other { tag: String, children: Vec<XdxfNode> },
+	Text(String),
+}
+
+impl XdxfNode {
+	#[inline]
+	pub fn children(&self) -> &[XdxfNode]
+	{
+		match self {
+			XdxfNode::Ar(children)
+			| XdxfNode::K(children)
+			| XdxfNode::Def(children)
+			| XdxfNode::Tr(children)
+			| XdxfNode::Ex(children)
+			| XdxfNode::Kref { children, .. }
+			| XdxfNode::Other { children, .. } => children,
+			XdxfNode::Text(_) => &[],
+		}
+	}
+
+	/// all text with markup stripped
+	pub fn text(&self) -> String
+	{
+		let mut text = String::new();
+		self.push_text(&mut text);
+		text
+	}
+
+	fn push_text(&self, text: &mut String)
+	{
+		if let XdxfNode::Text(str) = self {
+			text.push_str(str);
+		} else {
+			for child in self.children() {
+				child.push_text(text);
+			}
+		}
+	}
+
+	fn new(tag: &str, attributes: Vec<(String, String)>, children: Vec<XdxfNode>) -> Self
+	{
+		match tag {
+			"ar" => XdxfNode::Ar(children),
+			"k" => XdxfNode::K(children),
+			"def" => XdxfNode::Def(children),
+			"tr" => XdxfNode::Tr(children),
+			"ex" => XdxfNode::Ex(children),
+			"kref" => {
+				let target = attributes.into_iter()
+					.find(|(name, _)| name == "k")
+					.map(|(_, value)| value);
+				XdxfNode::Kref { target, children }
+			}
+			_ => XdxfNode::Other { tag: tag.to_owned(), children },
+		}
+	}
+}
+
+struct OpenTag {
+	name: String,
+	attributes: Vec<(String, String)>,
+	children: Vec<XdxfNode>,
+}
+
+/// parse xdxf markup, a single <ar> returned as is,
+/// otherwise all top level nodes wrapped in an Ar
+pub(crate) fn parse(xml: &str) -> Result<XdxfNode>
+{
+	let mut stack: Vec<OpenTag> = vec![];
+	let mut nodes = vec![];
+	let mut remain = xml;
+	while !remain.is_empty() {
+		let children = stack.last_mut().map_or(&mut nodes, |tag| &mut tag.children);
+		if let Some(tag) = remain.strip_prefix('<') {
+			if let Some(comment) = tag.strip_prefix("!--") {
+				let end = comment.find("-->").ok_or_else(|| invalid("unclosed comment"))?;
+				remain = &comment[end + 3..];
+				continue;
+			}
+			let end = tag.find('>').ok_or_else(|| invalid("unclosed tag"))?;
+			let content = &tag[..end];
+			remain = &tag[end + 1..];
+			if content.starts_with('?') || content.starts_with('!') {
+				continue;
+			}
+			if let Some(name) = content.strip_prefix('/') {
+				let name = name.trim();
+				match stack.pop() {
+					Some(tag) if tag.name == name => {
+						let node = XdxfNode::new(&tag.name, tag.attributes, tag.children);
+						stack.last_mut().map_or(&mut nodes, |tag| &mut tag.children).push(node);
+					}
+					_ => return Err(invalid(&format!("unexpected </{}>", name))),
+				}
+			} else if let Some(content) = content.strip_suffix('/') {
+				let (name, attributes) = parse_tag(content)?;
+				children.push(XdxfNode::new(&name, attributes, vec![]));
+			} else {
+				let (name, attributes) = parse_tag(content)?;
+				stack.push(OpenTag { name, attributes, children: vec![] });
+			}
+		} else {
+			let end = remain.find('<').unwrap_or(remain.len());
+			children.push(XdxfNode::Text(unescape(&remain[..end])));
+			remain = &remain[end..];
+		}
+	}
+	if let Some(tag) = stack.last() {
+		return Err(invalid(&format!("unclosed <{}>", tag.name)));
+	}
+	if nodes.len() == 1 && matches!(nodes[0], XdxfNode::Ar(_)) {
+		Ok(nodes.remove(0))
+	} else {
+		Ok(XdxfNode::Ar(nodes))
+	}
+}
+
+/// tag name and attributes
+fn parse_tag(content: &str) -> Result<(String, Vec<(String, String)>)>
+{
+	let content = content.trim();
+	let name_end = content.find(char::is_whitespace).unwrap_or(content.len());
+	let name = &content[..name_end];
+	if name.is_empty() {
+		return Err(invalid("empty tag"));
+	}
+	let mut attributes = vec![];
+	let mut remain = content[name_end..].trim_start();
+	while !remain.is_empty() {
+		let eq = remain.find('=').ok_or_else(|| invalid("attribute without value"))?;
+		let key = remain[..eq].trim().to_owned();
+		let value = remain[eq + 1..].trim_start();
+		let quote = value.chars().next()
+			.filter(|ch| *ch == '"' || *ch == '\'')
+			.ok_or_else(|| invalid("attribute value not quoted"))?;
+		let end = value[1..].find(quote).ok_or_else(|| invalid("unclosed attribute value"))?;
+		attributes.push((key, unescape(&value[1..end + 1])));
+		remain = value[end + 2..].trim_start();
+	}
+	Ok((name.to_owned(), attributes))
+}
+
+/// decode xml entities, &nbsp; as well for html
+pub(crate) fn unescape(str: &str) -> String
+{
+	let mut text = String::with_capacity(str.len());
+	let mut remain = str;
+	while let Some(start) = remain.find('&') {
+		text.push_str(&remain[..start]);
+		remain = &remain[start..];
+		let decoded = remain.find(';').and_then(|end| {
+			let entity = &remain[1..end];
+			let ch = match entity {
+				"lt" => Some('<'),
+				"gt" => Some('>'),
+				"amp" => Some('&'),
+				"quot" => Some('"'),
+				"apos" => Some('\''),
+				"nbsp" => Some('\u{a0}'),
+				_ => if let Some(hex) = entity.strip_prefix("#x") {
+					u32::from_str_radix(hex, 16).ok().and_then(char::from_u32)
+				} else if let Some(dec) = entity.strip_prefix('#') {
+					dec.parse().ok().and_then(char::from_u32)
+				} else {
+					None
+				}
+			};
+			ch.map(|ch| (ch, end))
+		});
+		if let Some((ch, end)) = decoded {
+			text.push(ch);
+			remain = &remain[end + 1..];
+		} else {
+			// not an entity, keep it
+			text.push('&');
+			remain = &remain[1..];
+		}
+	}
+	text.push_str(remain);
+	text
+}
+
+#[inline]
+fn invalid(msg: &str) -> Error
+{
+	Error::InvalidXdxf(msg.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::xdxf::{parse, XdxfNode};
+	use crate::WordDefinitionSegment;
+
+	fn text(str: &str) -> XdxfNode
+	{
+		XdxfNode::Text(str.to_owned())
+	}
+
+	#[test]
+	fn nested() {
+		let node = parse("<ar><k>apple</k> <tr>ˈæp(ə)l</tr><def>a round fruit<def><ex>an apple a day</ex></def></def></ar>")
+			.unwrap();
+		assert_eq!(node, XdxfNode::Ar(vec![
+			XdxfNode::K(vec![text("apple")]),
+			text(" "),
+			XdxfNode::Tr(vec![text("ˈæp(ə)l")]),
+			XdxfNode::Def(vec![
+				text("a round fruit"),
+				XdxfNode::Def(vec![XdxfNode::Ex(vec![text("an apple a day")])]),
+			]),
+		]));
+		assert_eq!(node.text(), "apple ˈæp(ə)la round fruitan apple a day");
+	}
+
+	#[test]
+	fn cross_reference() {
+		let node = parse("<k>pear</k>see <kref>apple</kref> and <kref k=\"fruit\">fruits</kref><br/>").unwrap();
+		assert_eq!(node, XdxfNode::Ar(vec![
+			XdxfNode::K(vec![text("pear")]),
+			text("see "),
+			XdxfNode::Kref { target: None, children: vec![text("apple")] },
+			text(" and "),
+			XdxfNode::Kref { target: Some("fruit".to_owned()), children: vec![text("fruits")] },
+			XdxfNode::Other { tag: "br".to_owned(), children: vec![] },
+		]));
+	}
+
+	#[test]
+	fn entities() {
+		let node = parse("<def>&lt;b&gt; &amp; &#65;&#x42; &unknown; AT&T</def>").unwrap();
+		assert_eq!(node.text(), "<b> & AB &unknown; AT&T");
+	}
+
+	#[test]
+	fn invalid() {
+		assert!(parse("<k>apple</def>").is_err());
+		assert!(parse("<k>apple").is_err());
+		assert!(parse("<k apple</k>").is_err());
+	}
+
+	#[test]
+	fn segment() {
+		let segment = WordDefinitionSegment::new("x".to_owned(), b"<k>apple</k>");
+		assert_eq!(segment.parse_xdxf().unwrap().text(), "apple");
+		let segment = WordDefinitionSegment::new("m".to_owned(), b"<k>apple</k>");
+		assert!(segment.parse_xdxf().is_err());
+	}
+}